@@ -6,6 +6,7 @@
 
 // TODO(lucab): eventually move to its own "cincinnati client library" crate
 
+use crate::http_client::{self, HttpSettings};
 use anyhow::{Context, Result};
 use futures::prelude::*;
 use reqwest::Method;
@@ -14,9 +15,6 @@ use std::collections::HashMap;
 use std::time::Duration;
 use thiserror::Error;
 
-/// Default timeout for HTTP requests completion (30 minutes).
-const DEFAULT_HTTP_COMPLETION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
-
 /// Cincinnati graph API path endpoint (v1).
 static V1_GRAPH_PATH: &str = "v1/graph";
 
@@ -51,6 +49,8 @@ pub enum CincinnatiError {
     Graph(reqwest::StatusCode, GraphJsonError),
     /// Generic HTTP error.
     Http(reqwest::StatusCode),
+    /// Server is overloaded, and asked to retry after a delay.
+    Throttled(reqwest::StatusCode, Duration),
     /// Client builder failed.
     FailedClientBuilder(String),
     /// Client failed JSON decoding.
@@ -69,6 +69,7 @@ impl CincinnatiError {
         match *self {
             CincinnatiError::Graph(_, ref err) => err.kind.clone(),
             CincinnatiError::Http(status) => format!("generic_http_{}", status.as_u16()),
+            CincinnatiError::Throttled(_, _) => "server_throttled".to_string(),
             CincinnatiError::FailedClientBuilder(_) => "client_failed_build".to_string(),
             CincinnatiError::FailedJsonDecoding(_) => "client_failed_json_decoding".to_string(),
             CincinnatiError::FailedNodeLookup(_) => "client_failed_node_lookup".to_string(),
@@ -82,6 +83,7 @@ impl CincinnatiError {
         match *self {
             CincinnatiError::Graph(_, ref err) => err.value.clone(),
             CincinnatiError::Http(_) => "(unknown/generic server error)".to_string(),
+            CincinnatiError::Throttled(_, delay) => http_client::throttled_message(delay),
             CincinnatiError::FailedClientBuilder(ref err)
             | CincinnatiError::FailedJsonDecoding(ref err)
             | CincinnatiError::FailedNodeLookup(ref err)
@@ -93,10 +95,32 @@ impl CincinnatiError {
     /// Return the server-side error status code, if any.
     pub fn status_code(&self) -> Option<u16> {
         match *self {
-            CincinnatiError::Graph(s, _) | CincinnatiError::Http(s) => Some(s.as_u16()),
+            CincinnatiError::Graph(s, _)
+            | CincinnatiError::Http(s)
+            | CincinnatiError::Throttled(s, _) => Some(s.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// Return the retry delay requested by the server, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match *self {
+            CincinnatiError::Throttled(_, delay) => Some(delay),
             _ => None,
         }
     }
+
+    /// Return whether the remote service could not serve the request.
+    ///
+    /// This covers connection failures, throttling and server-side (5xx)
+    /// errors, but not failures to process the update graph.
+    pub fn is_unavailable(&self) -> bool {
+        match *self {
+            CincinnatiError::FailedRequest(_) | CincinnatiError::Throttled(_, _) => true,
+            CincinnatiError::Graph(s, _) | CincinnatiError::Http(s) => s.is_server_error(),
+            _ => false,
+        }
+    }
 }
 
 impl std::fmt::Display for CincinnatiError {
@@ -117,6 +141,8 @@ pub struct Client {
     api_base: reqwest::Url,
     /// Asynchronous reqwest client.
     hclient: reqwest::Client,
+    /// HTTP client settings, for credentials.
+    http: HttpSettings,
     /// Client parameters (query portion).
     query_params: HashMap<String, String>,
 }
@@ -148,7 +174,7 @@ impl Client {
             .request(method, url)
             .header("accept", "application/json")
             .query(&self.query_params);
-        Ok(builder)
+        self.http.authorize(builder)
     }
 
     /// Map an HTTP response to a service result.
@@ -163,6 +189,11 @@ impl Client {
             return Ok(graph);
         }
 
+        // On throttling, surface the requested retry delay.
+        if let Some(delay) = http_client::throttle_delay(&response) {
+            return Err(CincinnatiError::Throttled(status, delay));
+        }
+
         // On error, decode failure details (or synthesize a generic error).
         match response.json::<GraphJsonError>().await {
             Ok(rej) => Err(CincinnatiError::Graph(status, rej)),
@@ -178,6 +209,8 @@ pub struct ClientBuilder {
     api_base: String,
     /// Asynchronous reqwest client (custom).
     hclient: Option<reqwest::Client>,
+    /// HTTP client settings.
+    http: HttpSettings,
     /// Client parameters (custom).
     query_params: Option<HashMap<String, String>>,
 }
@@ -191,10 +224,18 @@ impl ClientBuilder {
         Self {
             api_base: api_base.into(),
             hclient: None,
+            http: HttpSettings::default(),
             query_params: None,
         }
     }
 
+    /// Set the HTTP client settings to use.
+    pub(crate) fn http_settings(self, http: HttpSettings) -> Self {
+        let mut builder = self;
+        builder.http = http;
+        builder
+    }
+
     /// Set (or reset) the query parameters to use.
     pub fn query_params(self, params: Option<HashMap<String, String>>) -> Self {
         let mut builder = self;
@@ -206,9 +247,7 @@ impl ClientBuilder {
     pub fn build(self) -> Result<Client> {
         let hclient = match self.hclient {
             Some(client) => client,
-            None => reqwest::ClientBuilder::new()
-                .timeout(DEFAULT_HTTP_COMPLETION_TIMEOUT)
-                .build()?,
+            None => self.http.build_client()?,
         };
         let query_params = self.query_params.unwrap_or_default();
 
@@ -217,6 +256,7 @@ impl ClientBuilder {
         let client = Client {
             api_base,
             hclient,
+            http: self.http,
             query_params,
         };
         Ok(client)
@@ -286,4 +326,25 @@ mod tests {
             "client-side error: failed to decode graph: error decoding response body";
         assert_eq!(&msg, expected_msg);
     }
+
+    #[test]
+    fn test_throttled_error() {
+        let runtime = rt::Runtime::new().unwrap();
+        let response = Response::builder()
+            .status(429)
+            .header("retry-after", "120")
+            .body("")
+            .unwrap();
+        let fut_rejection = Client::map_response(response.into());
+        let rejection = runtime.block_on(fut_rejection).unwrap_err();
+        let expected_rejection =
+            CincinnatiError::Throttled(StatusCode::TOO_MANY_REQUESTS, Duration::from_secs(120));
+        assert_eq!(&rejection, &expected_rejection);
+        assert_eq!(rejection.retry_after(), Some(Duration::from_secs(120)));
+
+        assert_eq!(rejection.error_kind(), "server_throttled");
+        assert!(rejection
+            .to_string()
+            .starts_with("server-side error, code 429: "));
+    }
 }
@@ -8,6 +8,7 @@ pub use client::{CincinnatiError, Node};
 mod mock_tests;
 
 use crate::config::inputs;
+use crate::http_client::{Backoff, HttpSettings};
 use crate::identity::Identity;
 use crate::rpm_ostree::{Payload, Release};
 use anyhow::{Context, Result};
@@ -19,6 +20,8 @@ use serde::Serialize;
 use std::collections::BTreeSet;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// Metadata key for payload scheme.
 pub static AGE_INDEX_KEY: &str = "org.fedoraproject.coreos.releases.age_index";
@@ -32,6 +35,9 @@ pub static DEADEND_KEY: &str = "org.fedoraproject.coreos.updates.deadend";
 /// Metadata key for dead-end reason.
 pub static DEADEND_REASON_KEY: &str = "org.fedoraproject.coreos.updates.deadend_reason";
 
+/// Metadata key for urgent release marker.
+pub static URGENT_KEY: &str = "org.fedoraproject.coreos.updates.urgent";
+
 /// Metadata value for "checksum" payload scheme.
 pub const CHECKSUM_SCHEME: &str = "checksum";
 
@@ -100,11 +106,27 @@ impl DeadEndState {
     }
 }
 
+/// Outcome of re-checking a staged release against the update graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum StagedUpdate {
+    /// Staged release is still the best update target.
+    Current,
+    /// A newer update target is available.
+    Superseded(Release),
+    /// Staged release is not a valid update target anymore.
+    Pulled,
+}
+
 /// Cincinnati configuration.
 #[derive(Debug, Serialize, Clone)]
 pub struct Cincinnati {
     /// Service base URL.
     pub base_url: String,
+    /// HTTP client settings.
+    pub http: HttpSettings,
+    /// Retry backoff, while the service is unavailable.
+    #[serde(skip)]
+    pub(crate) backoff: Arc<Mutex<Backoff>>,
 }
 
 impl Cincinnati {
@@ -124,11 +146,22 @@ impl Cincinnati {
             cfg.base_url
         };
         log::info!("Cincinnati service: {}", &base_url);
+        let http = HttpSettings::with_config(cfg.http)?;
 
-        let c = Self { base_url };
+        let c = Self {
+            base_url,
+            http,
+            backoff: Arc::default(),
+        };
         Ok(c)
     }
 
+    /// Return the time left before the service should be queried again, if
+    /// backing off after failures.
+    pub(crate) fn retry_delay(&self) -> Option<Duration> {
+        self.backoff.lock().unwrap().remaining(Instant::now())
+    }
+
     /// Fetch next update-hint from Cincinnati.
     pub(crate) fn fetch_update_hint(
         &self,
@@ -139,8 +172,10 @@ impl Cincinnati {
         UPDATE_CHECKS.inc();
         log::trace!("checking upstream Cincinnati server for updates");
 
+        let backoff = Arc::clone(&self.backoff);
         let update = self
             .next_update(id, denylisted_depls, allow_downgrade)
+            .inspect(move |res| track_backoff(&backoff, res))
             .unwrap_or_else(|e| {
                 UPDATE_CHECKS_ERRORS
                     .with_label_values(&[&e.error_kind()])
@@ -151,6 +186,76 @@ impl Cincinnati {
         Box::pin(update)
     }
 
+    /// Re-check a staged release against the current update graph.
+    ///
+    /// This returns `None` if the check could not be performed.
+    pub(crate) fn check_staged_update(
+        &self,
+        id: &Identity,
+        staged: Release,
+        denylisted_depls: BTreeSet<Release>,
+        allow_downgrade: bool,
+    ) -> Pin<Box<dyn Future<Output = Option<StagedUpdate>>>> {
+        UPDATE_CHECKS.inc();
+        log::trace!("checking upstream Cincinnati server for staged update");
+
+        let booted = id.current_os.clone();
+        let params = id.cincinnati_params();
+        let client = client::ClientBuilder::new(self.base_url.to_string())
+            .query_params(Some(params))
+            .http_settings(self.http.clone())
+            .build()
+            .map_err(|e| CincinnatiError::FailedClientBuilder(e.to_string()));
+
+        let backoff = Arc::clone(&self.backoff);
+        let check = futures::future::ready(client)
+            .and_then(|c| c.fetch_graph())
+            .and_then(move |graph| async move {
+                evaluate_staged_update(graph, booted, staged, denylisted_depls, allow_downgrade)
+            })
+            .inspect(move |res| track_backoff(&backoff, res))
+            .unwrap_or_else(|e| {
+                UPDATE_CHECKS_ERRORS
+                    .with_label_values(&[&e.error_kind()])
+                    .inc();
+                log::error!("failed to check Cincinnati for staged update: {}", e);
+                None
+            });
+        Box::pin(check)
+    }
+
+    /// Look up a specific release in the current update graph, as a target
+    /// for the booted deployment.
+    pub(crate) fn fetch_target_version(
+        &self,
+        id: &Identity,
+        version: String,
+        allow_downgrade: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<Release>>>> {
+        log::trace!(
+            "looking up release '{}' on upstream Cincinnati server",
+            version
+        );
+
+        let booted = id.current_os.clone();
+        let params = id.cincinnati_params();
+        let client = client::ClientBuilder::new(self.base_url.to_string())
+            .query_params(Some(params))
+            .http_settings(self.http.clone())
+            .build()
+            .map_err(|e| CincinnatiError::FailedClientBuilder(e.to_string()));
+
+        let backoff = Arc::clone(&self.backoff);
+        let target = futures::future::ready(client)
+            .and_then(|c| c.fetch_graph())
+            .inspect(move |res| track_backoff(&backoff, res))
+            .map_err(|e| anyhow::anyhow!("failed to fetch update graph: {}", e))
+            .and_then(move |graph| async move {
+                find_target_version(graph, booted, &version, allow_downgrade)
+            });
+        Box::pin(target)
+    }
+
     /// Get the next update.
     fn next_update(
         &self,
@@ -162,6 +267,7 @@ impl Cincinnati {
         let params = id.cincinnati_params();
         let client = client::ClientBuilder::new(self.base_url.to_string())
             .query_params(Some(params))
+            .http_settings(self.http.clone())
             .build()
             .map_err(|e| CincinnatiError::FailedClientBuilder(e.to_string()));
 
@@ -174,6 +280,21 @@ impl Cincinnati {
     }
 }
 
+/// Record the outcome of a request to the service in its retry backoff.
+fn track_backoff<T>(backoff: &Mutex<Backoff>, res: &Result<T, CincinnatiError>) {
+    let mut backoff = backoff.lock().unwrap();
+    match res {
+        Err(e) if e.is_unavailable() => {
+            let delay = backoff.record_failure(e.retry_after(), Instant::now());
+            log::warn!(
+                "Cincinnati service unavailable, backing off for {} seconds",
+                delay.as_secs()
+            );
+        }
+        _ => backoff.reset(),
+    }
+}
+
 /// Evaluate and record whether booted OS is a dead-end release, and
 /// log that information in a MOTD file.
 fn refresh_deadend_status(node: &Node) -> Result<()> {
@@ -317,6 +438,108 @@ fn find_update(
     Ok(Some(next))
 }
 
+/// Evaluate whether a staged release is still the best update target.
+///
+/// This returns `None` if the booted deployment cannot be found in the graph,
+/// as nothing can be said about the staged release in that case.
+fn evaluate_staged_update(
+    graph: client::Graph,
+    booted_depl: Release,
+    staged: Release,
+    denylisted_depls: BTreeSet<Release>,
+    allow_downgrade: bool,
+) -> Result<Option<StagedUpdate>, CincinnatiError> {
+    let cur_position = match graph
+        .nodes
+        .iter()
+        .position(|node| is_same_checksum(node, &booted_depl))
+    {
+        Some(pos) => pos as u64,
+        None => {
+            log::warn!(
+                "booted deployment {} not found in the update graph",
+                &booted_depl.payload
+            );
+            return Ok(None);
+        }
+    };
+
+    // The staged release must still be reachable from the booted deployment,
+    // and must not have been marked as a dead-end in the meantime.
+    let staged_is_valid = graph
+        .edges
+        .iter()
+        .filter(|(src, _)| *src == cur_position)
+        .filter_map(|(_, dst)| graph.nodes.get(*dst as usize))
+        .find(|node| is_same_checksum(node, &staged))
+        .map(|node| evaluate_deadend(node).is_none())
+        .unwrap_or(false);
+    if !staged_is_valid {
+        return Ok(Some(StagedUpdate::Pulled));
+    }
+
+    let outcome = match find_update(graph, booted_depl, denylisted_depls, allow_downgrade)? {
+        Some(next) if next.payload != staged.payload && next > staged => {
+            StagedUpdate::Superseded(next)
+        }
+        _ => StagedUpdate::Current,
+    };
+    Ok(Some(outcome))
+}
+
+/// Find a specific release in the graph, checking that it is a valid target
+/// for the booted deployment.
+fn find_target_version(
+    graph: client::Graph,
+    booted_depl: Release,
+    version: &str,
+    allow_downgrade: bool,
+) -> Result<Release> {
+    let cur_position = graph
+        .nodes
+        .iter()
+        .position(|node| is_same_checksum(node, &booted_depl))
+        .with_context(|| {
+            format!(
+                "booted deployment {} not found in the update graph",
+                &booted_depl.payload
+            )
+        })?;
+    let cur_release = Release::from_cincinnati(graph.nodes[cur_position].clone())?;
+
+    let (target_position, target_node) = graph
+        .nodes
+        .iter()
+        .enumerate()
+        .find(|(_, node)| node.version == version)
+        .with_context(|| format!("release '{}' not found in the update graph", version))?;
+    let target = Release::from_cincinnati(target_node.clone())?;
+    if target.payload == cur_release.payload {
+        anyhow::bail!("release '{}' is already booted", version);
+    }
+
+    if target <= cur_release && !allow_downgrade {
+        anyhow::bail!(
+            "release '{}' is a downgrade, which is not allowed by configuration",
+            version
+        );
+    }
+
+    // Upgrades must follow an edge, while downgrades never have one.
+    let has_edge = graph
+        .edges
+        .contains(&(cur_position as u64, target_position as u64));
+    if !has_edge && target > cur_release {
+        anyhow::bail!(
+            "no update path from booted release '{}' to release '{}'",
+            cur_release.version,
+            version
+        );
+    }
+
+    Ok(target)
+}
+
 /// Try to match a set of (denylisted) deployments to their graph entries.
 fn find_denylisted_releases(graph: &client::Graph, depls: BTreeSet<Release>) -> BTreeSet<Release> {
     use std::collections::HashSet;
@@ -394,6 +617,7 @@ mod tests {
             version: String::new(),
             payload: Payload::Checksum("current-sha".to_string()),
             age_index: None,
+            urgent: false,
         };
 
         let mut metadata = HashMap::new();
@@ -445,4 +669,120 @@ mod tests {
         let common: Node = serde_json::from_str(common_json).unwrap();
         assert_eq!(evaluate_deadend(&common), None);
     }
+
+    #[test]
+    fn staged_update_evaluation() {
+        fn node(version: &str, payload: &str, age_index: &str, deadend: bool) -> Node {
+            let mut metadata = HashMap::new();
+            metadata.insert(SCHEME_KEY.to_string(), CHECKSUM_SCHEME.to_string());
+            metadata.insert(AGE_INDEX_KEY.to_string(), age_index.to_string());
+            if deadend {
+                metadata.insert(DEADEND_KEY.to_string(), "true".to_string());
+            }
+            Node {
+                version: version.to_string(),
+                payload: payload.to_string(),
+                metadata,
+            }
+        }
+        fn graph(staged_deadend: bool, edges: Vec<(u64, u64)>) -> client::Graph {
+            client::Graph {
+                nodes: vec![
+                    node("v0", "booted-sha", "0", false),
+                    node("v1", "staged-sha", "1", staged_deadend),
+                    node("v2", "newer-sha", "2", false),
+                ],
+                edges,
+            }
+        }
+
+        let booted = Release {
+            version: "v0".to_string(),
+            payload: Payload::Checksum("booted-sha".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        let staged = Release::from_cincinnati(node("v1", "staged-sha", "1", false)).unwrap();
+        let newer = Release::from_cincinnati(node("v2", "newer-sha", "2", false)).unwrap();
+        let check = |graph| {
+            evaluate_staged_update(
+                graph,
+                booted.clone(),
+                staged.clone(),
+                BTreeSet::new(),
+                false,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            check(graph(false, vec![(0, 1)])),
+            Some(StagedUpdate::Current)
+        );
+        assert_eq!(
+            check(graph(false, vec![(0, 1), (0, 2)])),
+            Some(StagedUpdate::Superseded(newer))
+        );
+        assert_eq!(
+            check(graph(false, vec![(0, 2)])),
+            Some(StagedUpdate::Pulled)
+        );
+        assert_eq!(check(graph(true, vec![(0, 1)])), Some(StagedUpdate::Pulled));
+
+        let mut unknown_booted = graph(false, vec![(0, 1)]);
+        unknown_booted.nodes[0].payload = "other-sha".to_string();
+        assert_eq!(check(unknown_booted), None);
+    }
+
+    #[test]
+    fn target_version_lookup() {
+        fn node(version: &str, age_index: &str) -> Node {
+            let mut metadata = HashMap::new();
+            metadata.insert(SCHEME_KEY.to_string(), CHECKSUM_SCHEME.to_string());
+            metadata.insert(AGE_INDEX_KEY.to_string(), age_index.to_string());
+            Node {
+                version: version.to_string(),
+                payload: format!("{}-sha", version),
+                metadata,
+            }
+        }
+        fn graph() -> client::Graph {
+            client::Graph {
+                nodes: vec![
+                    node("v0", "0"),
+                    node("v1", "1"),
+                    node("v2", "2"),
+                    node("v3", "3"),
+                ],
+                edges: vec![(0, 1), (1, 2), (1, 3), (2, 3)],
+            }
+        }
+        let booted = Release {
+            version: "v1".to_string(),
+            payload: Payload::Checksum("v1-sha".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+
+        let target = find_target_version(graph(), booted.clone(), "v2", false).unwrap();
+        assert_eq!(target.version, "v2");
+        assert_eq!(target.payload, Payload::Checksum("v2-sha".to_string()));
+        find_target_version(graph(), booted.clone(), "v3", false).unwrap();
+
+        // Unknown and booted releases.
+        find_target_version(graph(), booted.clone(), "v9", true).unwrap_err();
+        find_target_version(graph(), booted.clone(), "v1", true).unwrap_err();
+
+        // Downgrades, without an edge.
+        find_target_version(graph(), booted.clone(), "v0", false).unwrap_err();
+        let target = find_target_version(graph(), booted.clone(), "v0", true).unwrap();
+        assert_eq!(target.version, "v0");
+
+        // Not reachable through an edge, even with downgrades allowed.
+        for allow_downgrade in [false, true] {
+            let mut no_edges = graph();
+            no_edges.edges.clear();
+            find_target_version(no_edges, booted.clone(), "v2", allow_downgrade).unwrap_err();
+        }
+    }
 }
@@ -1,14 +1,21 @@
 //! Logic for the `agent` subcommand.
 
 use super::ensure_user;
+use crate::notifications::webhook::WebhookClient;
+use crate::strategy::UpdateStrategy;
 use crate::{config, dbus, metrics, rpm_ostree, update_agent, utils};
 use actix::{Actor, Addr};
 use anyhow::{Context, Result};
 use clap::{crate_name, crate_version};
 use log::{info, trace};
 use prometheus::IntGauge;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::runtime::Runtime;
 
+/// Maximum time to spend releasing a held reboot slot on shutdown.
+const SHUTDOWN_RELEASE_TIMEOUT: Duration = Duration::from_secs(10);
+
 lazy_static::lazy_static! {
     static ref PROCESS_START_TIME: IntGauge = register_int_gauge!(opts!(
         "process_start_time_seconds",
@@ -26,21 +33,31 @@ pub(crate) fn run_agent() -> Result<()> {
     );
 
     // Start a new dedicated signal handling thread in a new runtime.
+    // The update strategy is only known once settings are assembled.
     let signal_handling_rt = Runtime::new().unwrap();
-    signal_handling_rt.spawn(async {
+    let shutdown_strategy: Arc<Mutex<Option<UpdateStrategy>>> = Arc::default();
+    let strategy = Arc::clone(&shutdown_strategy);
+    signal_handling_rt.spawn(async move {
         use tokio::signal::unix::{signal, SignalKind};
 
         // Create stream of terminate signals.
         let mut stream = signal(SignalKind::terminate()).expect("failed to set SIGTERM handler");
 
         stream.recv().await;
+        utils::notify_stopping();
+        // Do not keep a reboot slot locked while this node stays down.
+        let strategy = strategy.lock().unwrap().clone();
+        if let Some(strategy) = strategy {
+            release_on_shutdown(strategy).await;
+        }
         // Reset status text to empty string (default).
         utils::update_unit_status("");
-        utils::notify_stopping();
         std::process::exit(0);
     });
 
-    let settings = config::Settings::assemble()?;
+    let mut settings = config::Settings::assemble()?;
+    *shutdown_strategy.lock().unwrap() = Some(settings.strategy.clone());
+
     settings.refresh_metrics();
     info!(
         "agent running on node '{}', in update group '{}'",
@@ -66,8 +83,16 @@ pub(crate) fn run_agent() -> Result<()> {
         trace!("creating rpm-ostree client");
         let rpm_ostree_addr = rpm_ostree::RpmOstreeClient::start(1);
 
+        let webhook_addr = match settings.webhook.take() {
+            Some(webhook) => {
+                trace!("creating webhook client");
+                Some(WebhookClient::new(webhook)?.start())
+            }
+            None => None,
+        };
+
         trace!("creating update agent");
-        let agent = update_agent::UpdateAgent::with_config(settings, rpm_ostree_addr);
+        let agent = update_agent::UpdateAgent::with_config(settings, rpm_ostree_addr, webhook_addr);
         let agent_addr = agent.start();
 
         trace!("creating D-Bus service");
@@ -81,3 +106,25 @@ pub(crate) fn run_agent() -> Result<()> {
 
     Ok(())
 }
+
+/// Release the held reboot slot (if any), within `SHUTDOWN_RELEASE_TIMEOUT`.
+///
+/// Strategy futures are not `Send`, so this runs on its own single-threaded
+/// runtime, on a blocking thread.
+async fn release_on_shutdown(strategy: UpdateStrategy) {
+    let release = tokio::task::spawn_blocking(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to create runtime")?;
+        let release = strategy.release_on_shutdown();
+        runtime
+            .block_on(tokio::time::timeout(SHUTDOWN_RELEASE_TIMEOUT, release))
+            .context("timed out")
+    });
+    match release.await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::warn!("failed to release reboot slot on shutdown: {:#}", e),
+        Err(e) => log::warn!("failed to release reboot slot on shutdown: {}", e),
+    }
+}
@@ -0,0 +1,194 @@
+//! Logic for the `fleetlock` subcommand.
+
+use crate::config::Settings;
+use crate::identity::Identity;
+use crate::strategy::{StrategyFleetLock, UpdateStrategy};
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use fn_error_context::context;
+
+/// Subcommand `fleetlock`.
+#[derive(Debug, Subcommand)]
+pub enum Cmd {
+    /// Lock a reboot slot for this node.
+    #[command(name = "lock")]
+    Lock(NodeArgs),
+    /// Unlock the reboot slot held by this node, if any.
+    #[command(name = "unlock")]
+    Unlock(NodeArgs),
+    /// Show the parameters identifying this node to the lock-manager.
+    #[command(name = "status")]
+    Status(NodeArgs),
+}
+
+/// Overrides of the parameters identifying this node to the lock-manager.
+#[derive(Debug, Default, Args)]
+pub struct NodeArgs {
+    /// Act on behalf of the node with this ID (e.g. the ID of this node before reimaging).
+    #[arg(long = "id")]
+    id: Option<String>,
+    /// Act on behalf of a node in this group.
+    #[arg(long = "group")]
+    group: Option<String>,
+}
+
+impl Cmd {
+    /// `fleetlock` subcommand entry point.
+    #[context("failed to run `fleetlock` subcommand")]
+    pub(crate) fn run(self) -> Result<()> {
+        let settings = Settings::assemble()?;
+        let strategy = match &settings.strategy {
+            UpdateStrategy::FleetLock(s) => s,
+            _ => anyhow::bail!(
+                "update strategy is '{}', not '{}'",
+                settings.strategy.configuration_label(),
+                StrategyFleetLock::LABEL
+            ),
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let output = runtime.block_on(self.execute(strategy, &settings.identity))?;
+        println!("{}", output);
+        Ok(())
+    }
+
+    /// Run the command with the given strategy, returning its output.
+    async fn execute(self, strategy: &StrategyFleetLock, identity: &Identity) -> Result<String> {
+        let output = match self {
+            Cmd::Lock(node) => {
+                node.strategy(strategy)?.manual_lock().await?;
+                "Reboot slot locked".to_string()
+            }
+            Cmd::Unlock(node) => {
+                node.strategy(strategy)?.manual_unlock().await?;
+                "Reboot slot unlocked".to_string()
+            }
+            Cmd::Status(node) => {
+                let id = node.id.unwrap_or_else(|| identity.node_uuid.lower_hex());
+                let group = node.group.unwrap_or_else(|| identity.group.clone());
+                let mut lines = vec![format!("Node ID: {}", id), format!("Group: {}", group)];
+                for base_url in strategy.base_urls() {
+                    lines.push(format!("Lock-manager: {}", base_url));
+                }
+                lines.join("\n")
+            }
+        };
+        Ok(output)
+    }
+}
+
+impl NodeArgs {
+    /// Return the strategy to use, with overrides applied.
+    fn strategy(&self, strategy: &StrategyFleetLock) -> Result<StrategyFleetLock> {
+        if self.id.is_none() && self.group.is_none() {
+            return Ok(strategy.clone());
+        }
+        strategy.for_node(self.id.as_deref(), self.group.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{CliCommand, CliOptions};
+    use crate::config::inputs::UpdateInput;
+    use crate::fleet_lock::server::{self, LockServer, ServerSettings};
+    use clap::Parser;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_fleetlock_args() {
+        for (arg, expected) in [("lock", "Lock"), ("unlock", "Unlock"), ("status", "Status")] {
+            let cli = CliOptions::try_parse_from(vec!["zincati", "fleetlock", arg]).unwrap();
+            if let CliCommand::Fleetlock(cmd) = &cli.cmd {
+                assert!(format!("{:?}", cmd).starts_with(expected));
+            } else {
+                panic!("unexpected result: {:?}", cli);
+            }
+        }
+
+        let args = vec![
+            "zincati",
+            "fleetlock",
+            "unlock",
+            "--id",
+            "27e3ac02af3946af995c9940e18b0cce",
+            "--group",
+            "workers",
+        ];
+        let cli = CliOptions::try_parse_from(args).unwrap();
+        if let CliCommand::Fleetlock(Cmd::Unlock(node)) = &cli.cmd {
+            assert_eq!(node.id.as_deref(), Some("27e3ac02af3946af995c9940e18b0cce"));
+            assert_eq!(node.group.as_deref(), Some("workers"));
+        } else {
+            panic!("unexpected result: {:?}", cli);
+        }
+
+        let missing = vec!["zincati", "fleetlock"];
+        CliOptions::try_parse_from(missing).unwrap_err();
+        let unknown = vec!["zincati", "fleetlock", "steal"];
+        CliOptions::try_parse_from(unknown).unwrap_err();
+    }
+
+    #[test]
+    fn test_fleetlock_server_roundtrip() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let settings = ServerSettings {
+            state_file: tmpdir.path().join("state.json"),
+            default_slots: 1,
+            group_slots: BTreeMap::new(),
+            lease_ttl: None,
+            admin_token_file: None,
+        };
+        let lock_server = LockServer::load(settings).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        runtime.spawn(server::serve(listener, lock_server));
+
+        let id = Identity::mock_default();
+        let input = UpdateInput::mock_fleet_lock(vec![base_url.clone()]);
+        let strategy = StrategyFleetLock::new(input, &id).unwrap();
+        let reimaged = || NodeArgs {
+            id: Some("27e3ac02af3946af995c9940e18b0cce".to_string()),
+            group: None,
+        };
+
+        // The slot is held by this node before being reimaged.
+        let output = runtime
+            .block_on(Cmd::Lock(reimaged()).execute(&strategy, &id))
+            .unwrap();
+        assert_eq!(output, "Reboot slot locked");
+        runtime
+            .block_on(Cmd::Lock(NodeArgs::default()).execute(&strategy, &id))
+            .unwrap_err();
+
+        // Releasing it on behalf of the previous node ID frees the slot.
+        let output = runtime
+            .block_on(Cmd::Unlock(reimaged()).execute(&strategy, &id))
+            .unwrap();
+        assert_eq!(output, "Reboot slot unlocked");
+        runtime
+            .block_on(Cmd::Lock(NodeArgs::default()).execute(&strategy, &id))
+            .unwrap();
+        runtime
+            .block_on(Cmd::Unlock(NodeArgs::default()).execute(&strategy, &id))
+            .unwrap();
+
+        let output = runtime
+            .block_on(Cmd::Status(reimaged()).execute(&strategy, &id))
+            .unwrap();
+        let expected = format!(
+            "Node ID: 27e3ac02af3946af995c9940e18b0cce\nGroup: {}\nLock-manager: {}",
+            id.group, base_url
+        );
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,120 @@
+//! Logic for the `fleetlock-server` subcommand.
+
+use crate::fleet_lock::server::{self, LockServer, ServerSettings};
+use crate::utils;
+use anyhow::{Context, Result};
+use clap::Args;
+use fn_error_context::context;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Maximum lease TTL that can be granted (1 week).
+const MAX_LEASE_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Subcommand `fleetlock-server`.
+#[derive(Debug, Args)]
+pub struct Cmd {
+    /// Address to listen on.
+    #[arg(long = "listen", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+    /// Path to the file holding locks state.
+    #[arg(
+        long = "state-file",
+        default_value = "/var/lib/zincati/fleetlock-server.json"
+    )]
+    state_file: PathBuf,
+    /// Number of reboot slots per group.
+    #[arg(long = "slots", default_value_t = 1)]
+    slots: u64,
+    /// Number of reboot slots for a specific group (e.g. `workers=3`).
+    #[arg(long = "group-slots", value_parser = parse_group_slots)]
+    group_slots: Vec<(String, u64)>,
+    /// TTL of leases granted to clients asking for them (e.g. `15m`, default: no leases).
+    #[arg(long = "lease-ttl")]
+    lease_ttl: Option<String>,
+    /// Path to a file with the bearer token for admin endpoints (default: admin endpoints disabled).
+    #[arg(long = "admin-token-file")]
+    admin_token_file: Option<PathBuf>,
+}
+
+impl Cmd {
+    /// `fleetlock-server` subcommand entry point.
+    #[context("failed to run `fleetlock-server` subcommand")]
+    pub(crate) fn run(self) -> Result<()> {
+        let lease_ttl = match self.lease_ttl {
+            Some(ttl) => {
+                let ttl = utils::parse_duration(&ttl).context("failed to parse `--lease-ttl`")?;
+                if ttl.is_zero() || ttl.as_secs() > MAX_LEASE_TTL_SECS {
+                    anyhow::bail!("invalid `--lease-ttl`, must be between 1s and 7d");
+                }
+                Some(ttl)
+            }
+            None => None,
+        };
+        let settings = ServerSettings {
+            state_file: self.state_file,
+            default_slots: self.slots,
+            group_slots: self.group_slots.into_iter().collect::<BTreeMap<_, _>>(),
+            lease_ttl,
+            admin_token_file: self.admin_token_file,
+        };
+        let lock_server = LockServer::load(settings)?;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind(self.listen)
+                .await
+                .with_context(|| format!("failed to listen on '{}'", self.listen))?;
+            log::info!("FleetLock server listening on {}", self.listen);
+            server::serve(listener, lock_server).await
+        })
+    }
+}
+
+/// Parse a `<group>=<slots>` value.
+fn parse_group_slots(input: &str) -> Result<(String, u64)> {
+    let (group, slots) = input
+        .split_once('=')
+        .with_context(|| format!("invalid value '{}', expected '<group>=<slots>'", input))?;
+    let slots = slots
+        .parse()
+        .with_context(|| format!("invalid slots number '{}'", slots))?;
+    Ok((group.to_string(), slots))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{CliCommand, CliOptions};
+    use clap::Parser;
+
+    #[test]
+    fn test_fleetlock_server_args() {
+        let args = vec![
+            "zincati",
+            "fleetlock-server",
+            "--slots",
+            "2",
+            "--group-slots",
+            "workers=3",
+            "--group-slots",
+            "controllers=1",
+        ];
+        let cli = CliOptions::try_parse_from(args).unwrap();
+        if let CliCommand::FleetlockServer(cmd) = &cli.cmd {
+            assert_eq!(cmd.slots, 2);
+            assert_eq!(
+                cmd.group_slots,
+                vec![("workers".to_string(), 3), ("controllers".to_string(), 1)]
+            );
+            assert_eq!(cmd.listen.to_string(), "127.0.0.1:8080");
+        } else {
+            panic!("unexpected result: {:?}", cli);
+        }
+
+        let invalid = vec!["zincati", "fleetlock-server", "--group-slots", "workers"];
+        CliOptions::try_parse_from(invalid).unwrap_err();
+    }
+}
@@ -3,6 +3,11 @@
 mod agent;
 mod deadend;
 mod ex;
+mod fleetlock;
+mod fleetlock_server;
+mod motd;
+mod notify;
+mod postpone;
 
 use anyhow::Result;
 use clap::{ArgAction, Parser};
@@ -38,6 +43,11 @@ impl CliOptions {
             CliCommand::Agent => agent::run_agent(),
             CliCommand::DeadendMotd(cmd) => cmd.run(),
             CliCommand::Ex(cmd) => cmd.run(),
+            CliCommand::Fleetlock(cmd) => cmd.run(),
+            CliCommand::FleetlockServer(cmd) => cmd.run(),
+            CliCommand::Motd(cmd) => cmd.run(),
+            CliCommand::Notify(cmd) => cmd.run(),
+            CliCommand::Postpone(cmd) => cmd.run(),
         }
     }
 }
@@ -54,6 +64,19 @@ pub(crate) enum CliCommand {
     /// Print update agent state's last refresh time.
     #[command(hide = true, subcommand)]
     Ex(ex::Cmd),
+    /// Manage the FleetLock reboot slot of this node.
+    #[command(subcommand)]
+    Fleetlock(fleetlock::Cmd),
+    /// Reference FleetLock server, for reboot coordination.
+    FleetlockServer(fleetlock_server::Cmd),
+    /// Set or unset pending update MOTD state.
+    #[command(hide = true, subcommand)]
+    Motd(motd::Cmd),
+    /// Send a desktop notification to a graphical session.
+    #[command(hide = true)]
+    Notify(notify::Cmd),
+    /// Postpone a pending reboot.
+    Postpone(postpone::Cmd),
 }
 
 /// Return Error with msg if not run by user.
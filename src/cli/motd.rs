@@ -0,0 +1,177 @@
+//! Logic for the `motd` subcommand.
+
+use super::ensure_user;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use fn_error_context::context;
+use std::fs::Permissions;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Absolute path to the MOTD fragments directory.
+pub(super) static MOTD_FRAGMENTS_DIR: &str = "/run/motd.d/";
+/// Absolute path to the MOTD fragment with pending update state.
+static UPDATE_MOTD_PATH: &str = "/run/motd.d/85-zincati-update.motd";
+/// Absolute path to the issue fragments directory.
+static ISSUE_FRAGMENTS_DIR: &str = "/run/issue.d/";
+/// Absolute path to the issue fragment with pending update state.
+static UPDATE_ISSUE_PATH: &str = "/run/issue.d/85-zincati-update.issue";
+
+/// Subcommand `motd`.
+#[derive(Debug, Subcommand)]
+pub enum Cmd {
+    /// Set pending update state, for a staged release.
+    #[command(name = "set")]
+    Set {
+        /// Version of the staged release.
+        #[arg(long = "version")]
+        version: String,
+        /// When the reboot into the staged release is expected.
+        #[arg(long = "reboot")]
+        reboot: String,
+    },
+    /// Unset pending update state.
+    #[command(name = "unset")]
+    Unset,
+}
+
+impl Cmd {
+    /// `motd` subcommand entry point.
+    #[context("failed to run `motd` subcommand")]
+    pub(crate) fn run(self) -> Result<()> {
+        ensure_user(
+            "root",
+            "motd subcommand must be run as `root` user, \
+             and should be called by the Zincati agent process",
+        )?;
+        match self {
+            Cmd::Set { version, reboot } => {
+                let content = update_message(&version, &reboot);
+                write_fragment(MOTD_FRAGMENTS_DIR, UPDATE_MOTD_PATH, &content)?;
+                write_fragment(ISSUE_FRAGMENTS_DIR, UPDATE_ISSUE_PATH, &content)
+            }
+            Cmd::Unset => {
+                remove_fragment(UPDATE_MOTD_PATH)?;
+                remove_fragment(UPDATE_ISSUE_PATH)
+            }
+        }
+    }
+}
+
+/// Format the pending update message.
+fn update_message(version: &str, reboot: &str) -> String {
+    format!(
+        "Zincati has staged update {}, a reboot is expected {}.",
+        version, reboot
+    )
+}
+
+/// Atomically write a fragment file at `path`, under `dir`.
+pub(super) fn write_fragment(dir: &str, path: &str, content: &str) -> Result<()> {
+    // Fragments directories are on tmpfs and may not exist yet.
+    if !Path::new(dir).exists() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create directory '{}'", dir))?;
+    }
+
+    // Avoid showing partially-written messages using tempfile and
+    // persist (rename).
+    let mut f = tempfile::Builder::new()
+        .prefix(".zincati.")
+        .suffix(".partial")
+        // Create the tempfile in the same directory as the final fragment,
+        // to ensure proper SELinux labels are applied to the tempfile
+        // before renaming.
+        .tempfile_in(dir)
+        .with_context(|| format!("failed to create temporary file under '{}'", dir))?;
+    // Set correct permissions of the temporary file, before moving to
+    // the destination (`tempfile` creates files with mode 0600).
+    std::fs::set_permissions(f.path(), Permissions::from_mode(0o644)).with_context(|| {
+        format!(
+            "failed to set permissions of temporary file at '{}'",
+            f.path().display()
+        )
+    })?;
+
+    writeln!(f, "{}", content)
+        .and_then(|_| f.flush())
+        .with_context(|| format!("failed to write content to '{}'", f.path().display()))?;
+
+    f.persist(path)
+        .with_context(|| format!("failed to persist fragment to '{}'", path))?;
+    Ok(())
+}
+
+/// Remove fragment file, if any.
+pub(super) fn remove_fragment(path: &str) -> Result<()> {
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            anyhow::bail!("failed to remove fragment at '{}': {}", path, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{CliCommand, CliOptions};
+    use clap::Parser;
+
+    #[test]
+    fn test_motd_set() {
+        {
+            let missing_reboot = vec!["zincati", "motd", "set", "--version", "v1"];
+            let cli = CliOptions::try_parse_from(missing_reboot);
+            assert!(cli.is_err());
+        }
+        {
+            let args = vec![
+                "zincati",
+                "motd",
+                "set",
+                "--version",
+                "v1",
+                "--reboot",
+                "when a reboot lock is acquired",
+            ];
+            let cli = CliOptions::try_parse_from(args).unwrap();
+            if let CliCommand::Motd(Cmd::Set { version, reboot }) = &cli.cmd {
+                assert_eq!(version, "v1");
+                assert_eq!(reboot, "when a reboot lock is acquired");
+            } else {
+                panic!("unexpected result: {:?}", cli);
+            }
+        }
+        {
+            let unset = vec!["zincati", "motd", "unset"];
+            let cli = CliOptions::try_parse_from(unset).unwrap();
+            if !matches!(&cli.cmd, CliCommand::Motd(Cmd::Unset)) {
+                panic!("unexpected result: {:?}", cli);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fragment_lifecycle() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let dir = tmpdir.path().join("motd.d");
+        let dir = dir.to_str().unwrap();
+        let path = format!("{}/85-zincati-update.motd", dir);
+
+        let content = update_message("v1", "when a reboot lock is acquired");
+        write_fragment(dir, &path, &content).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "Zincati has staged update v1, a reboot is expected when a reboot lock is acquired.\n"
+        );
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+
+        remove_fragment(&path).unwrap();
+        assert!(!Path::new(&path).exists());
+        // Removing a missing fragment is not an error.
+        remove_fragment(&path).unwrap();
+    }
+}
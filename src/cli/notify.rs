@@ -0,0 +1,112 @@
+//! Logic for the `notify` subcommand.
+
+use super::ensure_user;
+use crate::notifications::desktop;
+use anyhow::{Context, Result};
+use clap::Parser;
+use fn_error_context::context;
+use std::path::Path;
+
+/// Subcommand `notify`.
+#[derive(Debug, Parser)]
+pub struct Cmd {
+    /// UID of the user to notify, on its D-Bus user bus.
+    #[arg(long = "uid")]
+    uid: u32,
+    /// Summary line of the notification.
+    #[arg(long = "summary")]
+    summary: String,
+    /// Body of the notification.
+    #[arg(long = "body")]
+    body: String,
+    /// Send the notification with critical urgency.
+    #[arg(long = "critical")]
+    critical: bool,
+}
+
+impl Cmd {
+    /// `notify` subcommand entry point.
+    #[context("failed to run `notify` subcommand")]
+    pub(crate) fn run(self) -> Result<()> {
+        ensure_user(
+            "root",
+            "notify subcommand must be run as `root` user, \
+             and should be called by the Zincati agent process",
+        )?;
+
+        let bus_path = desktop::user_bus_path(self.uid);
+        if !Path::new(&bus_path).exists() {
+            anyhow::bail!("no user bus running at '{}'", bus_path);
+        }
+
+        // User buses only accept connections from their owner.
+        drop_privileges(self.uid)?;
+        desktop::notify(
+            &format!("unix:path={}", bus_path),
+            &self.summary,
+            &self.body,
+            self.critical,
+        )?;
+        Ok(())
+    }
+}
+
+/// Switch this process to the given user, and its primary group.
+#[context("failed to switch to user with UID {}", uid)]
+fn drop_privileges(uid: u32) -> Result<()> {
+    let user = users::get_user_by_uid(uid).context("unknown user")?;
+    let gid = user.primary_group_id();
+
+    // Group membership must be changed first, while still privileged.
+    // SAFETY: these only alter credentials of the current process.
+    let ret = unsafe { libc::setgroups(0, std::ptr::null()) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error()).context("failed to drop supplementary groups");
+    }
+    let ret = unsafe { libc::setresgid(gid, gid, gid) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error()).context("failed to set group ID");
+    }
+    let ret = unsafe { libc::setresuid(uid, uid, uid) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error()).context("failed to set user ID");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{CliCommand, CliOptions};
+
+    #[test]
+    fn test_notify_args() {
+        {
+            let missing_uid = vec!["zincati", "notify", "--summary", "s", "--body", "b"];
+            let cli = CliOptions::try_parse_from(missing_uid);
+            assert!(cli.is_err());
+        }
+        {
+            let args = vec![
+                "zincati",
+                "notify",
+                "--uid",
+                "1000",
+                "--summary",
+                "Reboot pending",
+                "--body",
+                "Update v1",
+                "--critical",
+            ];
+            let cli = CliOptions::try_parse_from(args).unwrap();
+            if let CliCommand::Notify(cmd) = &cli.cmd {
+                assert_eq!(cmd.uid, 1000);
+                assert_eq!(cmd.summary, "Reboot pending");
+                assert_eq!(cmd.body, "Update v1");
+                assert!(cmd.critical);
+            } else {
+                panic!("unexpected result: {:?}", cli);
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Logic for the `postpone` subcommand.
+
+use anyhow::Result;
+use clap::Args;
+use fn_error_context::context;
+use zbus::proxy;
+
+/// Subcommand `postpone`.
+#[derive(Debug, Args)]
+pub struct Cmd {
+    /// How long to postpone the pending reboot for (e.g. `30m`, `1h`).
+    duration: String,
+    /// Reason for postponing, recorded in the agent logs.
+    #[arg(long = "reason", default_value = "")]
+    reason: String,
+}
+
+impl Cmd {
+    /// `postpone` subcommand entry point.
+    #[context("failed to run `postpone` subcommand")]
+    pub(crate) fn run(self) -> Result<()> {
+        let connection = zbus::blocking::Connection::system()?;
+        let proxy = UpdatesProxyBlocking::new(&connection)?;
+        let until = proxy.postpone(&self.duration, &self.reason)?;
+        println!("Reboot postponed until {}", until);
+        Ok(())
+    }
+}
+
+#[proxy(
+    interface = "org.coreos.zincati.Updates",
+    default_service = "org.coreos.zincati",
+    default_path = "/org/coreos/zincati"
+)]
+trait Updates {
+    /// Postpone method
+    fn postpone(&self, duration: &str, reason: &str) -> zbus::Result<String>;
+}
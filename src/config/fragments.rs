@@ -1,8 +1,9 @@
 //! TOML configuration fragments.
 
+use anyhow::Result;
 use ordered_float::NotNan;
 use serde::Deserialize;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::num::NonZeroU64;
 
 /// Top-level configuration stanza.
@@ -12,12 +13,30 @@ pub(crate) struct ConfigFragment {
     pub(crate) agent: Option<AgentFragment>,
     /// Cincinnati client configuration.
     pub(crate) cincinnati: Option<CincinnatiFragment>,
+    /// HTTP client configuration, shared by all services.
+    pub(crate) http: Option<HttpFragment>,
     /// Agent identity.
     pub(crate) identity: Option<IdentityFragment>,
+    /// User-facing notifications configuration.
+    pub(crate) notifications: Option<NotificationsFragment>,
     /// Update strategy configuration.
     pub(crate) updates: Option<UpdateFragment>,
 }
 
+impl ConfigFragment {
+    /// Check for conflicting settings within this fragment.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let fleet_lock = self.updates.as_ref().and_then(|u| u.fleet_lock.as_ref());
+        if let Some(fl) = fleet_lock {
+            anyhow::ensure!(
+                fl.base_url.is_none() || fl.base_urls.is_none(),
+                "`updates.fleet_lock.base_url` and `updates.fleet_lock.base_urls` are mutually exclusive"
+            );
+        }
+        Ok(())
+    }
+}
+
 /// Config fragment for agent settings.
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub(crate) struct AgentFragment {
@@ -48,6 +67,70 @@ pub(crate) struct IdentityFragment {
 pub(crate) struct CincinnatiFragment {
     /// Base URL to upstream cincinnati server.
     pub(crate) base_url: Option<String>,
+    /// HTTP client configuration overrides.
+    pub(crate) http: Option<HttpFragment>,
+}
+
+/// Config fragment for HTTP clients.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub(crate) struct HttpFragment {
+    /// Path to a PEM bundle of additional trusted CA certificates.
+    pub(crate) ca_bundle: Option<String>,
+    /// Path to a PEM client certificate, for mutual TLS.
+    pub(crate) client_cert: Option<String>,
+    /// Path to a PEM (PKCS#8) client private key, for mutual TLS.
+    pub(crate) client_key: Option<String>,
+    /// Path to a file containing a bearer token.
+    pub(crate) bearer_token_file: Option<String>,
+    /// Username for basic authentication.
+    pub(crate) username: Option<String>,
+    /// Path to a file containing the basic authentication password.
+    pub(crate) password_file: Option<String>,
+    /// Timeout for connection establishment (e.g. `10s`, default: none).
+    pub(crate) connect_timeout: Option<String>,
+    /// Timeout for requests completion (e.g. `5m`, default: `30m`).
+    pub(crate) request_timeout: Option<String>,
+    /// Proxy URL for all requests.
+    pub(crate) proxy: Option<String>,
+    /// Username for proxy authentication.
+    pub(crate) proxy_username: Option<String>,
+    /// Path to a file containing the proxy authentication password.
+    pub(crate) proxy_password_file: Option<String>,
+}
+
+/// Config fragment for user-facing notifications.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub(crate) struct NotificationsFragment {
+    /// Custom message templates.
+    pub(crate) template: Option<Vec<MessageTemplate>>,
+    /// Webhook for update lifecycle events.
+    pub(crate) webhook: Option<Webhook>,
+}
+
+/// Config fragment for a `notifications.template` entry.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub(crate) struct MessageTemplate {
+    /// Message phase (`header`, `first_warning`, `final_warning`, or `urgent_warning`).
+    pub(crate) phase: String,
+    /// Language (e.g. `de` or `de_DE`, default: any).
+    pub(crate) lang: Option<String>,
+    /// Inline template text.
+    pub(crate) text: Option<String>,
+    /// Path to a file containing the template text.
+    pub(crate) file: Option<String>,
+}
+
+/// Config fragment for the `notifications.webhook` section.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub(crate) struct Webhook {
+    /// Endpoint URL (default: none, disabled).
+    pub(crate) url: Option<String>,
+    /// Custom HTTP headers.
+    pub(crate) headers: Option<BTreeMap<String, String>>,
+    /// Path to a file containing a bearer token.
+    pub(crate) bearer_token_file: Option<String>,
+    /// Events to deliver (default: all).
+    pub(crate) events: Option<Vec<String>>,
 }
 
 /// Config fragment for update logic.
@@ -59,6 +142,14 @@ pub(crate) struct UpdateFragment {
     pub(crate) enabled: Option<bool>,
     /// Update strategy (default: immediate).
     pub(crate) strategy: Option<String>,
+    /// Maximum local delay before staging a newly seen release (e.g. `48h`).
+    pub(crate) rollout_spread: Option<String>,
+    /// Minimum interval between two finalizations (e.g. `24h`).
+    pub(crate) min_interval_between_reboots: Option<String>,
+    /// Whether urgent releases may bypass periodic windows and user sessions grace period.
+    pub(crate) honor_urgent: Option<bool>,
+    /// Maximum time users may postpone a pending reboot (e.g. `2h`).
+    pub(crate) max_postponement: Option<String>,
     /// `fleet_lock` strategy config.
     pub(crate) fleet_lock: Option<UpdateFleetLock>,
     /// `periodic` strategy config.
@@ -70,6 +161,14 @@ pub(crate) struct UpdateFragment {
 pub(crate) struct UpdateFleetLock {
     /// Base URL for the remote semaphore manager.
     pub(crate) base_url: Option<String>,
+    /// Base URLs for replicas of the remote semaphore manager, in order of preference.
+    pub(crate) base_urls: Option<Vec<String>>,
+    /// Whether to request lock leases, on servers supporting them (default: false).
+    pub(crate) leases: Option<bool>,
+    /// Number of failed finalizations after which a held reboot slot is released (default: 3).
+    pub(crate) max_failed_finalizations: Option<u32>,
+    /// HTTP client configuration overrides.
+    pub(crate) http: Option<HttpFragment>,
 }
 
 /// Config fragment for `periodic` update strategy.
@@ -77,11 +176,21 @@ pub(crate) struct UpdateFleetLock {
 pub(crate) struct UpdatePeriodic {
     /// A weekly window.
     pub(crate) window: Option<Vec<UpdatePeriodicWindow>>,
+    /// A monthly window.
+    pub(crate) monthly_window: Option<Vec<UpdatePeriodicMonthlyWindow>>,
     /// A time zone in the IANA Time Zone Database (https://www.iana.org/time-zones)
     /// or "localtime". If unset, UTC is used.
     ///
     /// Examples: `America/Toronto`, `Europe/Rome`
     pub(crate) time_zone: Option<String>,
+    /// An absolute period during which updates are not allowed.
+    pub(crate) blackout: Option<Vec<UpdatePeriodicBlackout>>,
+    /// Minimum remaining window length (in minutes) required to start finalization.
+    pub(crate) min_remaining_minutes: Option<u32>,
+    /// Whether to wait for system clock synchronization before finalizing.
+    pub(crate) wait_time_sync: Option<bool>,
+    /// Maximum time (in minutes) to wait for system clock synchronization.
+    pub(crate) time_sync_timeout_minutes: Option<u32>,
 }
 
 /// Config fragment for a `periodic.window` entry.
@@ -95,6 +204,28 @@ pub(crate) struct UpdatePeriodicWindow {
     pub(crate) length_minutes: u32,
 }
 
+/// Config fragment for a `periodic.monthly_window` entry.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub(crate) struct UpdatePeriodicMonthlyWindow {
+    /// Weekdays (English names).
+    pub(crate) days: BTreeSet<String>,
+    /// Weekday occurrences within the month (English ordinals).
+    pub(crate) occurrences: BTreeSet<String>,
+    /// Start time (`hh:mm` 24h format).
+    pub(crate) start_time: String,
+    /// Window length in minutes.
+    pub(crate) length_minutes: u32,
+}
+
+/// Config fragment for a `periodic.blackout` entry.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub(crate) struct UpdatePeriodicBlackout {
+    /// Start datetime (`YYYY-MM-DDThh:mm` format), inclusive.
+    pub(crate) start: String,
+    /// End datetime (`YYYY-MM-DDThh:mm` format), exclusive.
+    pub(crate) end: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -113,18 +244,94 @@ mod tests {
             }),
             cincinnati: Some(CincinnatiFragment {
                 base_url: Some("http://cincinnati.example.com:80/".to_string()),
+                http: Some(HttpFragment {
+                    ca_bundle: None,
+                    client_cert: None,
+                    client_key: None,
+                    bearer_token_file: None,
+                    username: Some("zincati".to_string()),
+                    password_file: Some("/etc/zincati/cincinnati-password".to_string()),
+                    connect_timeout: None,
+                    request_timeout: None,
+                    proxy: None,
+                    proxy_username: None,
+                    proxy_password_file: None,
+                }),
+            }),
+            http: Some(HttpFragment {
+                ca_bundle: Some("/etc/zincati/tls/ca.pem".to_string()),
+                client_cert: None,
+                client_key: None,
+                bearer_token_file: None,
+                username: None,
+                password_file: None,
+                connect_timeout: Some("10s".to_string()),
+                request_timeout: Some("5m".to_string()),
+                proxy: Some("http://proxy.example.com:3128".to_string()),
+                proxy_username: Some("zincati".to_string()),
+                proxy_password_file: Some("/etc/zincati/proxy-password".to_string()),
             }),
             identity: Some(IdentityFragment {
                 group: Some("workers".to_string()),
                 node_uuid: Some("27e3ac02af3946af995c9940e18b0cce".to_string()),
                 rollout_wariness: Some(NotNan::new(0.5).unwrap()),
             }),
+            notifications: Some(NotificationsFragment {
+                template: Some(vec![
+                    MessageTemplate {
+                        phase: "first_warning".to_string(),
+                        lang: None,
+                        text: Some(
+                            "Update ${version} will be applied in ${time_remaining}.".to_string(),
+                        ),
+                        file: None,
+                    },
+                    MessageTemplate {
+                        phase: "first_warning".to_string(),
+                        lang: Some("de".to_string()),
+                        text: None,
+                        file: Some("/etc/zincati/templates/first-warning.de.txt".to_string()),
+                    },
+                ]),
+                webhook: Some(Webhook {
+                    url: Some("https://chatops.example.com/zincati".to_string()),
+                    headers: Some(BTreeMap::from([(
+                        "X-Cluster".to_string(),
+                        "prod".to_string(),
+                    )])),
+                    bearer_token_file: Some("/etc/zincati/webhook-token".to_string()),
+                    events: Some(vec![
+                        "update_staged".to_string(),
+                        "update_finalized".to_string(),
+                    ]),
+                }),
+            }),
             updates: Some(UpdateFragment {
                 allow_downgrade: Some(true),
                 enabled: Some(false),
                 strategy: Some("fleet_lock".to_string()),
+                rollout_spread: Some("48h".to_string()),
+                min_interval_between_reboots: Some("24h".to_string()),
+                honor_urgent: Some(true),
+                max_postponement: Some("2h".to_string()),
                 fleet_lock: Some(UpdateFleetLock {
                     base_url: Some("http://fleet-lock.example.com:8080/".to_string()),
+                    base_urls: None,
+                    leases: Some(true),
+                    max_failed_finalizations: Some(5),
+                    http: Some(HttpFragment {
+                        ca_bundle: None,
+                        client_cert: Some("/etc/zincati/tls/client.pem".to_string()),
+                        client_key: Some("/etc/zincati/tls/client.key".to_string()),
+                        bearer_token_file: None,
+                        username: None,
+                        password_file: None,
+                        connect_timeout: None,
+                        request_timeout: None,
+                        proxy: None,
+                        proxy_username: None,
+                        proxy_password_file: None,
+                    }),
                 }),
                 periodic: Some(UpdatePeriodic {
                     window: Some(vec![
@@ -139,11 +346,43 @@ mod tests {
                             length_minutes: 25,
                         },
                     ]),
+                    monthly_window: Some(vec![UpdatePeriodicMonthlyWindow {
+                        days: btreeset!("Tue".to_string()),
+                        occurrences: btreeset!("second".to_string()),
+                        start_time: "22:00".to_string(),
+                        length_minutes: 180,
+                    }]),
                     time_zone: Some("localtime".to_string()),
+                    blackout: Some(vec![UpdatePeriodicBlackout {
+                        start: "2019-12-23T00:00".to_string(),
+                        end: "2020-01-02T12:00".to_string(),
+                    }]),
+                    min_remaining_minutes: Some(15),
+                    wait_time_sync: Some(true),
+                    time_sync_timeout_minutes: Some(30),
                 }),
             }),
         };
 
         assert_eq!(cfg, expected);
+        cfg.validate().unwrap();
+    }
+
+    #[test]
+    fn fleet_lock_base_url_conflict() {
+        let content = r#"
+[updates.fleet_lock]
+base_url = "http://fleet-lock.example.com:8080/"
+base_urls = [ "http://fleet-lock-2.example.com:8080/" ]
+"#;
+        let cfg: ConfigFragment = toml::from_str(content).unwrap();
+        cfg.validate().unwrap_err();
+
+        let content = r#"
+[updates.fleet_lock]
+base_urls = [ "http://fleet-lock-2.example.com:8080/" ]
+"#;
+        let cfg: ConfigFragment = toml::from_str(content).unwrap();
+        cfg.validate().unwrap();
     }
 }
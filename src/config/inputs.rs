@@ -5,6 +5,7 @@ use fn_error_context::context;
 use log::trace;
 use ordered_float::NotNan;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::num::NonZeroU64;
 
 /// Runtime configuration holding environmental inputs.
@@ -14,6 +15,7 @@ pub(crate) struct ConfigInput {
     pub(crate) cincinnati: CincinnatiInput,
     pub(crate) updates: UpdateInput,
     pub(crate) identity: IdentityInput,
+    pub(crate) notifications: NotificationsInput,
 }
 
 impl ConfigInput {
@@ -32,6 +34,8 @@ impl ConfigInput {
                 .with_context(|| format!("failed to read file '{}'", fpath.display()))?;
             let frag: fragments::ConfigFragment =
                 toml::from_str(&content).context("failed to parse TOML")?;
+            frag.validate()
+                .with_context(|| format!("invalid config fragment '{}'", fpath.display()))?;
 
             fragments.push(frag);
         }
@@ -46,6 +50,8 @@ impl ConfigInput {
         let mut cincinnatis = vec![];
         let mut updates = vec![];
         let mut identities = vec![];
+        let mut notifications = vec![];
+        let mut http = HttpInput::default();
 
         for snip in fragments {
             if let Some(a) = snip.agent {
@@ -60,13 +66,26 @@ impl ConfigInput {
             if let Some(i) = snip.identity {
                 identities.push(i);
             }
+            if let Some(n) = snip.notifications {
+                notifications.push(n);
+            }
+            if let Some(h) = snip.http {
+                http.merge_fragment(h);
+            }
         }
 
+        // Apply service-specific HTTP overrides on top of shared settings.
+        let mut cincinnati = CincinnatiInput::from_fragments(cincinnatis);
+        cincinnati.http = http.with_overrides(&cincinnati.http);
+        let mut updates = UpdateInput::from_fragments(updates);
+        updates.fleet_lock.http = http.with_overrides(&updates.fleet_lock.http);
+
         Self {
             agent: AgentInput::from_fragments(agents),
-            cincinnati: CincinnatiInput::from_fragments(cincinnatis),
-            updates: UpdateInput::from_fragments(updates),
+            cincinnati,
+            updates,
             identity: IdentityInput::from_fragments(identities),
+            notifications: NotificationsInput::from_fragments(notifications),
         }
     }
 }
@@ -100,24 +119,102 @@ impl AgentInput {
 pub(crate) struct CincinnatiInput {
     /// Base URL (template) for the Cincinnati service.
     pub(crate) base_url: String,
+    /// HTTP client config.
+    pub(crate) http: HttpInput,
 }
 
 impl CincinnatiInput {
     fn from_fragments(fragments: Vec<fragments::CincinnatiFragment>) -> Self {
         let mut cfg = Self {
             base_url: String::new(),
+            http: HttpInput::default(),
         };
 
         for snip in fragments {
             if let Some(u) = snip.base_url {
                 cfg.base_url = u;
             }
+            if let Some(h) = snip.http {
+                cfg.http.merge_fragment(h);
+            }
         }
 
         cfg
     }
 }
 
+/// Config for HTTP clients.
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct HttpInput {
+    pub(crate) ca_bundle: Option<String>,
+    pub(crate) client_cert: Option<String>,
+    pub(crate) client_key: Option<String>,
+    pub(crate) bearer_token_file: Option<String>,
+    pub(crate) username: Option<String>,
+    pub(crate) password_file: Option<String>,
+    pub(crate) connect_timeout: Option<String>,
+    pub(crate) request_timeout: Option<String>,
+    pub(crate) proxy: Option<String>,
+    pub(crate) proxy_username: Option<String>,
+    pub(crate) proxy_password_file: Option<String>,
+}
+
+impl HttpInput {
+    /// Merge a config fragment into this config.
+    fn merge_fragment(&mut self, snip: fragments::HttpFragment) {
+        let overrides = Self {
+            ca_bundle: snip.ca_bundle,
+            client_cert: snip.client_cert,
+            client_key: snip.client_key,
+            bearer_token_file: snip.bearer_token_file,
+            username: snip.username,
+            password_file: snip.password_file,
+            connect_timeout: snip.connect_timeout,
+            request_timeout: snip.request_timeout,
+            proxy: snip.proxy,
+            proxy_username: snip.proxy_username,
+            proxy_password_file: snip.proxy_password_file,
+        };
+        *self = self.with_overrides(&overrides);
+    }
+
+    /// Return this config with values from `overrides` taking precedence.
+    ///
+    /// Credentials are overridden as a whole, so that bearer and basic
+    /// authentication settings from different sources do not conflict.
+    /// The same applies to the client certificate and its key.
+    pub(crate) fn with_overrides(&self, overrides: &Self) -> Self {
+        let pick = |base: &Option<String>, over: &Option<String>| over.clone().or(base.clone());
+        let auth = if overrides.bearer_token_file.is_some()
+            || overrides.username.is_some()
+            || overrides.password_file.is_some()
+        {
+            overrides
+        } else {
+            self
+        };
+        let client_tls = if overrides.client_cert.is_some() || overrides.client_key.is_some() {
+            overrides
+        } else {
+            self
+        };
+
+        Self {
+            ca_bundle: pick(&self.ca_bundle, &overrides.ca_bundle),
+            client_cert: client_tls.client_cert.clone(),
+            client_key: client_tls.client_key.clone(),
+            bearer_token_file: auth.bearer_token_file.clone(),
+            username: auth.username.clone(),
+            password_file: auth.password_file.clone(),
+            connect_timeout: pick(&self.connect_timeout, &overrides.connect_timeout),
+            request_timeout: pick(&self.request_timeout, &overrides.request_timeout),
+            proxy: pick(&self.proxy, &overrides.proxy),
+            proxy_username: pick(&self.proxy_username, &overrides.proxy_username),
+            proxy_password_file: pick(&self.proxy_password_file, &overrides.proxy_password_file),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub(crate) struct IdentityInput {
     pub(crate) group: String,
@@ -149,6 +246,73 @@ impl IdentityInput {
     }
 }
 
+/// Config for user-facing notifications.
+#[derive(Debug, Serialize)]
+pub(crate) struct NotificationsInput {
+    /// Custom message templates.
+    pub(crate) templates: Vec<MessageTemplateInput>,
+    /// Webhook for update lifecycle events.
+    pub(crate) webhook: WebhookInput,
+}
+
+/// Custom template for a user-facing message.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct MessageTemplateInput {
+    pub(crate) phase: String,
+    pub(crate) lang: String,
+    pub(crate) text: Option<String>,
+    pub(crate) file: Option<String>,
+}
+
+/// Config for the update lifecycle events webhook.
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct WebhookInput {
+    pub(crate) url: String,
+    #[serde(skip)]
+    pub(crate) headers: BTreeMap<String, String>,
+    pub(crate) bearer_token_file: Option<String>,
+    pub(crate) events: Vec<String>,
+}
+
+impl NotificationsInput {
+    fn from_fragments(fragments: Vec<fragments::NotificationsFragment>) -> Self {
+        let mut cfg = Self {
+            templates: vec![],
+            webhook: WebhookInput::default(),
+        };
+
+        for snip in fragments {
+            if let Some(templates) = snip.template {
+                for entry in templates {
+                    let template = MessageTemplateInput {
+                        phase: entry.phase,
+                        lang: entry.lang.unwrap_or_default(),
+                        text: entry.text,
+                        file: entry.file,
+                    };
+                    cfg.templates.push(template);
+                }
+            }
+            if let Some(webhook) = snip.webhook {
+                if let Some(url) = webhook.url {
+                    cfg.webhook.url = url;
+                }
+                if let Some(headers) = webhook.headers {
+                    cfg.webhook.headers.extend(headers);
+                }
+                if let Some(path) = webhook.bearer_token_file {
+                    cfg.webhook.bearer_token_file = Some(path);
+                }
+                if let Some(events) = webhook.events {
+                    cfg.webhook.events = events;
+                }
+            }
+        }
+
+        cfg
+    }
+}
+
 /// Config for update logic.
 #[derive(Debug, Serialize)]
 pub(crate) struct UpdateInput {
@@ -158,6 +322,14 @@ pub(crate) struct UpdateInput {
     pub(crate) enabled: bool,
     /// Update strategy.
     pub(crate) strategy: String,
+    /// Maximum local delay before staging a newly seen release.
+    pub(crate) rollout_spread: String,
+    /// Minimum interval between two finalizations.
+    pub(crate) min_interval_between_reboots: String,
+    /// Whether urgent releases may bypass periodic windows and user sessions grace period.
+    pub(crate) honor_urgent: bool,
+    /// Maximum time users may postpone a pending reboot.
+    pub(crate) max_postponement: String,
     /// `fleet_lock` strategy config.
     pub(crate) fleet_lock: FleetLockInput,
     /// `periodic` strategy config.
@@ -167,8 +339,14 @@ pub(crate) struct UpdateInput {
 /// Config for "fleet_lock" strategy.
 #[derive(Clone, Debug, Serialize)]
 pub(crate) struct FleetLockInput {
-    /// Base URL (template) for the FleetLock service.
-    pub(crate) base_url: String,
+    /// Base URLs (templates) for the FleetLock service, in order of preference.
+    pub(crate) base_urls: Vec<String>,
+    /// Whether to request lock leases.
+    pub(crate) leases: bool,
+    /// Number of failed finalizations after which a held reboot slot is released.
+    pub(crate) max_failed_finalizations: u32,
+    /// HTTP client config.
+    pub(crate) http: HttpInput,
 }
 
 /// Config for "periodic" strategy.
@@ -176,9 +354,19 @@ pub(crate) struct FleetLockInput {
 pub(crate) struct PeriodicInput {
     /// Set of updates windows.
     pub(crate) intervals: Vec<PeriodicIntervalInput>,
+    /// Set of monthly updates windows.
+    pub(crate) monthly_intervals: Vec<PeriodicMonthlyIntervalInput>,
     /// A time zone in the IANA Time Zone Database or "localtime".
     /// Defaults to "UTC".
     pub(crate) time_zone: String,
+    /// Set of blackout periods.
+    pub(crate) blackouts: Vec<PeriodicBlackoutInput>,
+    /// Minimum remaining window length (in minutes) required to start finalization.
+    pub(crate) min_remaining_minutes: u32,
+    /// Whether to wait for system clock synchronization before finalizing.
+    pub(crate) wait_time_sync: bool,
+    /// Maximum time (in minutes) to wait for system clock synchronization.
+    pub(crate) time_sync_timeout_minutes: u32,
 }
 
 /// Update window for a "periodic" interval.
@@ -189,17 +377,53 @@ pub(crate) struct PeriodicIntervalInput {
     pub(crate) length_minutes: u32,
 }
 
+/// Monthly update window for a "periodic" interval.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct PeriodicMonthlyIntervalInput {
+    pub(crate) occurrence: String,
+    pub(crate) start_day: String,
+    pub(crate) start_time: String,
+    pub(crate) length_minutes: u32,
+}
+
+/// Blackout period for a "periodic" strategy.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct PeriodicBlackoutInput {
+    pub(crate) start: String,
+    pub(crate) end: String,
+}
+
 impl UpdateInput {
+    #[cfg(test)]
+    pub(crate) fn mock_fleet_lock(base_urls: Vec<String>) -> Self {
+        let mut input = Self::from_fragments(vec![]);
+        input.strategy = "fleet_lock".to_string();
+        input.fleet_lock.base_urls = base_urls;
+        input
+    }
+
     fn from_fragments(fragments: Vec<fragments::UpdateFragment>) -> Self {
         let mut allow_downgrade = false;
         let mut enabled = true;
         let mut strategy = String::new();
+        let mut rollout_spread = String::new();
+        let mut min_interval_between_reboots = String::new();
+        let mut honor_urgent = false;
+        let mut max_postponement = String::new();
         let mut fleet_lock = FleetLockInput {
-            base_url: String::new(),
+            base_urls: vec![],
+            leases: false,
+            max_failed_finalizations: 3,
+            http: HttpInput::default(),
         };
         let mut periodic = PeriodicInput {
             intervals: vec![],
+            monthly_intervals: vec![],
             time_zone: "UTC".to_string(),
+            blackouts: vec![],
+            min_remaining_minutes: 0,
+            wait_time_sync: false,
+            time_sync_timeout_minutes: 60,
         };
 
         for snip in fragments {
@@ -212,15 +436,48 @@ impl UpdateInput {
             if let Some(s) = snip.strategy {
                 strategy = s;
             }
+            if let Some(rs) = snip.rollout_spread {
+                rollout_spread = rs;
+            }
+            if let Some(mi) = snip.min_interval_between_reboots {
+                min_interval_between_reboots = mi;
+            }
+            if let Some(hu) = snip.honor_urgent {
+                honor_urgent = hu;
+            }
+            if let Some(mp) = snip.max_postponement {
+                max_postponement = mp;
+            }
             if let Some(fl) = snip.fleet_lock {
                 if let Some(b) = fl.base_url {
-                    fleet_lock.base_url = b;
+                    fleet_lock.base_urls = vec![b];
+                }
+                if let Some(b) = fl.base_urls {
+                    fleet_lock.base_urls = b;
+                }
+                if let Some(l) = fl.leases {
+                    fleet_lock.leases = l;
+                }
+                if let Some(m) = fl.max_failed_finalizations {
+                    fleet_lock.max_failed_finalizations = m;
+                }
+                if let Some(h) = fl.http {
+                    fleet_lock.http.merge_fragment(h);
                 }
             }
             if let Some(w) = snip.periodic {
                 if let Some(tz) = w.time_zone {
                     periodic.time_zone = tz;
                 }
+                if let Some(mins) = w.min_remaining_minutes {
+                    periodic.min_remaining_minutes = mins;
+                }
+                if let Some(wait) = w.wait_time_sync {
+                    periodic.wait_time_sync = wait;
+                }
+                if let Some(mins) = w.time_sync_timeout_minutes {
+                    periodic.time_sync_timeout_minutes = mins;
+                }
                 if let Some(win) = w.window {
                     for entry in win {
                         for day in entry.days {
@@ -233,6 +490,30 @@ impl UpdateInput {
                         }
                     }
                 }
+                if let Some(win) = w.monthly_window {
+                    for entry in win {
+                        for occurrence in &entry.occurrences {
+                            for day in &entry.days {
+                                let interval = PeriodicMonthlyIntervalInput {
+                                    occurrence: occurrence.clone(),
+                                    start_day: day.clone(),
+                                    start_time: entry.start_time.clone(),
+                                    length_minutes: entry.length_minutes,
+                                };
+                                periodic.monthly_intervals.push(interval);
+                            }
+                        }
+                    }
+                }
+                if let Some(blackouts) = w.blackout {
+                    for entry in blackouts {
+                        let blackout = PeriodicBlackoutInput {
+                            start: entry.start,
+                            end: entry.end,
+                        };
+                        periodic.blackouts.push(blackout);
+                    }
+                }
             }
         }
 
@@ -240,6 +521,10 @@ impl UpdateInput {
             allow_downgrade,
             enabled,
             strategy,
+            rollout_spread,
+            min_interval_between_reboots,
+            honor_urgent,
+            max_postponement,
             fleet_lock,
             periodic,
         }
@@ -13,13 +13,17 @@ pub(crate) mod inputs;
 
 use crate::cincinnati::Cincinnati;
 use crate::identity::Identity;
+use crate::notifications::webhook::WebhookSettings;
+use crate::notifications::MessageTemplates;
 use crate::strategy::UpdateStrategy;
 use crate::update_agent;
-use anyhow::Result;
+use crate::utils;
+use anyhow::{Context, Result};
 use clap::crate_name;
 use fn_error_context::context;
 use serde::Serialize;
 use std::num::NonZeroU64;
+use std::time::Duration;
 
 /// Runtime configuration for the agent.
 ///
@@ -32,12 +36,24 @@ pub(crate) struct Settings {
     pub(crate) enabled: bool,
     /// Agent timing, steady state refresh period.
     pub(crate) steady_interval_secs: NonZeroU64,
+    /// Maximum local delay before staging a newly seen release.
+    pub(crate) rollout_spread: Duration,
+    /// Minimum interval between two finalizations.
+    pub(crate) min_interval_between_reboots: Duration,
+    /// Whether urgent releases may bypass periodic windows and user sessions grace period.
+    pub(crate) honor_urgent: bool,
+    /// Maximum time users may postpone a pending reboot.
+    pub(crate) max_postponement: Duration,
     /// Cincinnati configuration.
     pub(crate) cincinnati: Cincinnati,
     /// Agent configuration.
     pub(crate) identity: Identity,
     /// Agent update strategy.
     pub(crate) strategy: UpdateStrategy,
+    /// Templates for user-facing messages.
+    pub(crate) message_templates: MessageTemplates,
+    /// Webhook for update lifecycle events, if enabled.
+    pub(crate) webhook: Option<WebhookSettings>,
 }
 
 impl Settings {
@@ -69,17 +85,42 @@ impl Settings {
         let allow_downgrade = cfg.updates.allow_downgrade;
         let enabled = cfg.updates.enabled;
         let steady_interval_secs = cfg.agent.steady_interval_secs;
+        let rollout_spread =
+            parse_optional_duration(&cfg.updates.rollout_spread, "updates.rollout_spread")?;
+        let min_interval_between_reboots = parse_optional_duration(
+            &cfg.updates.min_interval_between_reboots,
+            "updates.min_interval_between_reboots",
+        )?;
+        let honor_urgent = cfg.updates.honor_urgent;
+        let max_postponement =
+            parse_optional_duration(&cfg.updates.max_postponement, "updates.max_postponement")?;
         let identity = Identity::with_config(cfg.identity)?;
         let strategy = UpdateStrategy::with_config(cfg.updates, &identity)?;
         let cincinnati = Cincinnati::with_config(cfg.cincinnati, &identity)?;
+        let message_templates = MessageTemplates::with_config(cfg.notifications.templates)?;
+        let webhook = WebhookSettings::with_config(cfg.notifications.webhook)?;
 
         Ok(Self {
             allow_downgrade,
             enabled,
             steady_interval_secs,
+            rollout_spread,
+            min_interval_between_reboots,
+            honor_urgent,
+            max_postponement,
             cincinnati,
             identity,
             strategy,
+            message_templates,
+            webhook,
         })
     }
 }
+
+/// Parse an optional duration setting, where an empty value means zero.
+fn parse_optional_duration(input: &str, key: &str) -> Result<Duration> {
+    if input.is_empty() {
+        return Ok(Duration::from_secs(0));
+    }
+    utils::parse_duration(input).with_context(|| format!("failed to parse `{}`", key))
+}
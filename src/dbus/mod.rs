@@ -2,6 +2,10 @@
 
 mod experimental;
 use experimental::Experimental;
+mod strategy;
+use strategy::Strategy;
+mod updates;
+use updates::Updates;
 
 use crate::update_agent::UpdateAgent;
 use actix::prelude::*;
@@ -42,6 +46,18 @@ impl DBusService {
                     agent_addr: self.agent_addr.clone(),
                 },
             )?
+            .serve_at(
+                "/org/coreos/zincati",
+                Strategy {
+                    agent_addr: self.agent_addr.clone(),
+                },
+            )?
+            .serve_at(
+                "/org/coreos/zincati",
+                Updates {
+                    agent_addr: self.agent_addr.clone(),
+                },
+            )?
             .build()?;
 
         Ok(connection)
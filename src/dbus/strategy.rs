@@ -0,0 +1,47 @@
+//! Update strategy interface.
+
+use crate::update_agent::{StrategyWindow, UpdateAgent};
+use actix::Addr;
+use futures::prelude::*;
+use tokio::runtime::Runtime;
+use zbus::{fdo, interface};
+
+/// Interface exposing update strategy details.
+pub(crate) struct Strategy {
+    pub(crate) agent_addr: Addr<UpdateAgent>,
+}
+
+impl Strategy {
+    /// Query update_agent actor for strategy window details.
+    fn window_details(&self) -> fdo::Result<(i64, i64)> {
+        let msg = StrategyWindow {};
+        let window_fut = self.agent_addr.send(msg).map_err(|e| {
+            let err_msg = format!("failed to get strategy window from agent actor: {}", e);
+            log::error!("Strategy D-Bus property read: {}", err_msg);
+            fdo::Error::Failed(err_msg)
+        });
+
+        Runtime::new()
+            .map_err(|e| {
+                let err_msg = format!("failed to create runtime to execute future: {}", e);
+                log::error!("{}", err_msg);
+                fdo::Error::Failed(err_msg)
+            })
+            .and_then(|runtime| runtime.block_on(window_fut))
+    }
+}
+
+#[interface(name = "org.coreos.zincati.Strategy")]
+impl Strategy {
+    /// UTC timestamp of the next (or current) periodic window start, or zero.
+    #[zbus(property)]
+    fn next_window_timestamp(&self) -> fdo::Result<i64> {
+        self.window_details().map(|(next, _)| next)
+    }
+
+    /// Remaining minutes in the current periodic window, or zero.
+    #[zbus(property)]
+    fn window_remaining_minutes(&self) -> fdo::Result<i64> {
+        self.window_details().map(|(_, remaining)| remaining)
+    }
+}
@@ -0,0 +1,139 @@
+//! Updates control interface.
+
+use crate::update_agent::{DeployVersion, PostponeFinalization, UpdateAgent};
+use crate::utils;
+use actix::Addr;
+use futures::prelude::*;
+use std::collections::HashMap;
+use zbus::message::Header;
+use zbus::zvariant::Value;
+use zbus::{fdo, interface, proxy, Connection};
+
+/// Polkit action for postponing a pending reboot.
+static POSTPONE_ACTION_ID: &str = "org.coreos.zincati.postpone";
+
+/// Polkit `CheckAuthorization` flag, allowing interactive authentication.
+const POLKIT_ALLOW_USER_INTERACTION: u32 = 1;
+
+/// Interface for controlling auto-updates.
+pub(crate) struct Updates {
+    pub(crate) agent_addr: Addr<UpdateAgent>,
+}
+
+#[interface(name = "org.coreos.zincati.Updates")]
+impl Updates {
+    /// Deploy a specific release, by version.
+    ///
+    /// The release must be reachable from the booted deployment in the
+    /// current update graph, unless downgrades are allowed. Staging and
+    /// finalization then follow the usual update strategy.
+    async fn deploy_version(&self, version: String) -> fdo::Result<()> {
+        let msg = DeployVersion { version };
+        self.agent_addr
+            .send(msg)
+            .map_err(|e| format!("failed to send request to agent actor: {}", e))
+            .and_then(|res| future::ready(res.map_err(|e| format!("{:#}", e))))
+            .map_err(|err_msg| {
+                log::error!("DeployVersion D-Bus method call: {}", err_msg);
+                fdo::Error::Failed(err_msg)
+            })
+            .map_ok(|_| ())
+            .await
+    }
+
+    /// Postpone the pending reboot by the given duration (e.g. `30m`).
+    ///
+    /// The caller must be authorized through polkit. The overall postponement
+    /// is capped by configuration. Returns the time until which the reboot is
+    /// postponed, in RFC 3339 format.
+    async fn postpone(
+        &self,
+        duration: String,
+        reason: String,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &Connection,
+    ) -> fdo::Result<String> {
+        let duration = utils::parse_duration(&duration)
+            .map_err(|e| fdo::Error::InvalidArgs(format!("{:#}", e)))?;
+        let sender = header
+            .sender()
+            .ok_or_else(|| fdo::Error::Failed("missing sender".to_string()))?;
+
+        let uid = fdo::DBusProxy::new(connection)
+            .await?
+            .get_connection_unix_user(sender.clone().into())
+            .await?;
+        let user = users::get_user_by_uid(uid)
+            .map(|u| u.name().to_string_lossy().into_owned())
+            .unwrap_or_else(|| uid.to_string());
+
+        check_authorization(connection, sender.as_str(), POSTPONE_ACTION_ID)
+            .await
+            .map_err(|e| {
+                log::warn!("Postpone D-Bus method call by user '{}': {}", user, e);
+                e
+            })?;
+
+        let msg = PostponeFinalization {
+            duration,
+            user,
+            reason,
+        };
+        let postpone_fut = self
+            .agent_addr
+            .send(msg)
+            .map_err(|e| format!("failed to send request to agent actor: {}", e))
+            .and_then(|res| future::ready(res.map_err(|e| format!("{:#}", e))))
+            .map_err(|err_msg| {
+                log::error!("Postpone D-Bus method call: {}", err_msg);
+                fdo::Error::Failed(err_msg)
+            })
+            .map_ok(|until| until.to_rfc3339());
+
+        postpone_fut.await
+    }
+}
+
+/// Check through polkit whether the given D-Bus sender is authorized for an action.
+async fn check_authorization(
+    connection: &Connection,
+    sender: &str,
+    action_id: &str,
+) -> fdo::Result<()> {
+    let subject_details = HashMap::from([("name", Value::from(sender))]);
+    let (is_authorized, _, _) = PolkitAuthorityProxy::new(connection)
+        .await?
+        .check_authorization(
+            &("system-bus-name", subject_details),
+            action_id,
+            HashMap::new(),
+            POLKIT_ALLOW_USER_INTERACTION,
+            "",
+        )
+        .await?;
+
+    if !is_authorized {
+        return Err(fdo::Error::AccessDenied(format!(
+            "not authorized for '{}'",
+            action_id
+        )));
+    }
+    Ok(())
+}
+
+#[proxy(
+    interface = "org.freedesktop.PolicyKit1.Authority",
+    default_service = "org.freedesktop.PolicyKit1",
+    default_path = "/org/freedesktop/PolicyKit1/Authority"
+)]
+trait PolkitAuthority {
+    /// CheckAuthorization method
+    fn check_authorization(
+        &self,
+        subject: &(&str, HashMap<&str, Value<'_>>),
+        action_id: &str,
+        details: HashMap<&str, &str>,
+        flags: u32,
+        cancellation_id: &str,
+    ) -> zbus::Result<(bool, bool, HashMap<String, String>)>;
+}
@@ -2,22 +2,23 @@
 //!
 //! This module implements a client for FleetLock, a bare HTTP
 //! protocol for managing cluster-wide reboot via a remote
-//! lock manager. Protocol specification is available at
+//! lock manager, as well as a reference lock manager. Protocol
+//! specification is available at
 //! https://coreos.github.io/zincati/development/fleetlock/protocol/ .
 
+use crate::http_client::{self, HttpSettings};
 use crate::identity::Identity;
 use anyhow::{Context, Result};
 use futures::prelude::*;
 use reqwest::Method;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
 
 #[cfg(test)]
 mod mock_tests;
-
-/// Default timeout for HTTP requests completion (30 minutes).
-const DEFAULT_HTTP_COMPLETION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+pub(crate) mod server;
 
 /// FleetLock pre-reboot API path endpoint (v1).
 static V1_PRE_REBOOT: &str = "v1/pre-reboot";
@@ -25,6 +26,12 @@ static V1_PRE_REBOOT: &str = "v1/pre-reboot";
 /// FleetLock steady-state API path endpoint (v1).
 static V1_STEADY_STATE: &str = "v1/steady-state";
 
+/// Lease extension, request header asking for a lock lease.
+static LEASE_REQUEST_HEADER: &str = "fleet-lock-lease";
+
+/// Lease extension, response header carrying the lease TTL (in seconds).
+static LEASE_TTL_HEADER: &str = "fleet-lock-lease-ttl";
+
 /// FleetLock JSON protocol: service error.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct RemoteJsonError {
@@ -41,6 +48,8 @@ pub enum FleetLockError {
     Remote(reqwest::StatusCode, RemoteJsonError),
     /// Generic HTTP error.
     Http(reqwest::StatusCode),
+    /// Server is overloaded, and asked to retry after a delay.
+    Throttled(reqwest::StatusCode, Duration),
     /// Client builder failed.
     FailedClientBuilder(String),
     /// Client failed request.
@@ -53,6 +62,7 @@ impl FleetLockError {
         match *self {
             FleetLockError::Remote(_, ref err) => err.kind.clone(),
             FleetLockError::Http(status) => format!("generic_http_{}", status.as_u16()),
+            FleetLockError::Throttled(_, _) => "server_throttled".to_string(),
             FleetLockError::FailedClientBuilder(_) => "client_failed_build".to_string(),
             FleetLockError::FailedRequest(_) => "client_failed_request".to_string(),
         }
@@ -63,6 +73,7 @@ impl FleetLockError {
         match *self {
             FleetLockError::Remote(_, ref err) => err.value.clone(),
             FleetLockError::Http(_) => "(unknown/generic server error)".to_string(),
+            FleetLockError::Throttled(_, delay) => http_client::throttled_message(delay),
             FleetLockError::FailedClientBuilder(ref err)
             | FleetLockError::FailedRequest(ref err) => err.clone(),
         }
@@ -71,10 +82,34 @@ impl FleetLockError {
     /// Return the server-side error status code, if any.
     pub fn status_code(&self) -> Option<u16> {
         match *self {
-            FleetLockError::Remote(s, _) | FleetLockError::Http(s) => Some(s.as_u16()),
+            FleetLockError::Remote(s, _)
+            | FleetLockError::Http(s)
+            | FleetLockError::Throttled(s, _) => Some(s.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// Return the retry delay requested by the server, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match *self {
+            FleetLockError::Throttled(_, delay) => Some(delay),
             _ => None,
         }
     }
+
+    /// Return whether the remote manager could not serve the request.
+    ///
+    /// This covers connection failures and server-side (5xx) errors, but not
+    /// semantic rejections (e.g. a full semaphore).
+    /// Throttling servers are considered unavailable too.
+    pub fn is_unavailable(&self) -> bool {
+        match *self {
+            FleetLockError::FailedRequest(_) => true,
+            FleetLockError::Remote(s, _) | FleetLockError::Http(s) => s.is_server_error(),
+            FleetLockError::Throttled(_, _) => true,
+            FleetLockError::FailedClientBuilder(_) => false,
+        }
+    }
 }
 
 impl std::fmt::Display for FleetLockError {
@@ -97,18 +132,34 @@ pub struct Client {
     /// Asynchronous reqwest client.
     #[serde(skip)]
     hclient: reqwest::Client,
-    /// Request body.
-    body: String,
+    /// HTTP client settings, for credentials.
+    #[serde(skip)]
+    http: Arc<HttpSettings>,
+    /// Client identity and details, for requests body.
+    client_identity: ClientIdentity,
+    /// Whether to request lock leases.
+    leases: bool,
 }
 
 impl Client {
-    /// Try to lock a semaphore slot on the remote manager.
+    /// Try to lock (or renew the lease of) a semaphore slot on the remote manager.
     ///
-    /// It returns `true` if the operation succeeds, or a `FleetLockError`
-    /// with the relevant error explanation.
-    pub fn pre_reboot(&self) -> impl Future<Output = Result<bool, FleetLockError>> {
+    /// On success, it returns the TTL of the granted lease if lock leases are
+    /// enabled and supported by the server. Servers without lease support (i.e.
+    /// plain v1) grant non-expiring locks, for which `None` is returned.
+    /// On failure, it returns a `FleetLockError` with the relevant error explanation.
+    pub fn pre_reboot(
+        &self,
+        target_os_version: Option<&str>,
+        reason: RequestReason,
+    ) -> impl Future<Output = Result<Option<Duration>, FleetLockError>> {
+        let leases = self.leases;
         let req = self
-            .new_request(Method::POST, V1_PRE_REBOOT)
+            .new_request(Method::POST, V1_PRE_REBOOT, target_os_version, reason)
+            .map(|req| match leases {
+                true => req.header(LEASE_REQUEST_HEADER, "true"),
+                false => req,
+            })
             .map_err(|e| FleetLockError::FailedClientBuilder(e.to_string()));
 
         futures::future::ready(req)
@@ -116,16 +167,26 @@ impl Client {
                 req.send()
                     .map_err(|e| FleetLockError::FailedRequest(e.to_string()))
             })
-            .and_then(Self::map_response)
+            .and_then(move |response| async move {
+                let ttl = if leases {
+                    Self::lease_ttl(&response)
+                } else {
+                    None
+                };
+                Self::map_response(response).await.map(|_| ttl)
+            })
     }
 
     /// Try to unlock a semaphore slot on the remote manager.
     ///
     /// It returns `true` if the operation succeeds, or a `FleetLockError`
     /// with the relevant error explanation.
-    pub fn steady_state(&self) -> impl Future<Output = Result<bool, FleetLockError>> {
+    pub fn steady_state(
+        &self,
+        reason: RequestReason,
+    ) -> impl Future<Output = Result<bool, FleetLockError>> {
         let req = self
-            .new_request(Method::POST, V1_STEADY_STATE)
+            .new_request(Method::POST, V1_STEADY_STATE, None, reason)
             .map_err(|e| FleetLockError::FailedClientBuilder(e.to_string()));
 
         futures::future::ready(req)
@@ -136,19 +197,69 @@ impl Client {
             .and_then(Self::map_response)
     }
 
+    /// Return a copy of this client, identifying as another node and/or group.
+    pub(crate) fn for_node(&self, id: Option<&str>, group: Option<&str>) -> Result<Self> {
+        let mut client = self.clone();
+        let params = &mut client.client_identity.client_params;
+        if let Some(id) = id {
+            if id.is_empty() {
+                anyhow::bail!("missing node ID value");
+            }
+            params.id = id.to_string();
+        }
+        if let Some(group) = group {
+            if group.is_empty() {
+                anyhow::bail!("missing group value");
+            }
+            params.group = group.to_string();
+        }
+        Ok(client)
+    }
+
     /// Return a request builder for the target URL, with proper parameters set.
     fn new_request<S: AsRef<str>>(
         &self,
         method: reqwest::Method,
         url_suffix: S,
+        target_os_version: Option<&str>,
+        reason: RequestReason,
     ) -> Result<reqwest::RequestBuilder> {
         let url = self.api_base.clone().join(url_suffix.as_ref())?;
         let builder = self
             .hclient
             .request(method, url)
-            .body(self.body.clone())
+            .body(self.request_body(target_os_version, reason)?)
             .header("fleet-lock-protocol", "true");
-        Ok(builder)
+        self.http.authorize(builder)
+    }
+
+    /// Return the JSON request body, with request-specific client details.
+    fn request_body(
+        &self,
+        target_os_version: Option<&str>,
+        reason: RequestReason,
+    ) -> Result<String> {
+        let mut client_identity = self.client_identity.clone();
+        client_identity.client_info.target_os_version = target_os_version.map(String::from);
+        client_identity.client_info.reason = Some(reason);
+        let body = serde_json::to_string_pretty(&client_identity)?;
+        Ok(body)
+    }
+
+    /// Return the lease TTL advertised in a response, if any.
+    fn lease_ttl(response: &reqwest::Response) -> Option<Duration> {
+        let value = response.headers().get(LEASE_TTL_HEADER)?;
+        match value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+        {
+            Some(secs) if secs > 0 => Some(Duration::from_secs(secs)),
+            _ => {
+                log::warn!("ignoring invalid lease TTL from server: {:?}", value);
+                None
+            }
+        }
     }
 
     /// Map an HTTP response to a service result.
@@ -159,6 +270,11 @@ impl Client {
             return Ok(true);
         }
 
+        // On throttling, surface the requested retry delay.
+        if let Some(delay) = http_client::throttle_delay(&response) {
+            return Err(FleetLockError::Throttled(status, delay));
+        }
+
         // On error, decode failure details (or synthesize a generic error).
         match response.json::<RemoteJsonError>().await {
             Ok(rej) => Err(FleetLockError::Remote(status, rej)),
@@ -174,14 +290,20 @@ pub struct ClientBuilder {
     api_base: String,
     /// Asynchronous reqwest client (custom).
     hclient: Option<reqwest::Client>,
+    /// HTTP client settings.
+    http: HttpSettings,
     /// Client identity.
     client_identity: ClientIdentity,
+    /// Whether to request lock leases.
+    leases: bool,
 }
 
 /// Client identity, for requests body.
 #[derive(Clone, Debug, Serialize)]
 pub struct ClientIdentity {
     client_params: ClientParameters,
+    /// Additional details, which servers may ignore.
+    client_info: ClientInfo,
 }
 
 /// Client parameters.
@@ -193,6 +315,43 @@ pub struct ClientParameters {
     group: String,
 }
 
+/// Client details, for lock managers to prioritize and display lock holders.
+#[derive(Clone, Debug, Serialize)]
+pub struct ClientInfo {
+    /// Booted OS version.
+    current_os_version: String,
+    /// OS version of the update, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_os_version: Option<String>,
+    /// Update stream.
+    stream: String,
+    /// OS platform.
+    platform: String,
+    /// Reason for the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<RequestReason>,
+}
+
+/// Reason for a request, as reported to the lock manager.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestReason {
+    /// Locking a slot to finalize an update.
+    Finalization,
+    /// Locking a slot to finalize an urgent update.
+    UrgentFinalization,
+    /// Renewing the lease on a held slot.
+    LeaseRenewal,
+    /// Unlocking a slot after reaching steady state.
+    SteadyState,
+    /// Locking or unlocking a slot on manual request.
+    Manual,
+    /// Unlocking a slot after repeatedly failing to finalize an update.
+    FailedFinalization,
+    /// Unlocking a slot on agent shutdown, with no reboot in progress.
+    Shutdown,
+}
+
 impl ClientBuilder {
     /// Return a new client builder for the given base API endpoint URL.
     pub(crate) fn new<T>(api_base: T, identity: &Identity) -> Self
@@ -202,15 +361,38 @@ impl ClientBuilder {
         Self {
             api_base: api_base.into(),
             hclient: None,
+            http: HttpSettings::default(),
             client_identity: ClientIdentity {
                 client_params: ClientParameters {
                     id: identity.node_uuid.lower_hex(),
                     group: identity.group.clone(),
                 },
+                client_info: ClientInfo {
+                    current_os_version: identity.current_os.version.clone(),
+                    target_os_version: None,
+                    stream: identity.stream.clone(),
+                    platform: identity.platform.clone(),
+                    reason: None,
+                },
             },
+            leases: false,
         }
     }
 
+    /// Set whether to request lock leases from the server.
+    pub fn leases(self, leases: bool) -> Self {
+        let mut builder = self;
+        builder.leases = leases;
+        builder
+    }
+
+    /// Set the HTTP client settings to use.
+    pub(crate) fn http_settings(self, http: HttpSettings) -> Self {
+        let mut builder = self;
+        builder.http = http;
+        builder
+    }
+
     /// Set (or reset) the HTTP client to use.
     #[allow(dead_code)]
     pub fn http_client(self, hclient: Option<reqwest::Client>) -> Self {
@@ -223,9 +405,7 @@ impl ClientBuilder {
     pub fn build(self) -> Result<Client> {
         let hclient = match self.hclient {
             Some(client) => client,
-            None => reqwest::ClientBuilder::new()
-                .timeout(DEFAULT_HTTP_COMPLETION_TIMEOUT)
-                .build()?,
+            None => self.http.build_client()?,
         };
 
         let api_base = reqwest::Url::parse(&self.api_base)
@@ -233,11 +413,12 @@ impl ClientBuilder {
         if self.client_identity.client_params.group.is_empty() {
             anyhow::bail!("missing group value");
         }
-        let body = serde_json::to_string_pretty(&self.client_identity)?;
         let client = Client {
             api_base,
             hclient,
-            body,
+            http: Arc::new(self.http),
+            client_identity: self.client_identity,
+            leases: self.leases,
         };
         Ok(client)
     }
@@ -289,4 +470,25 @@ mod tests {
         let expected_msg = "server-side error, code 433: (unknown/generic server error)";
         assert_eq!(&msg, expected_msg);
     }
+
+    #[test]
+    fn test_throttled_error() {
+        let runtime = rt::Runtime::new().unwrap();
+        let response = Response::builder()
+            .status(429)
+            .header("retry-after", "120")
+            .body("")
+            .unwrap();
+        let fut_rejection = Client::map_response(response.into());
+        let rejection = runtime.block_on(fut_rejection).unwrap_err();
+        let expected_rejection =
+            FleetLockError::Throttled(StatusCode::TOO_MANY_REQUESTS, Duration::from_secs(120));
+        assert_eq!(&rejection, &expected_rejection);
+        assert_eq!(rejection.retry_after(), Some(Duration::from_secs(120)));
+
+        assert_eq!(rejection.error_kind(), "server_throttled");
+        assert!(rejection
+            .to_string()
+            .starts_with("server-side error, code 429: "));
+    }
 }
@@ -0,0 +1,717 @@
+//! Reference FleetLock server.
+//!
+//! This implements the FleetLock protocol endpoints on top of a counting
+//! semaphore per group, persisted to a JSON state file. Locks are owned and
+//! recursive, and expiring leases are granted to clients asking for them.
+//! Additional admin endpoints allow listing and force-releasing locks.
+
+use super::{
+    RemoteJsonError, LEASE_REQUEST_HEADER, LEASE_TTL_HEADER, V1_PRE_REBOOT, V1_STEADY_STATE,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Admin API path endpoint for locks (v1).
+static ADMIN_V1_LOCKS: &str = "admin/v1/locks";
+
+/// Path prefix of single locks, as `<group>/<id>` (admin API).
+static ADMIN_V1_LOCK_PREFIX: &str = "admin/v1/locks/";
+
+/// Maximum size of request bodies (64 KiB).
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+lazy_static! {
+    static ref VALID_GROUP_REGEX: Regex = Regex::new("^[a-zA-Z0-9.-]+$").unwrap();
+}
+
+/// Lock server settings.
+#[derive(Clone, Debug)]
+pub(crate) struct ServerSettings {
+    /// Path to the JSON file holding locks state.
+    pub(crate) state_file: PathBuf,
+    /// Number of reboot slots for groups without specific settings.
+    pub(crate) default_slots: u64,
+    /// Number of reboot slots for specific groups.
+    pub(crate) group_slots: BTreeMap<String, u64>,
+    /// TTL of granted leases, if leases are enabled.
+    pub(crate) lease_ttl: Option<Duration>,
+    /// Path to a file containing the bearer token for admin endpoints, if enabled.
+    pub(crate) admin_token_file: Option<PathBuf>,
+}
+
+impl ServerSettings {
+    /// Return the number of reboot slots for a group.
+    fn slots(&self, group: &str) -> u64 {
+        self.group_slots
+            .get(group)
+            .copied()
+            .unwrap_or(self.default_slots)
+    }
+}
+
+/// Persisted locks state, by group and client ID.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct LocksState {
+    groups: BTreeMap<String, BTreeMap<String, LockHolder>>,
+}
+
+/// Holder of a reboot slot.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+struct LockHolder {
+    /// When the slot was locked.
+    locked_at: DateTime<Utc>,
+    /// When the lease on the slot expires, for leased locks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lease_expires: Option<DateTime<Utc>>,
+    /// Client details, from the latest lock request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_info: Option<serde_json::Value>,
+}
+
+/// Lock or unlock request body.
+#[derive(Debug, Deserialize)]
+struct LockRequest {
+    client_params: LockClientParams,
+    client_info: Option<serde_json::Value>,
+}
+
+/// Client parameters, for lock ownership.
+#[derive(Debug, Deserialize)]
+struct LockClientParams {
+    id: String,
+    group: String,
+}
+
+/// Admin view of a group semaphore.
+#[derive(Debug, Deserialize, Serialize)]
+struct GroupLocks {
+    group: String,
+    slots: u64,
+    holders: BTreeMap<String, LockHolder>,
+}
+
+/// Request failure, returned to clients as a JSON error.
+#[derive(Debug)]
+struct Rejection {
+    status: StatusCode,
+    error: RemoteJsonError,
+}
+
+impl Rejection {
+    fn new(status: StatusCode, kind: &str, value: impl Into<String>) -> Self {
+        Self {
+            status,
+            error: RemoteJsonError {
+                kind: kind.to_string(),
+                value: value.into(),
+            },
+        }
+    }
+
+    fn into_response(self) -> Response<String> {
+        let body = serde_json::to_string(&self.error).unwrap_or_default();
+        json_response(self.status, body)
+    }
+}
+
+/// FleetLock server, holding semaphores for all groups.
+#[derive(Debug)]
+pub(crate) struct LockServer {
+    settings: ServerSettings,
+    state: LocksState,
+}
+
+impl LockServer {
+    /// Create a lock server, loading previous state (if any) from the state file.
+    pub(crate) fn load(settings: ServerSettings) -> Result<Self> {
+        let path = &settings.state_file;
+        let state = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("failed to parse state file '{}'", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => LocksState::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read state file '{}'", path.display()))
+            }
+        };
+        Ok(Self { settings, state })
+    }
+
+    /// Handle a request, returning the response to send.
+    pub(crate) fn handle(&mut self, req: Request<Vec<u8>>, now: DateTime<Utc>) -> Response<String> {
+        let path = req.uri().path().trim_matches('/').to_string();
+        let admin_lock = path.strip_prefix(ADMIN_V1_LOCK_PREFIX);
+        let result = match (req.method(), path.as_str(), admin_lock) {
+            (&Method::POST, p, _) if p == V1_PRE_REBOOT => self.pre_reboot(&req, now),
+            (&Method::POST, p, _) if p == V1_STEADY_STATE => self.steady_state(&req),
+            (&Method::GET, p, _) if p == ADMIN_V1_LOCKS => self.admin_list(&req, now),
+            (&Method::DELETE, _, Some(lock)) => self.admin_release(&req, lock),
+            (_, p, _) if p == V1_PRE_REBOOT || p == V1_STEADY_STATE || p == ADMIN_V1_LOCKS => {
+                Err(Rejection::new(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "method_not_allowed",
+                    "method not allowed",
+                ))
+            }
+            _ => Err(Rejection::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                format!("unknown endpoint '/{}'", path),
+            )),
+        };
+        result.unwrap_or_else(Rejection::into_response)
+    }
+
+    /// Try to lock a reboot slot, or renew the lease on a held one.
+    fn pre_reboot(
+        &mut self,
+        req: &Request<Vec<u8>>,
+        now: DateTime<Utc>,
+    ) -> Result<Response<String>, Rejection> {
+        let body = Self::parse_request(req)?;
+        let (id, group) = (body.client_params.id, body.client_params.group);
+        let lease_ttl = match req.headers().get(LEASE_REQUEST_HEADER) {
+            Some(v) if v == "true" => self.settings.lease_ttl,
+            _ => None,
+        };
+        let slots = self.settings.slots(&group);
+
+        let previous = self.state.clone();
+        self.expire_leases(now);
+        let holders = self.state.groups.entry(group.clone()).or_default();
+        let locked = holders.len() as u64;
+        let lease_expires = lease_ttl.map(|ttl| now + chrono::Duration::from_std(ttl).unwrap());
+        match holders.get_mut(&id) {
+            // Locking is recursive, and renews the lease (if requested).
+            Some(holder) => {
+                if lease_expires.is_some() {
+                    holder.lease_expires = lease_expires;
+                }
+                holder.client_info = body.client_info;
+            }
+            None if locked < slots => {
+                log::info!("group '{}': locked reboot slot for '{}'", group, id);
+                let holder = LockHolder {
+                    locked_at: now,
+                    lease_expires,
+                    client_info: body.client_info,
+                };
+                holders.insert(id, holder);
+            }
+            None => {
+                self.state = previous;
+                return Err(Rejection::new(
+                    StatusCode::CONFLICT,
+                    "failed_lock_semaphore_full",
+                    format!(
+                        "semaphore currently full, all {} slots are locked already",
+                        slots
+                    ),
+                ));
+            }
+        }
+        self.persist_or_rollback(previous)?;
+
+        let mut response = json_response(StatusCode::OK, String::new());
+        if let Some(ttl) = lease_ttl {
+            response
+                .headers_mut()
+                .insert(LEASE_TTL_HEADER, HeaderValue::from(ttl.as_secs()));
+        }
+        Ok(response)
+    }
+
+    /// Release a reboot slot, if held by the client.
+    fn steady_state(&mut self, req: &Request<Vec<u8>>) -> Result<Response<String>, Rejection> {
+        let body = Self::parse_request(req)?;
+        let (id, group) = (body.client_params.id, body.client_params.group);
+
+        let previous = self.state.clone();
+        if self.release(&group, &id) {
+            log::info!("group '{}': unlocked reboot slot for '{}'", group, id);
+            self.persist_or_rollback(previous)?;
+        }
+        Ok(json_response(StatusCode::OK, String::new()))
+    }
+
+    /// List locks for all groups (admin).
+    fn admin_list(
+        &mut self,
+        req: &Request<Vec<u8>>,
+        now: DateTime<Utc>,
+    ) -> Result<Response<String>, Rejection> {
+        self.authorize_admin(req)?;
+
+        let previous = self.state.clone();
+        if self.expire_leases(now) {
+            self.persist_or_rollback(previous)?;
+        }
+        let locks: Vec<GroupLocks> = self
+            .state
+            .groups
+            .iter()
+            .map(|(group, holders)| GroupLocks {
+                group: group.clone(),
+                slots: self.settings.slots(group),
+                holders: holders.clone(),
+            })
+            .collect();
+        let body = serde_json::to_string_pretty(&locks).unwrap_or_default();
+        Ok(json_response(StatusCode::OK, body))
+    }
+
+    /// Force-release a lock, in `<group>/<id>` format (admin).
+    fn admin_release(
+        &mut self,
+        req: &Request<Vec<u8>>,
+        lock: &str,
+    ) -> Result<Response<String>, Rejection> {
+        self.authorize_admin(req)?;
+
+        let (group, id) = lock.split_once('/').ok_or_else(|| {
+            Rejection::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                format!("invalid lock '{}', expected '<group>/<id>'", lock),
+            )
+        })?;
+        let previous = self.state.clone();
+        if !self.release(group, id) {
+            return Err(Rejection::new(
+                StatusCode::NOT_FOUND,
+                "lock_not_found",
+                format!("no lock held by '{}' in group '{}'", id, group),
+            ));
+        }
+        log::warn!("group '{}': force-released reboot slot of '{}'", group, id);
+        self.persist_or_rollback(previous)?;
+        Ok(json_response(StatusCode::OK, String::new()))
+    }
+
+    /// Parse and validate a lock or unlock request.
+    fn parse_request(req: &Request<Vec<u8>>) -> Result<LockRequest, Rejection> {
+        match req.headers().get("fleet-lock-protocol") {
+            Some(v) if v == "true" => {}
+            _ => {
+                return Err(Rejection::new(
+                    StatusCode::BAD_REQUEST,
+                    "missing_protocol_header",
+                    "missing `fleet-lock-protocol: true` header",
+                ))
+            }
+        }
+
+        let body: LockRequest = serde_json::from_slice(req.body()).map_err(|e| {
+            Rejection::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_body",
+                format!("failed to parse request body: {}", e),
+            )
+        })?;
+        if body.client_params.id.is_empty() {
+            return Err(Rejection::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_client_params",
+                "empty client ID",
+            ));
+        }
+        if !VALID_GROUP_REGEX.is_match(&body.client_params.group) {
+            return Err(Rejection::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_client_params",
+                format!("invalid group '{}'", body.client_params.group),
+            ));
+        }
+        Ok(body)
+    }
+
+    /// Check admin credentials.
+    fn authorize_admin(&self, req: &Request<Vec<u8>>) -> Result<(), Rejection> {
+        // Admin endpoints are hidden unless enabled.
+        let path = self.settings.admin_token_file.as_ref().ok_or_else(|| {
+            Rejection::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                format!("unknown endpoint '{}'", req.uri().path()),
+            )
+        })?;
+        // Read the token on each request, so that it can be rotated.
+        let token = std::fs::read_to_string(path).map_err(|e| {
+            log::error!(
+                "failed to read admin token file '{}': {}",
+                path.display(),
+                e
+            );
+            Rejection::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed_admin_token",
+                "failed to read admin token",
+            )
+        })?;
+        let expected = format!("Bearer {}", token.trim());
+        match req.headers().get(AUTHORIZATION) {
+            Some(v) if !token.trim().is_empty() && v == expected.as_str() => Ok(()),
+            _ => Err(Rejection::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "missing or invalid admin token",
+            )),
+        }
+    }
+
+    /// Release the slot held by a client, returning whether it was held.
+    fn release(&mut self, group: &str, id: &str) -> bool {
+        let holders = match self.state.groups.get_mut(group) {
+            Some(h) => h,
+            None => return false,
+        };
+        let released = holders.remove(id).is_some();
+        if holders.is_empty() {
+            self.state.groups.remove(group);
+        }
+        released
+    }
+
+    /// Release slots with expired leases, returning whether any was released.
+    fn expire_leases(&mut self, now: DateTime<Utc>) -> bool {
+        let mut expired = false;
+        for (group, holders) in self.state.groups.iter_mut() {
+            holders.retain(|id, holder| match holder.lease_expires {
+                Some(expires) if expires <= now => {
+                    log::warn!("group '{}': lease of '{}' expired, unlocking", group, id);
+                    expired = true;
+                    false
+                }
+                _ => true,
+            });
+        }
+        self.state.groups.retain(|_, holders| !holders.is_empty());
+        expired
+    }
+
+    /// Persist current state, restoring `previous` state on failure.
+    fn persist_or_rollback(&mut self, previous: LocksState) -> Result<(), Rejection> {
+        if let Err(e) = self.persist() {
+            log::error!("{:#}", e);
+            self.state = previous;
+            return Err(Rejection::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed_state_persist",
+                "failed to persist locks state",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Atomically write current state to the state file.
+    fn persist(&self) -> Result<()> {
+        let path = &self.settings.state_file;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut f = tempfile::Builder::new()
+            .prefix(".fleetlock.")
+            .suffix(".partial")
+            .tempfile_in(dir)
+            .with_context(|| {
+                format!("failed to create temporary file under '{}'", dir.display())
+            })?;
+        serde_json::to_writer_pretty(&mut f, &self.state)?;
+        f.flush()?;
+        f.persist(path)
+            .with_context(|| format!("failed to persist state file '{}'", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Build a response with a JSON (or empty) body.
+fn json_response(status: StatusCode, body: String) -> Response<String> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+/// Serve FleetLock requests on `listener`, till failure.
+pub(crate) async fn serve(listener: TcpListener, server: LockServer) -> Result<()> {
+    let server = Arc::new(Mutex::new(server));
+    loop {
+        let (stream, peer) = listener
+            .accept()
+            .await
+            .context("failed to accept connection")?;
+        let server = Arc::clone(&server);
+        tokio::spawn(async move {
+            let service = service_fn(move |req| handle_request(Arc::clone(&server), req));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log::debug!("failed to serve connection from {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Handle an incoming HTTP request.
+async fn handle_request(
+    server: Arc<Mutex<LockServer>>,
+    req: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let (parts, body) = req.into_parts();
+    let response = match Limited::new(body, MAX_BODY_SIZE).collect().await {
+        Ok(body) => {
+            let req = Request::from_parts(parts, body.to_bytes().to_vec());
+            server.lock().unwrap().handle(req, Utc::now())
+        }
+        Err(e) => Rejection::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_body",
+            format!("failed to read request body: {}", e),
+        )
+        .into_response(),
+    };
+    Ok(response.map(|body| Full::new(Bytes::from(body))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet_lock::{ClientBuilder, RequestReason};
+    use crate::identity::Identity;
+
+    fn settings(dir: &Path) -> ServerSettings {
+        ServerSettings {
+            state_file: dir.join("state.json"),
+            default_slots: 1,
+            group_slots: BTreeMap::from([("workers".to_string(), 2)]),
+            lease_ttl: Some(Duration::from_secs(600)),
+            admin_token_file: None,
+        }
+    }
+
+    fn request(method: Method, path: &str, body: &str) -> Request<Vec<u8>> {
+        Request::builder()
+            .method(method)
+            .uri(path)
+            .header("fleet-lock-protocol", "true")
+            .body(body.as_bytes().to_vec())
+            .unwrap()
+    }
+
+    fn body(id: &str, group: &str) -> String {
+        format!(
+            r#"{{ "client_params": {{ "id": "{}", "group": "{}" }} }}"#,
+            id, group
+        )
+    }
+
+    #[test]
+    fn test_semaphore() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let mut server = LockServer::load(settings(tmpdir.path())).unwrap();
+        let now = Utc::now();
+
+        // Locking is recursive.
+        for _ in 0..2 {
+            let req = request(Method::POST, "/v1/pre-reboot", &body("a", "default"));
+            let resp = server.handle(req, now);
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert!(resp.headers().get(LEASE_TTL_HEADER).is_none());
+        }
+
+        // Semaphore is full.
+        let req = request(Method::POST, "/v1/pre-reboot", &body("b", "default"));
+        let resp = server.handle(req, now);
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let err: RemoteJsonError = serde_json::from_str(resp.body()).unwrap();
+        assert_eq!(err.kind, "failed_lock_semaphore_full");
+
+        // Groups have separate semaphores.
+        for id in ["b", "c"] {
+            let req = request(Method::POST, "/v1/pre-reboot", &body(id, "workers"));
+            assert_eq!(server.handle(req, now).status(), StatusCode::OK);
+        }
+
+        // Locks are owned, unlocking is recursive.
+        let req = request(Method::POST, "/v1/steady-state", &body("b", "default"));
+        assert_eq!(server.handle(req, now).status(), StatusCode::OK);
+        let req = request(Method::POST, "/v1/pre-reboot", &body("d", "default"));
+        assert_eq!(server.handle(req, now).status(), StatusCode::CONFLICT);
+        for _ in 0..2 {
+            let req = request(Method::POST, "/v1/steady-state", &body("a", "default"));
+            assert_eq!(server.handle(req, now).status(), StatusCode::OK);
+        }
+        let req = request(Method::POST, "/v1/pre-reboot", &body("d", "default"));
+        assert_eq!(server.handle(req, now).status(), StatusCode::OK);
+
+        // State is persisted.
+        let server = LockServer::load(settings(tmpdir.path())).unwrap();
+        assert!(server.state.groups["default"].contains_key("d"));
+        assert_eq!(server.state.groups["workers"].len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let mut server = LockServer::load(settings(tmpdir.path())).unwrap();
+        let now = Utc::now();
+
+        let mut req = request(Method::POST, "/v1/pre-reboot", &body("a", "default"));
+        req.headers_mut().remove("fleet-lock-protocol");
+        assert_eq!(server.handle(req, now).status(), StatusCode::BAD_REQUEST);
+
+        let req = request(Method::POST, "/v1/pre-reboot", &body("", "default"));
+        assert_eq!(server.handle(req, now).status(), StatusCode::BAD_REQUEST);
+        let req = request(Method::POST, "/v1/pre-reboot", &body("a", "def/ault"));
+        assert_eq!(server.handle(req, now).status(), StatusCode::BAD_REQUEST);
+        let req = request(Method::POST, "/v1/pre-reboot", "{}");
+        assert_eq!(server.handle(req, now).status(), StatusCode::BAD_REQUEST);
+
+        let req = request(Method::GET, "/v1/pre-reboot", "");
+        assert_eq!(
+            server.handle(req, now).status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        let req = request(Method::GET, "/v2/pre-reboot", "");
+        assert_eq!(server.handle(req, now).status(), StatusCode::NOT_FOUND);
+
+        // Admin endpoints are disabled by default.
+        let req = request(Method::GET, "/admin/v1/locks", "");
+        assert_eq!(server.handle(req, now).status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_leases() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let mut server = LockServer::load(settings(tmpdir.path())).unwrap();
+        let now = Utc::now();
+
+        let mut req = request(Method::POST, "/v1/pre-reboot", &body("a", "default"));
+        req.headers_mut()
+            .insert(LEASE_REQUEST_HEADER, HeaderValue::from_static("true"));
+        let resp = server.handle(req, now);
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[LEASE_TTL_HEADER], "600");
+
+        // Locking again without requesting a lease keeps the current one.
+        let later = now + chrono::Duration::seconds(300);
+        let req = request(Method::POST, "/v1/pre-reboot", &body("a", "default"));
+        let resp = server.handle(req, later);
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!resp.headers().contains_key(LEASE_TTL_HEADER));
+
+        // Slot is locked till the lease expires.
+        let later = now + chrono::Duration::seconds(599);
+        let req = request(Method::POST, "/v1/pre-reboot", &body("b", "default"));
+        assert_eq!(server.handle(req, later).status(), StatusCode::CONFLICT);
+        let later = now + chrono::Duration::seconds(600);
+        let req = request(Method::POST, "/v1/pre-reboot", &body("b", "default"));
+        assert_eq!(server.handle(req, later).status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_admin() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let token_path = tmpdir.path().join("admin-token");
+        std::fs::write(&token_path, "s3cr3t\n").unwrap();
+        let mut settings = settings(tmpdir.path());
+        settings.admin_token_file = Some(token_path);
+        let mut server = LockServer::load(settings).unwrap();
+        let now = Utc::now();
+
+        let lock = r#"{
+  "client_params": { "id": "a", "group": "workers" },
+  "client_info": { "target_os_version": "v2", "reason": "finalization" }
+}"#;
+        let req = request(Method::POST, "/v1/pre-reboot", lock);
+        assert_eq!(server.handle(req, now).status(), StatusCode::OK);
+
+        let req = request(Method::GET, "/admin/v1/locks", "");
+        assert_eq!(server.handle(req, now).status(), StatusCode::UNAUTHORIZED);
+
+        let mut req = request(Method::GET, "/admin/v1/locks", "");
+        req.headers_mut()
+            .insert(AUTHORIZATION, HeaderValue::from_static("Bearer s3cr3t"));
+        let resp = server.handle(req, now);
+        assert_eq!(resp.status(), StatusCode::OK);
+        let locks: Vec<GroupLocks> = serde_json::from_str(resp.body()).unwrap();
+        assert_eq!(locks.len(), 1);
+        assert_eq!(locks[0].group, "workers");
+        assert_eq!(locks[0].slots, 2);
+        let info = locks[0].holders["a"].client_info.as_ref().unwrap();
+        assert_eq!(info["target_os_version"], "v2");
+
+        for (path, status) in [
+            ("/admin/v1/locksworkers/a", StatusCode::NOT_FOUND),
+            ("/admin/v1/locks", StatusCode::METHOD_NOT_ALLOWED),
+            ("/admin/v1/locks/workers/b", StatusCode::NOT_FOUND),
+            ("/admin/v1/locks/workers/a", StatusCode::OK),
+            ("/admin/v1/locks/workers/a", StatusCode::NOT_FOUND),
+        ] {
+            let mut req = request(Method::DELETE, path, "");
+            req.headers_mut()
+                .insert(AUTHORIZATION, HeaderValue::from_static("Bearer s3cr3t"));
+            assert_eq!(server.handle(req, now).status(), status);
+        }
+        assert!(server.state.groups.is_empty());
+    }
+
+    #[test]
+    fn test_client_roundtrip() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let server = LockServer::load(settings(tmpdir.path())).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        runtime.spawn(serve(listener, server));
+
+        let id = Identity::mock_default();
+        let client = ClientBuilder::new(&base_url, &id)
+            .leases(true)
+            .build()
+            .unwrap();
+        let lease = runtime
+            .block_on(client.pre_reboot(Some("v2"), RequestReason::Finalization))
+            .unwrap();
+        assert_eq!(lease, Some(Duration::from_secs(600)));
+
+        let mut other = Identity::mock_default();
+        other.node_uuid =
+            libsystemd::id128::Id128::parse_str("27e3ac02af3946af995c9940e18b0cce").unwrap();
+        let other_client = ClientBuilder::new(&base_url, &other).build().unwrap();
+        let err = runtime
+            .block_on(other_client.pre_reboot(Some("v2"), RequestReason::Finalization))
+            .unwrap_err();
+        assert_eq!(err.error_kind(), "failed_lock_semaphore_full");
+        assert!(!err.is_unavailable());
+
+        let unlocked = runtime
+            .block_on(client.steady_state(RequestReason::SteadyState))
+            .unwrap();
+        assert!(unlocked);
+        runtime
+            .block_on(other_client.pre_reboot(Some("v2"), RequestReason::Finalization))
+            .unwrap();
+    }
+}
@@ -0,0 +1,533 @@
+//! HTTP client settings and retry backoff, shared by the Cincinnati and FleetLock clients.
+
+use crate::config::inputs;
+use crate::utils;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use fn_error_context::context;
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Default timeout for HTTP requests completion (30 minutes).
+const DEFAULT_HTTP_COMPLETION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Retry delay after a first failure, doubling on each consecutive one (5 minutes).
+const BACKOFF_INITIAL_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Maximum retry delay, before jitter (1 hour).
+const BACKOFF_MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Validated HTTP client settings.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct HttpSettings {
+    /// Path to a PEM bundle of additional trusted CA certificates.
+    ca_bundle: Option<String>,
+    /// Client certificate and key, for mutual TLS.
+    client_tls: Option<ClientTls>,
+    /// Credentials for the remote service.
+    auth: Option<HttpAuth>,
+    /// Timeout for connection establishment.
+    connect_timeout: Option<Duration>,
+    /// Timeout for requests completion.
+    request_timeout: Duration,
+    /// Explicit proxy for all requests.
+    proxy: Option<ProxySettings>,
+}
+
+/// Client certificate and private key paths, in PEM format.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+struct ClientTls {
+    cert: String,
+    key: String,
+}
+
+/// Credentials for the remote service, read from files on each request.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+enum HttpAuth {
+    /// Bearer token authentication.
+    Bearer { token_file: String },
+    /// Basic authentication.
+    Basic {
+        username: String,
+        password_file: Option<String>,
+    },
+}
+
+/// Explicit proxy settings.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+struct ProxySettings {
+    url: String,
+    username: Option<String>,
+    password_file: Option<String>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            ca_bundle: None,
+            client_tls: None,
+            auth: None,
+            connect_timeout: None,
+            request_timeout: DEFAULT_HTTP_COMPLETION_TIMEOUT,
+            proxy: None,
+        }
+    }
+}
+
+impl HttpSettings {
+    /// Process HTTP client configuration.
+    #[context("failed to validate HTTP client configuration")]
+    pub(crate) fn with_config(cfg: inputs::HttpInput) -> Result<Self> {
+        let client_tls = match (cfg.client_cert, cfg.client_key) {
+            (Some(cert), Some(key)) => Some(ClientTls { cert, key }),
+            (None, None) => None,
+            _ => anyhow::bail!("`client_cert` and `client_key` must be set together"),
+        };
+
+        let auth = match (cfg.bearer_token_file, cfg.username, cfg.password_file) {
+            (None, None, None) => None,
+            (Some(token_file), None, None) => Some(HttpAuth::Bearer { token_file }),
+            (None, Some(username), password_file) => Some(HttpAuth::Basic {
+                username,
+                password_file,
+            }),
+            (Some(_), _, _) => {
+                anyhow::bail!(
+                    "`bearer_token_file` and basic-auth credentials are mutually exclusive"
+                )
+            }
+            (None, None, Some(_)) => anyhow::bail!("`password_file` requires `username`"),
+        };
+
+        let connect_timeout = cfg
+            .connect_timeout
+            .map(|t| parse_timeout(&t))
+            .transpose()
+            .context("failed to parse `connect_timeout`")?;
+        let request_timeout = cfg
+            .request_timeout
+            .map(|t| parse_timeout(&t))
+            .transpose()
+            .context("failed to parse `request_timeout`")?
+            .unwrap_or(DEFAULT_HTTP_COMPLETION_TIMEOUT);
+
+        let proxy = match cfg.proxy {
+            Some(url) => {
+                reqwest::Proxy::all(&url).with_context(|| format!("invalid proxy '{}'", url))?;
+                if cfg.proxy_password_file.is_some() && cfg.proxy_username.is_none() {
+                    anyhow::bail!("`proxy_password_file` requires `proxy_username`");
+                }
+                Some(ProxySettings {
+                    url,
+                    username: cfg.proxy_username,
+                    password_file: cfg.proxy_password_file,
+                })
+            }
+            None => {
+                if cfg.proxy_username.is_some() || cfg.proxy_password_file.is_some() {
+                    anyhow::bail!("proxy credentials require `proxy`");
+                }
+                None
+            }
+        };
+
+        let settings = Self {
+            ca_bundle: cfg.ca_bundle,
+            client_tls,
+            auth,
+            connect_timeout,
+            request_timeout,
+            proxy,
+        };
+        Ok(settings)
+    }
+
+    /// Build an HTTP client, reading TLS material from the configured files.
+    #[context("failed to build HTTP client")]
+    pub(crate) fn build_client(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::ClientBuilder::new().timeout(self.request_timeout);
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(path) = &self.ca_bundle {
+            let bundle = read_file(path)?;
+            let certs = reqwest::Certificate::from_pem_bundle(&bundle)
+                .with_context(|| format!("failed to parse CA bundle '{}'", path))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        if let Some(tls) = &self.client_tls {
+            let cert = read_file(&tls.cert)?;
+            let key = read_file(&tls.key)?;
+            let identity = reqwest::Identity::from_pkcs8_pem(&cert, &key).with_context(|| {
+                format!(
+                    "failed to load client certificate '{}' with key '{}'",
+                    tls.cert, tls.key
+                )
+            })?;
+            builder = builder.identity(identity);
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let mut p = reqwest::Proxy::all(&proxy.url)?;
+            if let Some(username) = &proxy.username {
+                let password = match &proxy.password_file {
+                    Some(path) => read_secret(path)?,
+                    None => String::new(),
+                };
+                p = p.basic_auth(username, &password);
+            }
+            builder = builder.proxy(p);
+        }
+
+        let client = builder.build()?;
+        Ok(client)
+    }
+
+    /// Add configured credentials to a request.
+    ///
+    /// Credentials files are read on each request, so that they can be rotated.
+    pub(crate) fn authorize(
+        &self,
+        req: reqwest::RequestBuilder,
+    ) -> Result<reqwest::RequestBuilder> {
+        let req = match &self.auth {
+            None => req,
+            Some(HttpAuth::Bearer { token_file }) => req.bearer_auth(read_secret(token_file)?),
+            Some(HttpAuth::Basic {
+                username,
+                password_file,
+            }) => {
+                let password = password_file.as_deref().map(read_secret).transpose()?;
+                req.basic_auth(username, password)
+            }
+        };
+        Ok(req)
+    }
+}
+
+/// Retry backoff for a remote service.
+///
+/// This tracks consecutive failures, so that retries are spread out over time
+/// (and across clients) instead of hitting an overloaded service in lockstep.
+#[derive(Debug, Default)]
+pub(crate) struct Backoff {
+    /// Number of consecutive failures.
+    failures: u32,
+    /// Time before which the service should not be retried.
+    not_before: Option<Instant>,
+}
+
+impl Backoff {
+    /// Record a failed request, honoring the retry delay requested by the
+    /// server (if any).
+    ///
+    /// It returns the delay before the service should be retried.
+    pub(crate) fn record_failure(
+        &mut self,
+        retry_after: Option<Duration>,
+        now: Instant,
+    ) -> Duration {
+        use rand::Rng;
+
+        self.failures = self.failures.saturating_add(1);
+        let delay = Self::base_delay(self.failures, retry_after);
+        let jitter_ms = rand::rng().random_range(0..=delay.as_millis() as u64 / 4);
+        let delay = delay.saturating_add(Duration::from_millis(jitter_ms));
+        self.not_before = Some(now + delay);
+        delay
+    }
+
+    /// Record a successful request.
+    pub(crate) fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Return the time left before the service should be retried, if any.
+    pub(crate) fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.not_before
+            .map(|t| t.saturating_duration_since(now))
+            .filter(|d| !d.is_zero())
+    }
+
+    /// Return the retry delay after `failures` consecutive failures, without jitter.
+    fn base_delay(failures: u32, retry_after: Option<Duration>) -> Duration {
+        let delay = retry_after.unwrap_or_else(|| {
+            let exp = failures.saturating_sub(1).min(16);
+            BACKOFF_INITIAL_DELAY.saturating_mul(1 << exp)
+        });
+        delay.min(BACKOFF_MAX_DELAY)
+    }
+}
+
+/// Return the retry delay requested by a throttling response, if any.
+///
+/// This only considers the `Retry-After` header of 429 and 503 responses.
+pub(crate) fn throttle_delay(response: &reqwest::Response) -> Option<Duration> {
+    match response.status() {
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+            let value = response.headers().get(reqwest::header::RETRY_AFTER)?;
+            let delay = value
+                .to_str()
+                .ok()
+                .and_then(|v| parse_retry_after(v, Utc::now()));
+            if delay.is_none() {
+                log::warn!("ignoring invalid Retry-After from server: {:?}", value);
+            }
+            delay
+        }
+        _ => None,
+    }
+}
+
+/// Describe a throttling response, asking to retry after the given delay.
+pub(crate) fn throttled_message(delay: Duration) -> String {
+    format!("server overloaded, retry after {} seconds", delay.as_secs())
+}
+
+/// Parse a `Retry-After` value, either as a number of seconds or as an HTTP date.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&Utc).signed_duration_since(now);
+    // Dates in the past mean that the request can be retried right away.
+    Some(delay.to_std().unwrap_or_default())
+}
+
+/// Parse a non-zero timeout value (e.g. `30s`).
+fn parse_timeout(input: &str) -> Result<Duration> {
+    let timeout = utils::parse_duration(input)?;
+    if timeout.is_zero() {
+        anyhow::bail!("timeout must be greater than zero");
+    }
+    Ok(timeout)
+}
+
+/// Read the content of a file.
+fn read_file(path: &str) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("failed to read file '{}'", path))
+}
+
+/// Read a secret from a file, trimming surrounding whitespace.
+fn read_secret(path: &str) -> Result<String> {
+    let secret = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read credentials file '{}'", path))?;
+    Ok(secret.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_config() {
+        let settings = HttpSettings::with_config(inputs::HttpInput::default()).unwrap();
+        assert_eq!(settings, HttpSettings::default());
+
+        let cfg = inputs::HttpInput {
+            client_cert: Some("/etc/zincati/tls/client.pem".to_string()),
+            client_key: Some("/etc/zincati/tls/client.key".to_string()),
+            username: Some("zincati".to_string()),
+            password_file: Some("/etc/zincati/password".to_string()),
+            connect_timeout: Some("10s".to_string()),
+            request_timeout: Some("5m".to_string()),
+            proxy: Some("http://proxy.example.com:3128".to_string()),
+            ..Default::default()
+        };
+        let settings = HttpSettings::with_config(cfg).unwrap();
+        assert!(settings.client_tls.is_some());
+        assert_eq!(
+            settings.auth,
+            Some(HttpAuth::Basic {
+                username: "zincati".to_string(),
+                password_file: Some("/etc/zincati/password".to_string()),
+            })
+        );
+        assert_eq!(settings.connect_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(settings.request_timeout, Duration::from_secs(300));
+        // Client certificate files do not exist.
+        settings.build_client().unwrap_err();
+    }
+
+    #[test]
+    fn test_with_config_invalid() {
+        let invalid = vec![
+            inputs::HttpInput {
+                client_cert: Some("/etc/zincati/tls/client.pem".to_string()),
+                ..Default::default()
+            },
+            inputs::HttpInput {
+                bearer_token_file: Some("/etc/zincati/token".to_string()),
+                username: Some("zincati".to_string()),
+                ..Default::default()
+            },
+            inputs::HttpInput {
+                password_file: Some("/etc/zincati/password".to_string()),
+                ..Default::default()
+            },
+            inputs::HttpInput {
+                request_timeout: Some("0s".to_string()),
+                ..Default::default()
+            },
+            inputs::HttpInput {
+                connect_timeout: Some("soon".to_string()),
+                ..Default::default()
+            },
+            inputs::HttpInput {
+                proxy_username: Some("zincati".to_string()),
+                ..Default::default()
+            },
+        ];
+        for cfg in invalid {
+            HttpSettings::with_config(cfg).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_overrides() {
+        let global = inputs::HttpInput {
+            ca_bundle: Some("/etc/zincati/ca.pem".to_string()),
+            bearer_token_file: Some("/etc/zincati/token".to_string()),
+            request_timeout: Some("5m".to_string()),
+            ..Default::default()
+        };
+        let service = inputs::HttpInput {
+            username: Some("zincati".to_string()),
+            request_timeout: Some("1m".to_string()),
+            ..Default::default()
+        };
+
+        let merged = global.with_overrides(&service);
+        assert_eq!(merged.ca_bundle, global.ca_bundle);
+        assert_eq!(merged.request_timeout, service.request_timeout);
+        // Credentials are overridden as a whole.
+        assert_eq!(merged.bearer_token_file, None);
+        assert_eq!(merged.username, service.username);
+        HttpSettings::with_config(merged).unwrap();
+
+        let merged = global.with_overrides(&inputs::HttpInput::default());
+        assert_eq!(merged.bearer_token_file, global.bearer_token_file);
+
+        // Client certificate and key are overridden as a pair.
+        let global = inputs::HttpInput {
+            client_cert: Some("/etc/zincati/tls/global.pem".to_string()),
+            client_key: Some("/etc/zincati/tls/global.key".to_string()),
+            ..Default::default()
+        };
+        let service = inputs::HttpInput {
+            client_cert: Some("/etc/zincati/tls/service.pem".to_string()),
+            ..Default::default()
+        };
+        let merged = global.with_overrides(&service);
+        assert_eq!(merged.client_cert, service.client_cert);
+        assert_eq!(merged.client_key, None);
+        HttpSettings::with_config(merged).unwrap_err();
+
+        let merged = global.with_overrides(&inputs::HttpInput::default());
+        assert_eq!(merged.client_cert, global.client_cert);
+        assert_eq!(merged.client_key, global.client_key);
+    }
+
+    #[test]
+    fn test_authorize() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let token_path = tmpdir.path().join("token");
+        std::fs::write(&token_path, "s3cr3t\n").unwrap();
+        let cfg = inputs::HttpInput {
+            bearer_token_file: Some(token_path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        let settings = HttpSettings::with_config(cfg).unwrap();
+
+        let hclient = settings.build_client().unwrap();
+        let req = hclient.get("http://example.com");
+        let req = settings.authorize(req).unwrap().build().unwrap();
+        assert_eq!(req.headers()["authorization"], "Bearer s3cr3t");
+
+        std::fs::remove_file(&token_path).unwrap();
+        settings
+            .authorize(hclient.get("http://example.com"))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_throttle_delay() {
+        for (status, expected) in [
+            (429, Some(Duration::from_secs(120))),
+            (503, Some(Duration::from_secs(120))),
+            // Retry-After is only honored on throttling responses.
+            (500, None),
+        ] {
+            let response = http::Response::builder()
+                .status(status)
+                .header("retry-after", "120")
+                .body("")
+                .unwrap();
+            assert_eq!(throttle_delay(&response.into()), expected, "{}", status);
+        }
+
+        let response = http::Response::builder().status(429).body("").unwrap();
+        assert_eq!(throttle_delay(&response.into()), None);
+
+        let msg = throttled_message(Duration::from_secs(120));
+        assert_eq!(msg, "server overloaded, retry after 120 seconds");
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let cases = vec![
+            ("120", Some(Duration::from_secs(120))),
+            (" 0 ", Some(Duration::ZERO)),
+            (
+                "Wed, 21 Oct 2015 07:30:30 GMT",
+                Some(Duration::from_secs(150)),
+            ),
+            ("Wed, 21 Oct 2015 07:00:00 GMT", Some(Duration::ZERO)),
+            ("-5", None),
+            ("soon", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_retry_after(value, now), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let minutes = |m: u64| Duration::from_secs(m * 60);
+        assert_eq!(Backoff::base_delay(1, None), minutes(5));
+        assert_eq!(Backoff::base_delay(2, None), minutes(10));
+        assert_eq!(Backoff::base_delay(4, None), minutes(40));
+        assert_eq!(Backoff::base_delay(5, None), minutes(60));
+        assert_eq!(Backoff::base_delay(u32::MAX, None), minutes(60));
+        assert_eq!(Backoff::base_delay(3, Some(minutes(2))), minutes(2));
+        assert_eq!(Backoff::base_delay(1, Some(minutes(600))), minutes(60));
+
+        let now = Instant::now();
+        let mut backoff = Backoff::default();
+        assert_eq!(backoff.remaining(now), None);
+        let delay = backoff.record_failure(None, now);
+        assert!(delay >= minutes(5) && delay <= minutes(5) * 5 / 4);
+        assert_eq!(backoff.remaining(now), Some(delay));
+        assert_eq!(backoff.remaining(now + delay), None);
+        let delay = backoff.record_failure(None, now);
+        assert!(delay >= minutes(10) && delay <= minutes(10) * 5 / 4);
+        let delay = backoff.record_failure(Some(Duration::ZERO), now);
+        assert_eq!(delay, Duration::ZERO);
+        assert_eq!(backoff.remaining(now), None);
+
+        backoff.record_failure(None, now);
+        backoff.reset();
+        assert_eq!(backoff.remaining(now), None);
+        assert_eq!(backoff.failures, 0);
+    }
+}
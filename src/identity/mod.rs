@@ -161,6 +161,7 @@ impl Identity {
                 version: "0.0.0-mock".to_string(),
                 payload: Payload::Checksum("sha-mock".to_string()),
                 age_index: None,
+                urgent: false,
             },
             group: "mock-workers".to_string(),
             node_uuid: id128::Id128::parse_str("e0f3745b108f471cbd4883c6fbed8cdd").unwrap(),
@@ -17,10 +17,16 @@ mod config;
 mod dbus;
 /// FleetLock client.
 mod fleet_lock;
+/// HTTP client settings.
+mod http_client;
 /// Agent identity.
 mod identity;
 /// Metrics service.
 mod metrics;
+/// Logic for monthly maintenance windows.
+mod monthly;
+/// User-facing notifications.
+mod notifications;
 /// rpm-ostree client.
 mod rpm_ostree;
 /// Update strategies.
@@ -0,0 +1,435 @@
+//! Calendar-windows for events recurring on monthly basis.
+//!
+//! This contains helper logic to handle intervals of time which recur every month,
+//! anchored to the n-th occurrence of a weekday (e.g. "second Tuesday"):
+//!  * `MonthlyWindow`: a continuous interval starting on a given weekday occurrence.
+//!  * `MonthlyCalendar`: a set of intervals on a monthly calendar.
+
+use crate::weekly::utils;
+use anyhow::{bail, ensure, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone};
+use fn_error_context::context;
+use serde::Serialize;
+use std::ops::Range;
+
+/// Occurrence of a weekday within a month.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Occurrence {
+    First,
+    Second,
+    Third,
+    Fourth,
+    Last,
+}
+
+impl Occurrence {
+    /// Parse an occurrence string (English ordinals).
+    pub fn from_string(input: &str) -> Result<Self> {
+        let occurrence = match input.to_lowercase().as_str() {
+            "1st" | "first" => Occurrence::First,
+            "2nd" | "second" => Occurrence::Second,
+            "3rd" | "third" => Occurrence::Third,
+            "4th" | "fourth" => Occurrence::Fourth,
+            "last" => Occurrence::Last,
+            _ => bail!("unrecognized weekday occurrence: {}", input),
+        };
+
+        Ok(occurrence)
+    }
+
+    /// Return the date of this occurrence of a weekday, in the given month.
+    fn date_in_month(self, year: i32, month: u32, weekday: chrono::Weekday) -> Option<NaiveDate> {
+        let nth = match self {
+            Occurrence::First => 1,
+            Occurrence::Second => 2,
+            Occurrence::Third => 3,
+            Occurrence::Fourth => 4,
+            Occurrence::Last => {
+                let (next_year, next_month) = shift_month(year, month, 1);
+                let last_day = NaiveDate::from_ymd_opt(next_year, next_month, 1)?.pred_opt()?;
+                let offset = (7 + last_day.weekday().num_days_from_monday()
+                    - weekday.num_days_from_monday())
+                    % 7;
+                return last_day.checked_sub_signed(Duration::days(i64::from(offset)));
+            }
+        };
+
+        NaiveDate::from_weekday_of_month_opt(year, month, weekday, nth)
+    }
+}
+
+/// Calendar for periodic time-windows, recurring on monthly basis.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MonthlyCalendar {
+    /// A set of (possibly overlapping) windows.
+    windows: Vec<MonthlyWindow>,
+}
+
+impl MonthlyCalendar {
+    /// Create a calendar from a vector of monthly windows.
+    pub fn new(input: Vec<MonthlyWindow>) -> Self {
+        Self { windows: input }
+    }
+
+    /// Return whether datetime is contained in this monthly calendar.
+    pub fn contains_datetime(&self, datetime: &DateTime<impl TimeZone>) -> bool {
+        let timepoint = utils::datetime_at_minute(datetime);
+        self.occurrences(&timepoint, -1..=0)
+            .iter()
+            .any(|occurrence| occurrence.contains(&timepoint))
+    }
+
+    /// Return the duration remaining till the next window containing the given datetime.
+    ///
+    /// This returns `None` if no windows are reachable.
+    pub fn remaining_to_datetime(
+        &self,
+        datetime: &DateTime<impl TimeZone>,
+    ) -> Option<chrono::Duration> {
+        if self.is_empty() {
+            return None;
+        }
+
+        // Already in a window, zero minutes.
+        if self.contains_datetime(datetime) {
+            return Some(chrono::Duration::zero());
+        }
+
+        let timepoint = utils::datetime_at_minute(datetime);
+        self.occurrences(&timepoint, 0..=1)
+            .into_iter()
+            .find(|occurrence| occurrence.start > timepoint)
+            .map(|occurrence| occurrence.start.signed_duration_since(timepoint))
+    }
+
+    /// Return the duration remaining till the end of the window containing the given datetime.
+    ///
+    /// Overlapping and adjacent windows are coalesced, up to a whole week.
+    /// This returns `None` if datetime is not contained in any window.
+    pub fn remaining_in_window(
+        &self,
+        datetime: &DateTime<impl TimeZone>,
+    ) -> Option<chrono::Duration> {
+        let timepoint = utils::datetime_at_minute(datetime);
+        let limit = timepoint.clone() + Duration::weeks(1);
+        let occurrences = self.occurrences(&timepoint, -1..=1);
+        let coverage_end = utils::coalesced_end(&occurrences, &timepoint, &limit)?;
+
+        // Range end accounts for a +1 on the last minute in the window.
+        let remaining = coverage_end.signed_duration_since(timepoint) - Duration::minutes(1);
+        Some(remaining)
+    }
+
+    /// Return the absolute occurrences of all windows, for a range of months relative
+    /// to the month containing the given datetime.
+    ///
+    /// Window starts are local wall-clock times in the time zone of the datetime,
+    /// resolved according to `utils::resolve_local`, while lengths are elapsed time.
+    fn occurrences<T: TimeZone>(
+        &self,
+        datetime: &DateTime<T>,
+        months: std::ops::RangeInclusive<i32>,
+    ) -> Vec<Range<DateTime<T>>> {
+        let tz = datetime.timezone();
+        let local = datetime.naive_local();
+
+        let mut occurrences = Vec::new();
+        for delta in months {
+            let (year, month) = shift_month(local.year(), local.month(), delta);
+            for win in &self.windows {
+                let local_start = match win.start_in_month(year, month) {
+                    Some(t) => t,
+                    None => continue,
+                };
+                if let Some(start) = utils::resolve_local(&tz, &local_start) {
+                    // Like weekly windows, the last minute is included in the window.
+                    let length = Duration::minutes(i64::from(win.length_minutes()) + 1);
+                    let end = start.clone() + length;
+                    occurrences.push(start..end);
+                }
+            }
+        }
+        occurrences.sort_by(|a, b| a.start.cmp(&b.start));
+        occurrences
+    }
+
+    /// Return true if the calendar contains no time-windows.
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// Return the number of time-windows in the calendar.
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+}
+
+/// Timespan with a fixed duration, recurring on monthly basis.
+///
+/// Each window starts on a specific occurrence of a weekday, and can extend
+/// past the end of the month. Length duration is capped to a whole week.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MonthlyWindow {
+    occurrence: Occurrence,
+    start_day: chrono::Weekday,
+    start_hour: u8,
+    start_minute: u8,
+    length: std::time::Duration,
+}
+
+impl MonthlyWindow {
+    /// Build a monthly window from its start and length.
+    #[context("failed to parse monthly window")]
+    pub fn new(
+        occurrence: Occurrence,
+        start_day: chrono::Weekday,
+        start_hour: u8,
+        start_minute: u8,
+        length: std::time::Duration,
+    ) -> Result<Self> {
+        ensure!(
+            start_hour <= 23 && start_minute <= 59,
+            "invalid start time: {}:{}",
+            start_hour,
+            start_minute
+        );
+        utils::check_duration(&length)?;
+
+        let window = Self {
+            occurrence,
+            start_day,
+            start_hour,
+            start_minute,
+            length,
+        };
+        Ok(window)
+    }
+
+    /// Return window length, in minutes.
+    pub fn length_minutes(&self) -> u32 {
+        // SAFETY: invariant `length < MAX_WEEKLY_MINS < u32::MAX`
+        (self.length.as_secs() / 60) as u32
+    }
+
+    /// Return the start of this window in the given month.
+    fn start_in_month(&self, year: i32, month: u32) -> Option<NaiveDateTime> {
+        let date = self.occurrence.date_in_month(year, month, self.start_day)?;
+        date.and_hms_opt(u32::from(self.start_hour), u32::from(self.start_minute), 0)
+    }
+}
+
+/// Return the year and month shifted by the given number of months.
+fn shift_month(year: i32, month: u32, delta: i32) -> (i32, u32) {
+    let index = year
+        .saturating_mul(12)
+        .saturating_add(month as i32 - 1)
+        .saturating_add(delta);
+    (index.div_euclid(12), index.rem_euclid(12) as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Weekday;
+
+    #[test]
+    fn occurrence_from_string() {
+        assert_eq!(Occurrence::from_string("2nd").unwrap(), Occurrence::Second);
+        assert_eq!(Occurrence::from_string("Last").unwrap(), Occurrence::Last);
+        Occurrence::from_string("fifth").unwrap_err();
+    }
+
+    #[test]
+    fn occurrence_date_in_month() {
+        let cases = vec![
+            (Occurrence::First, Weekday::Sun, 2026, 11, "2026-11-01"),
+            (Occurrence::Second, Weekday::Tue, 2026, 11, "2026-11-10"),
+            (Occurrence::Fourth, Weekday::Mon, 2026, 11, "2026-11-23"),
+            (Occurrence::Last, Weekday::Mon, 2026, 11, "2026-11-30"),
+            (Occurrence::Last, Weekday::Thu, 2026, 12, "2026-12-31"),
+            (Occurrence::Last, Weekday::Fri, 2026, 12, "2026-12-25"),
+            (Occurrence::Last, Weekday::Sat, 2028, 2, "2028-02-26"),
+        ];
+        for (occurrence, weekday, year, month, expected) in cases {
+            let date = occurrence.date_in_month(year, month, weekday).unwrap();
+            assert_eq!(date.to_string(), expected, "{:?} {}", occurrence, weekday);
+        }
+    }
+
+    #[test]
+    fn window_invalid() {
+        let length = utils::check_minutes(60).unwrap();
+        MonthlyWindow::new(Occurrence::First, Weekday::Sun, 24, 0, length).unwrap_err();
+        let zero = std::time::Duration::from_secs(0);
+        MonthlyWindow::new(Occurrence::First, Weekday::Sun, 22, 0, zero).unwrap_err();
+    }
+
+    #[test]
+    fn calendar_contains_datetime() {
+        // Second Tuesday of every month, 22:00 for 3h.
+        let length = utils::check_minutes(180).unwrap();
+        let window = MonthlyWindow::new(Occurrence::Second, Weekday::Tue, 22, 0, length).unwrap();
+        let calendar = MonthlyCalendar::new(vec![window]);
+
+        let cases = vec![
+            ("2026-11-10T21:59:59+00:00", false),
+            ("2026-11-10T22:00:00+00:00", true),
+            ("2026-11-11T00:59:59+00:00", true),
+            ("2026-11-11T01:00:59+00:00", true),
+            ("2026-11-11T01:01:00+00:00", false),
+            ("2026-11-03T22:30:00+00:00", false),
+            ("2026-12-08T23:00:00+00:00", true),
+        ];
+        for (input, expected) in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap();
+            assert_eq!(calendar.contains_datetime(&datetime), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn calendar_month_boundary() {
+        // Last Thursday of December 2026 is the 31st, window spills into January.
+        let length = utils::check_minutes(120).unwrap();
+        let window = MonthlyWindow::new(Occurrence::Last, Weekday::Thu, 23, 0, length).unwrap();
+        let calendar = MonthlyCalendar::new(vec![window]);
+
+        let inside = DateTime::parse_from_rfc3339("2027-01-01T00:30:00+00:00").unwrap();
+        assert!(calendar.contains_datetime(&inside));
+        let end = DateTime::parse_from_rfc3339("2027-01-01T01:00:00+00:00").unwrap();
+        assert!(calendar.contains_datetime(&end));
+        let outside = DateTime::parse_from_rfc3339("2027-01-01T01:01:00+00:00").unwrap();
+        assert!(!calendar.contains_datetime(&outside));
+    }
+
+    #[test]
+    fn calendar_remaining_in_window() {
+        let l1 = utils::check_minutes(120).unwrap();
+        let w1 = MonthlyWindow::new(Occurrence::Second, Weekday::Tue, 22, 0, l1).unwrap();
+        let l2 = utils::check_minutes(60).unwrap();
+        let w2 = MonthlyWindow::new(Occurrence::Second, Weekday::Wed, 0, 0, l2).unwrap();
+        let calendar = MonthlyCalendar::new(vec![w1, w2]);
+
+        let cases = vec![
+            // Coalesced: Tue 22:00 to Wed 01:00.
+            ("2026-11-10T22:00:00+00:00", Some(180)),
+            ("2026-11-11T00:30:00+00:00", Some(30)),
+            ("2026-11-11T01:00:00+00:00", Some(0)),
+            ("2026-11-11T01:01:00+00:00", None),
+        ];
+        for (input, remaining) in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap();
+            let output = calendar
+                .remaining_in_window(&datetime)
+                .map(|d| d.num_minutes());
+            assert_eq!(output, remaining, "{}", input);
+        }
+    }
+
+    #[test]
+    fn calendar_matches_weekly() {
+        use crate::weekly::{WeeklyCalendar, WeeklyWindow};
+
+        // Second Tuesday of November 2026 (2026-11-10), 22:00 for 3h.
+        let length = utils::check_minutes(180).unwrap();
+        let window = MonthlyWindow::new(Occurrence::Second, Weekday::Tue, 22, 0, length).unwrap();
+        let monthly = MonthlyCalendar::new(vec![window]);
+        let windows = WeeklyWindow::parse_timespan(Weekday::Tue, 22, 0, length).unwrap();
+        let weekly = WeeklyCalendar::new(windows);
+
+        let cases = vec![
+            "2026-11-10T21:59:00+00:00",
+            "2026-11-10T22:00:00+00:00",
+            "2026-11-11T00:30:00+00:00",
+            "2026-11-11T00:59:00+00:00",
+            "2026-11-11T01:00:00+00:00",
+            "2026-11-11T01:01:00+00:00",
+        ];
+        for input in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap();
+            assert_eq!(
+                monthly.contains_datetime(&datetime),
+                weekly.contains_datetime(&datetime),
+                "{}",
+                input
+            );
+            assert_eq!(
+                monthly.remaining_in_window(&datetime),
+                weekly.remaining_in_window(&datetime),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn calendar_dst_transition() {
+        use tzfile::Tz;
+
+        // Second Sunday of March 2026 (2026-03-08) is DST start in New York.
+        let length = utils::check_minutes(120).unwrap();
+        let window = MonthlyWindow::new(Occurrence::Second, Weekday::Sun, 1, 0, length).unwrap();
+        let calendar = MonthlyCalendar::new(vec![window]);
+        let tz = Tz::named("America/New_York").unwrap();
+
+        let cases = vec![
+            ("2026-03-08T05:59:00+00:00", false, None),
+            ("2026-03-08T06:00:00+00:00", true, Some(120)),
+            ("2026-03-08T07:30:00+00:00", true, Some(30)),
+            ("2026-03-08T08:00:00+00:00", true, Some(0)),
+            ("2026-03-08T08:01:00+00:00", false, None),
+        ];
+        for (input, contained, remaining) in cases {
+            let utc = DateTime::parse_from_rfc3339(input).unwrap().naive_utc();
+            let datetime = (&tz).from_utc_datetime(&utc);
+            assert_eq!(
+                calendar.contains_datetime(&datetime),
+                contained,
+                "{}",
+                input
+            );
+            let output = calendar
+                .remaining_in_window(&datetime)
+                .map(|d| d.num_minutes());
+            assert_eq!(output, remaining, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_shift_month() {
+        assert_eq!(shift_month(2026, 11, 0), (2026, 11));
+        assert_eq!(shift_month(2026, 12, 1), (2027, 1));
+        assert_eq!(shift_month(2027, 1, -1), (2026, 12));
+        assert_eq!(shift_month(2026, 3, -14), (2025, 1));
+    }
+
+    #[test]
+    fn calendar_remaining() {
+        let l1 = utils::check_minutes(180).unwrap();
+        let w1 = MonthlyWindow::new(Occurrence::Second, Weekday::Tue, 22, 0, l1).unwrap();
+        let l2 = utils::check_minutes(60).unwrap();
+        let w2 = MonthlyWindow::new(Occurrence::First, Weekday::Sun, 3, 0, l2).unwrap();
+        let calendar = MonthlyCalendar::new(vec![w1, w2]);
+        assert_eq!(calendar.len(), 2);
+
+        let cases = vec![
+            // 2026-11-01 is the first Sunday of November.
+            ("2026-11-01T02:00:00+00:00", 60),
+            ("2026-11-01T03:30:00+00:00", 0),
+            ("2026-11-10T21:59:30+00:00", 1),
+            // Next window is the first Sunday of December (2026-12-06).
+            ("2026-11-11T01:01:00+00:00", (25 * 24 + 2) * 60 - 1),
+        ];
+        for (input, remaining) in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap();
+            let output = calendar
+                .remaining_to_datetime(&datetime)
+                .unwrap()
+                .num_minutes();
+            assert_eq!(output, remaining, "{}", input);
+        }
+
+        let empty = MonthlyCalendar::default();
+        let now = chrono::Utc::now();
+        assert_eq!(empty.remaining_to_datetime(&now), None);
+    }
+}
@@ -0,0 +1,158 @@
+//! Desktop notifications, via the `org.freedesktop.Notifications` interface.
+
+use anyhow::{Context, Result};
+use fn_error_context::context;
+use std::collections::HashMap;
+use zbus::blocking::{connection, Connection};
+use zbus::proxy;
+use zbus::zvariant::Value;
+
+/// Application name reported to the notification server.
+static APP_NAME: &str = "Zincati";
+
+/// Notification urgency level, as defined by the Desktop Notifications specification.
+const URGENCY_NORMAL: u8 = 1;
+const URGENCY_CRITICAL: u8 = 2;
+
+/// Return the path to the D-Bus bus socket of a user.
+pub(crate) fn user_bus_path(uid: u32) -> String {
+    format!("/run/user/{}/bus", uid)
+}
+
+/// Send a desktop notification on the user bus at `bus_address`.
+///
+/// User buses only accept connections from their owner, so this must be
+/// called with the credentials of the user owning the bus.
+#[context("failed to send desktop notification on '{}'", bus_address)]
+pub(crate) fn notify(bus_address: &str, summary: &str, body: &str, critical: bool) -> Result<u32> {
+    let connection = connection::Builder::address(bus_address)?
+        .build()
+        .context("failed to connect to user bus")?;
+    send_notification(&connection, summary, body, critical)
+}
+
+/// Send a desktop notification over an existing connection, returning its ID.
+fn send_notification(
+    connection: &Connection,
+    summary: &str,
+    body: &str,
+    critical: bool,
+) -> Result<u32> {
+    let urgency = if critical {
+        URGENCY_CRITICAL
+    } else {
+        URGENCY_NORMAL
+    };
+    let hints = HashMap::from([("urgency", Value::from(urgency))]);
+
+    let proxy = NotificationsProxyBlocking::new(connection)?;
+    // Let the server pick the expiration timeout (`-1`).
+    let id = proxy.notify(APP_NAME, 0, "", summary, body, &[], hints, -1)?;
+    Ok(id)
+}
+
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    /// Notify method
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+    use zbus::interface;
+    use zbus::zvariant::OwnedValue;
+
+    /// Notification as received by the mock server: app name, summary, body and urgency.
+    type Received = (String, String, String, u8);
+
+    /// Mock notification server, recording received notifications.
+    struct MockNotifications {
+        received: Arc<Mutex<Vec<Received>>>,
+    }
+
+    #[interface(name = "org.freedesktop.Notifications")]
+    impl MockNotifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            app_name: String,
+            _replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            body: String,
+            _actions: Vec<String>,
+            hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let urgency = hints
+                .get("urgency")
+                .and_then(|v| u8::try_from(v).ok())
+                .unwrap_or_default();
+            let mut received = self.received.lock().unwrap();
+            received.push((app_name, summary, body, urgency));
+            received.len() as u32
+        }
+    }
+
+    #[test]
+    fn test_send_notification() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+
+        let mock = MockNotifications {
+            received: Arc::clone(&received),
+        };
+        let server = std::thread::spawn(move || {
+            let guid = zbus::Guid::generate();
+            connection::Builder::unix_stream(server_stream)
+                .server(guid)
+                .unwrap()
+                .p2p()
+                .serve_at("/org/freedesktop/Notifications", mock)
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = connection::Builder::unix_stream(client_stream)
+            .p2p()
+            .build()
+            .unwrap();
+        let _server = server.join().unwrap();
+
+        let id = send_notification(&client, "Reboot pending", "Update v1", false).unwrap();
+        assert_eq!(id, 1);
+        let id = send_notification(&client, "Reboot imminent", "Update v2", true).unwrap();
+        assert_eq!(id, 2);
+
+        let received = received.lock().unwrap();
+        assert_eq!(
+            received[0],
+            (
+                APP_NAME.to_string(),
+                "Reboot pending".to_string(),
+                "Update v1".to_string(),
+                URGENCY_NORMAL
+            )
+        );
+        assert_eq!(received[1].1, "Reboot imminent");
+        assert_eq!(received[1].3, URGENCY_CRITICAL);
+    }
+}
@@ -0,0 +1,273 @@
+//! Templates for user-facing notification messages.
+
+pub(crate) mod desktop;
+pub(crate) mod webhook;
+
+use crate::config::inputs;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use fn_error_context::context;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// Variables available for substitution in message templates.
+static TEMPLATE_VARS: [&str; 5] = ["version", "deadline", "time_remaining", "node_name", "now"];
+
+/// Default template for the broadcast header.
+static DEFAULT_HEADER: &str = "Broadcast message from Zincati at ${now}:";
+
+/// Default template for the summary of desktop notifications.
+static DEFAULT_NOTIFICATION_SUMMARY: &str = "System update ${version} pending reboot";
+
+/// Default template for warnings about an upcoming reboot.
+static DEFAULT_REBOOT_WARNING: &str = "New update ${version} is available and has been deployed.
+If permitted by the update strategy, Zincati will reboot into this update when
+all interactive users have logged out, or in ${time_remaining}, whichever comes
+earlier. Please log out of all active sessions in order to let the auto-update
+process continue.";
+
+/// Default template for warnings about an imminent reboot into an urgent update.
+static DEFAULT_URGENT_WARNING: &str =
+    "New urgent update ${version} is available and has been deployed.
+Zincati will reboot into this update shortly, without waiting for interactive
+users to log out. Please save your work.";
+
+/// Path to the node hostname.
+static HOSTNAME_PATH: &str = "/proc/sys/kernel/hostname";
+
+/// Kind of user-facing message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub(crate) enum MessagePhase {
+    /// Header prepended to all broadcast messages.
+    Header,
+    /// Summary line of desktop notifications.
+    NotificationSummary,
+    /// First warning about an upcoming reboot, when entering the grace period.
+    FirstWarning,
+    /// Last warning about an upcoming reboot, at the end of the grace period.
+    FinalWarning,
+    /// Warning about an imminent reboot into an urgent update.
+    UrgentWarning,
+}
+
+impl MessagePhase {
+    /// Return the built-in template for this phase.
+    fn default_template(self) -> &'static str {
+        match self {
+            MessagePhase::Header => DEFAULT_HEADER,
+            MessagePhase::NotificationSummary => DEFAULT_NOTIFICATION_SUMMARY,
+            MessagePhase::FirstWarning | MessagePhase::FinalWarning => DEFAULT_REBOOT_WARNING,
+            MessagePhase::UrgentWarning => DEFAULT_URGENT_WARNING,
+        }
+    }
+}
+
+impl FromStr for MessagePhase {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let phase = match input {
+            "header" => MessagePhase::Header,
+            "notification_summary" => MessagePhase::NotificationSummary,
+            "first_warning" => MessagePhase::FirstWarning,
+            "final_warning" => MessagePhase::FinalWarning,
+            "urgent_warning" => MessagePhase::UrgentWarning,
+            _ => anyhow::bail!("unknown message phase '{}'", input),
+        };
+        Ok(phase)
+    }
+}
+
+/// Values for template variables.
+#[derive(Clone, Debug)]
+pub(crate) struct MessageVars {
+    /// Version of the pending release.
+    pub(crate) version: String,
+    /// Time of the upcoming reboot.
+    pub(crate) deadline: DateTime<Utc>,
+    /// Human-friendly time remaining until the upcoming reboot.
+    pub(crate) time_remaining: String,
+}
+
+/// Validated templates for user-facing messages.
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct MessageTemplates {
+    /// Custom templates, by phase and language (empty for the default one).
+    templates: BTreeMap<(MessagePhase, String), String>,
+}
+
+impl MessageTemplates {
+    /// Process message templates configuration.
+    #[context("failed to validate message templates")]
+    pub(crate) fn with_config(cfg: Vec<inputs::MessageTemplateInput>) -> Result<Self> {
+        let mut templates = BTreeMap::new();
+        for entry in cfg {
+            let phase = MessagePhase::from_str(&entry.phase)?;
+            let text = match (entry.text, entry.file) {
+                (Some(text), None) => text,
+                (None, Some(path)) => std::fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read template file '{}'", path))?,
+                _ => anyhow::bail!(
+                    "template for '{}' must have exactly one of `text` or `file`",
+                    entry.phase
+                ),
+            };
+            validate_template(&text)?;
+            templates.insert((phase, entry.lang), text.trim_end().to_string());
+        }
+
+        Ok(Self { templates })
+    }
+
+    /// Render the message for the given phase, in the language of `locale` if available.
+    pub(crate) fn render(
+        &self,
+        phase: MessagePhase,
+        locale: Option<&str>,
+        vars: &MessageVars,
+    ) -> String {
+        let template = self.template(phase, locale);
+        let variables = HashMap::from([
+            ("version".to_string(), vars.version.clone()),
+            ("deadline".to_string(), format_datetime(&vars.deadline)),
+            ("time_remaining".to_string(), vars.time_remaining.clone()),
+            ("node_name".to_string(), node_name()),
+            ("now".to_string(), format_datetime(&Utc::now())),
+        ]);
+
+        envsubst::substitute(template, &variables).unwrap_or_else(|e| {
+            log::error!("failed to render message template: {}", e);
+            template.to_string()
+        })
+    }
+
+    /// Return the best matching template for a phase and locale.
+    ///
+    /// Lookup order is: exact language and territory (e.g. `de_DE`), language
+    /// only (e.g. `de`), custom default, built-in default.
+    fn template(&self, phase: MessagePhase, locale: Option<&str>) -> &str {
+        let mut candidates = vec![];
+        if let Some(locale) = locale {
+            let lang_territory = locale.split(['.', '@']).next().unwrap_or_default();
+            let lang = lang_territory.split('_').next().unwrap_or_default();
+            candidates.push(lang_territory);
+            candidates.push(lang);
+        }
+        candidates.push("");
+
+        candidates
+            .into_iter()
+            .find_map(|lang| self.templates.get(&(phase, lang.to_string())))
+            .map(String::as_str)
+            .unwrap_or_else(|| phase.default_template())
+    }
+}
+
+/// Check that a template only references known variables.
+fn validate_template(template: &str) -> Result<()> {
+    let mut rest = template;
+    while let Some(start) = rest.find("${") {
+        let tail = &rest[start + 2..];
+        let end = tail
+            .find('}')
+            .with_context(|| format!("unterminated variable in template '{}'", template))?;
+        let name = &tail[..end];
+        if !TEMPLATE_VARS.contains(&name) {
+            anyhow::bail!("unknown variable '{}' in template", name);
+        }
+        rest = &tail[end + 1..];
+    }
+    Ok(())
+}
+
+/// Format a datetime for user-facing messages.
+fn format_datetime(datetime: &DateTime<Utc>) -> String {
+    datetime.format("%a %Y-%m-%d %H:%M:%S %Z").to_string()
+}
+
+/// Return the node hostname, or an empty string if unknown.
+fn node_name() -> String {
+    std::fs::read_to_string(HOSTNAME_PATH)
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template_input(phase: &str, lang: &str, text: &str) -> inputs::MessageTemplateInput {
+        inputs::MessageTemplateInput {
+            phase: phase.to_string(),
+            lang: lang.to_string(),
+            text: Some(text.to_string()),
+            file: None,
+        }
+    }
+
+    #[test]
+    fn test_templates_lookup() {
+        let templates = MessageTemplates::with_config(vec![
+            template_input(
+                "first_warning",
+                "",
+                "Update ${version} in ${time_remaining}",
+            ),
+            template_input(
+                "first_warning",
+                "de",
+                "Update ${version} in ${time_remaining} (de)",
+            ),
+            template_input("first_warning", "de_AT", "Update ${version} (de_AT)"),
+        ])
+        .unwrap();
+        let vars = MessageVars {
+            version: "v1".to_string(),
+            deadline: Utc::now(),
+            time_remaining: "10 minutes".to_string(),
+        };
+
+        let render = |locale| templates.render(MessagePhase::FirstWarning, locale, &vars);
+        assert_eq!(render(None), "Update v1 in 10 minutes");
+        assert_eq!(render(Some("fr_FR.UTF-8")), "Update v1 in 10 minutes");
+        assert_eq!(render(Some("de_DE.UTF-8")), "Update v1 in 10 minutes (de)");
+        assert_eq!(render(Some("de_AT.UTF-8@euro")), "Update v1 (de_AT)");
+
+        // Phases without custom templates use the built-in ones.
+        let default = templates.render(MessagePhase::FinalWarning, None, &vars);
+        assert!(default.starts_with("New update v1 is available"));
+        assert!(default.contains("in 10 minutes, whichever comes"));
+    }
+
+    #[test]
+    fn test_templates_validation() {
+        MessageTemplates::with_config(vec![template_input("bogus", "", "text")]).unwrap_err();
+        MessageTemplates::with_config(vec![template_input("header", "", "${unknown}")])
+            .unwrap_err();
+        MessageTemplates::with_config(vec![template_input("header", "", "${now")]).unwrap_err();
+
+        let both = inputs::MessageTemplateInput {
+            phase: "header".to_string(),
+            lang: String::new(),
+            text: Some("text".to_string()),
+            file: Some("/dev/null".to_string()),
+        };
+        MessageTemplates::with_config(vec![both]).unwrap_err();
+
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("header.txt");
+        std::fs::write(&path, "Message for ${node_name}:\n").unwrap();
+        let from_file = inputs::MessageTemplateInput {
+            phase: "header".to_string(),
+            lang: String::new(),
+            text: None,
+            file: Some(path.to_str().unwrap().to_string()),
+        };
+        let templates = MessageTemplates::with_config(vec![from_file]).unwrap();
+        assert_eq!(
+            templates.template(MessagePhase::Header, None),
+            "Message for ${node_name}:"
+        );
+    }
+}
@@ -0,0 +1,548 @@
+//! Webhook notifications for update lifecycle events.
+//!
+//! Events are queued in a bounded in-memory queue and delivered in order,
+//! as JSON payloads POSTed to the configured endpoint. Failed deliveries are
+//! retried with exponential backoff, up to a maximum number of attempts.
+
+use crate::config::inputs;
+use crate::identity::Identity;
+use crate::rpm_ostree::Release;
+use actix::prelude::*;
+use anyhow::{Context, Result};
+use fn_error_context::context;
+use prometheus::IntCounter;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Maximum number of pending events; the oldest ones are dropped first.
+const MAX_QUEUE_LENGTH: usize = 64;
+
+/// Maximum number of delivery attempts for a single event.
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+
+/// Delay before the first delivery retry, doubled on each further retry.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Timeout for HTTP requests completion.
+const HTTP_COMPLETION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout for delivering an event right before finalizing an update, as the
+/// node reboots shortly after.
+pub(crate) const FINALIZING_DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static::lazy_static! {
+    static ref WEBHOOK_DELIVERED: IntCounter = register_int_counter!(opts!(
+        "zincati_webhook_delivered_count",
+        "Total number of events delivered to the webhook endpoint."
+    )).unwrap();
+    static ref WEBHOOK_FAILED: IntCounter = register_int_counter!(opts!(
+        "zincati_webhook_failed_count",
+        "Total number of events dropped after exhausting delivery attempts."
+    )).unwrap();
+    static ref WEBHOOK_OVERFLOW: IntCounter = register_int_counter!(opts!(
+        "zincati_webhook_overflow_count",
+        "Total number of events dropped due to a full delivery queue."
+    )).unwrap();
+}
+
+/// Update lifecycle event kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WebhookEventKind {
+    /// Agent reported steady state.
+    ReportedSteady,
+    /// Update found on remote.
+    UpdateAvailable,
+    /// Staging an update failed.
+    DeployFailed,
+    /// Staged update replaced by a newer target.
+    UpdateSuperseded,
+    /// Update target abandoned.
+    UpdateAbandoned,
+    /// Update staged, waiting for finalization.
+    UpdateStaged,
+    /// Update about to be finalized.
+    UpdateFinalizing,
+    /// Update finalized, node about to reboot.
+    UpdateFinalized,
+}
+
+impl FromStr for WebhookEventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let kind = match input {
+            "reported_steady" => WebhookEventKind::ReportedSteady,
+            "update_available" => WebhookEventKind::UpdateAvailable,
+            "deploy_failed" => WebhookEventKind::DeployFailed,
+            "update_superseded" => WebhookEventKind::UpdateSuperseded,
+            "update_abandoned" => WebhookEventKind::UpdateAbandoned,
+            "update_staged" => WebhookEventKind::UpdateStaged,
+            "update_finalizing" => WebhookEventKind::UpdateFinalizing,
+            "update_finalized" => WebhookEventKind::UpdateFinalized,
+            _ => anyhow::bail!("unknown webhook event '{}'", input),
+        };
+        Ok(kind)
+    }
+}
+
+/// Node details, for event payloads.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct WebhookNode {
+    node_uuid: String,
+    group: String,
+    stream: String,
+    platform: String,
+    basearch: String,
+}
+
+/// Update lifecycle event, as sent to the webhook endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct WebhookEvent {
+    /// Event kind.
+    pub(crate) event: WebhookEventKind,
+    /// Event time, in RFC 3339 format.
+    timestamp: String,
+    /// Node identity.
+    node: WebhookNode,
+    /// Update agent state after the transition.
+    state: String,
+    /// Booted release.
+    from_release: String,
+    /// Target release, if any.
+    to_release: Option<String>,
+    /// Machine-friendly brief error kind, for failure events.
+    error_kind: Option<String>,
+}
+
+impl WebhookEvent {
+    /// Build an event for the given node.
+    pub(crate) fn new(
+        event: WebhookEventKind,
+        identity: &Identity,
+        state: &str,
+        to_release: Option<&Release>,
+        error_kind: Option<&str>,
+    ) -> Self {
+        Self {
+            event,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            node: WebhookNode {
+                node_uuid: identity.node_uuid.lower_hex(),
+                group: identity.group.clone(),
+                stream: identity.stream.clone(),
+                platform: identity.platform.clone(),
+                basearch: identity.basearch.clone(),
+            },
+            state: state.to_string(),
+            from_release: identity.current_os.version.clone(),
+            to_release: to_release.map(|r| r.version.clone()),
+            error_kind: error_kind.map(String::from),
+        }
+    }
+}
+
+/// Validated webhook settings.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct WebhookSettings {
+    /// Endpoint URL.
+    url: String,
+    /// Custom HTTP headers (values are not exposed, as they may hold secrets).
+    #[serde(skip)]
+    headers: BTreeMap<String, String>,
+    /// Path to a file containing a bearer token.
+    bearer_token_file: Option<String>,
+    /// Events to deliver (all of them, if empty).
+    events: BTreeSet<WebhookEventKind>,
+}
+
+impl WebhookSettings {
+    /// Process webhook configuration, returning `None` if webhooks are disabled.
+    #[context("failed to validate webhook configuration")]
+    pub(crate) fn with_config(cfg: inputs::WebhookInput) -> Result<Option<Self>> {
+        if cfg.url.is_empty() {
+            return Ok(None);
+        }
+
+        let url = reqwest::Url::parse(&cfg.url)
+            .with_context(|| format!("invalid webhook URL '{}'", cfg.url))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            anyhow::bail!("unsupported webhook URL scheme '{}'", url.scheme());
+        }
+        for (name, value) in &cfg.headers {
+            HeaderName::from_str(name)
+                .with_context(|| format!("invalid webhook header name '{}'", name))?;
+            HeaderValue::from_str(value)
+                .with_context(|| format!("invalid value for webhook header '{}'", name))?;
+        }
+        let events = cfg
+            .events
+            .iter()
+            .map(|e| WebhookEventKind::from_str(e))
+            .collect::<Result<_>>()?;
+
+        let settings = Self {
+            url: url.to_string(),
+            headers: cfg.headers,
+            bearer_token_file: cfg.bearer_token_file,
+            events,
+        };
+        Ok(Some(settings))
+    }
+
+    /// Whether the given event kind should be delivered.
+    fn accepts(&self, kind: WebhookEventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// Webhook delivery client.
+#[derive(Debug)]
+pub(crate) struct WebhookClient {
+    /// Webhook settings.
+    settings: WebhookSettings,
+    /// Asynchronous reqwest client.
+    hclient: reqwest::Client,
+    /// Pending events, oldest first.
+    queue: VecDeque<WebhookEvent>,
+    /// Failed delivery attempts for the oldest pending event.
+    attempts: u32,
+    /// Whether a delivery (or a retry) is in progress.
+    busy: bool,
+}
+
+impl WebhookClient {
+    /// Build a webhook client with the given settings.
+    pub(crate) fn new(settings: WebhookSettings) -> Result<Self> {
+        let hclient = reqwest::ClientBuilder::new()
+            .timeout(HTTP_COMPLETION_TIMEOUT)
+            .build()
+            .context("failed to build webhook HTTP client")?;
+        Ok(Self {
+            settings,
+            hclient,
+            queue: VecDeque::new(),
+            attempts: 0,
+            busy: false,
+        })
+    }
+
+    /// Queue an event, dropping the oldest pending one if the queue is full.
+    fn enqueue(&mut self, event: WebhookEvent) {
+        if self.queue.len() >= MAX_QUEUE_LENGTH {
+            if let Some(dropped) = self.queue.pop_front() {
+                log::warn!("webhook queue full, dropping '{:?}' event", dropped.event);
+                WEBHOOK_OVERFLOW.inc();
+                self.attempts = 0;
+            }
+        }
+        self.queue.push_back(event);
+    }
+
+    /// Start delivering the oldest pending event, if not busy.
+    fn deliver_next(&mut self, ctx: &mut actix::Context<Self>) {
+        if self.busy {
+            return;
+        }
+        let event = match self.queue.front() {
+            Some(event) => event.clone(),
+            None => return,
+        };
+
+        self.busy = true;
+        let delivery = deliver(self.hclient.clone(), self.settings.clone(), event.clone());
+        let delivery = delivery
+            .into_actor(self)
+            .map(move |res, actor, ctx| actor.delivery_done(ctx, &event, res));
+        ctx.spawn(delivery);
+    }
+
+    /// Record the outcome of a delivery, and schedule the next one.
+    fn delivery_done(
+        &mut self,
+        ctx: &mut actix::Context<Self>,
+        event: &WebhookEvent,
+        res: Result<()>,
+    ) {
+        // The event may have been dropped in the meantime, due to queue overflow.
+        let is_head = self.queue.front() == Some(event);
+        match res {
+            Ok(_) => {
+                log::trace!("delivered '{:?}' webhook event", event.event);
+                WEBHOOK_DELIVERED.inc();
+            }
+            Err(e) if is_head && self.attempts + 1 < MAX_DELIVERY_ATTEMPTS => {
+                let delay = INITIAL_RETRY_DELAY.saturating_mul(2u32.pow(self.attempts));
+                log::warn!("{:#}, retrying in {} seconds", e, delay.as_secs());
+                self.attempts += 1;
+                ctx.run_later(delay, |actor, ctx| {
+                    actor.busy = false;
+                    actor.deliver_next(ctx);
+                });
+                return;
+            }
+            Err(e) => {
+                log::error!("{:#}, giving up on '{:?}' event", e, event.event);
+                WEBHOOK_FAILED.inc();
+            }
+        }
+
+        if is_head {
+            self.queue.pop_front();
+            self.attempts = 0;
+        }
+        self.busy = false;
+        self.deliver_next(ctx);
+    }
+}
+
+impl Actor for WebhookClient {
+    type Context = actix::Context<Self>;
+}
+
+/// Request to deliver an update lifecycle event.
+pub(crate) struct SendEvent(pub(crate) WebhookEvent);
+
+impl Message for SendEvent {
+    type Result = ();
+}
+
+impl Handler<SendEvent> for WebhookClient {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendEvent, ctx: &mut Self::Context) -> Self::Result {
+        if !self.settings.accepts(msg.0.event) {
+            return;
+        }
+        self.enqueue(msg.0);
+        self.deliver_next(ctx);
+    }
+}
+
+/// Request to deliver an update lifecycle event right away, bypassing the queue.
+///
+/// This is meant for events sent right before rebooting, which cannot wait
+/// for pending events nor be retried later.
+pub(crate) struct DeliverEvent(pub(crate) WebhookEvent);
+
+impl Message for DeliverEvent {
+    type Result = Result<()>;
+}
+
+impl Handler<DeliverEvent> for WebhookClient {
+    type Result = ResponseFuture<Result<()>>;
+
+    fn handle(&mut self, msg: DeliverEvent, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.settings.accepts(msg.0.event) {
+            return Box::pin(async { Ok(()) });
+        }
+        let delivery = deliver(self.hclient.clone(), self.settings.clone(), msg.0);
+        Box::pin(async move {
+            delivery.await?;
+            WEBHOOK_DELIVERED.inc();
+            Ok(())
+        })
+    }
+}
+
+/// POST an event to the webhook endpoint.
+#[context("failed to deliver '{:?}' webhook event", event.event)]
+async fn deliver(
+    hclient: reqwest::Client,
+    settings: WebhookSettings,
+    event: WebhookEvent,
+) -> Result<()> {
+    let mut req = hclient.post(&settings.url).json(&event);
+    for (name, value) in &settings.headers {
+        req = req.header(name, value);
+    }
+    // Read the token on each delivery, so that it can be rotated.
+    if let Some(path) = &settings.bearer_token_file {
+        let token = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("failed to read bearer token file '{}'", path))?;
+        req = req.bearer_auth(token.trim());
+    }
+
+    let response = req.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("webhook endpoint returned status {}", status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+    use tokio::runtime as rt;
+
+    fn webhook_input(url: &str) -> inputs::WebhookInput {
+        inputs::WebhookInput {
+            url: url.to_string(),
+            headers: BTreeMap::new(),
+            bearer_token_file: None,
+            events: vec![],
+        }
+    }
+
+    fn mock_event(kind: WebhookEventKind) -> WebhookEvent {
+        let release = Release {
+            version: "v2".to_string(),
+            payload: crate::rpm_ostree::Payload::Checksum("ostree-checksum".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        WebhookEvent::new(
+            kind,
+            &Identity::mock_default(),
+            "UpdateStaged",
+            Some(&release),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        assert!(WebhookSettings::with_config(webhook_input(""))
+            .unwrap()
+            .is_none());
+        WebhookSettings::with_config(webhook_input("not a url")).unwrap_err();
+        WebhookSettings::with_config(webhook_input("ftp://example.com/")).unwrap_err();
+
+        let mut input = webhook_input("https://example.com/hook");
+        input.events = vec!["update_bogus".to_string()];
+        WebhookSettings::with_config(input).unwrap_err();
+
+        let mut input = webhook_input("https://example.com/hook");
+        input
+            .headers
+            .insert("bad header".to_string(), "value".to_string());
+        WebhookSettings::with_config(input).unwrap_err();
+
+        let mut input = webhook_input("https://example.com/hook");
+        input.events = vec!["update_staged".to_string(), "update_finalized".to_string()];
+        let settings = WebhookSettings::with_config(input).unwrap().unwrap();
+        assert!(settings.accepts(WebhookEventKind::UpdateStaged));
+        assert!(!settings.accepts(WebhookEventKind::UpdateAvailable));
+
+        let settings = WebhookSettings::with_config(webhook_input("https://example.com/hook"))
+            .unwrap()
+            .unwrap();
+        assert!(settings.accepts(WebhookEventKind::UpdateAvailable));
+    }
+
+    #[test]
+    fn test_deliver() {
+        let mut server = mockito::Server::new();
+        let tmpdir = tempfile::tempdir().unwrap();
+        let token_path = tmpdir.path().join("token");
+        std::fs::write(&token_path, "secret\n").unwrap();
+
+        let mut input = webhook_input(&format!("{}/hook", server.url()));
+        input
+            .headers
+            .insert("X-Source".to_string(), "zincati".to_string());
+        input.bearer_token_file = Some(token_path.to_str().unwrap().to_string());
+        let settings = WebhookSettings::with_config(input).unwrap().unwrap();
+
+        let body = r#"
+{
+  "event": "update_staged",
+  "node": {
+    "node_uuid": "e0f3745b108f471cbd4883c6fbed8cdd",
+    "group": "mock-workers"
+  },
+  "state": "UpdateStaged",
+  "to_release": "v2",
+  "error_kind": null
+}
+"#;
+        let m_hook = server
+            .mock("POST", "/hook")
+            .match_header("x-source", "zincati")
+            .match_header("authorization", "Bearer secret")
+            .match_header("content-type", "application/json")
+            .match_body(Matcher::PartialJsonString(body.to_string()))
+            .with_status(204)
+            .create();
+        let m_error = server.mock("POST", "/error").with_status(503).create();
+
+        let runtime = rt::Runtime::new().unwrap();
+        let hclient = reqwest::Client::new();
+        let event = mock_event(WebhookEventKind::UpdateStaged);
+        runtime
+            .block_on(deliver(hclient.clone(), settings.clone(), event.clone()))
+            .unwrap();
+        m_hook.assert();
+
+        let settings = WebhookSettings {
+            url: format!("{}/error", server.url()),
+            ..settings
+        };
+        runtime
+            .block_on(deliver(hclient, settings, event))
+            .unwrap_err();
+        m_error.assert();
+    }
+
+    #[test]
+    fn test_deliver_event() {
+        let mut server = mockito::Server::new();
+        let m_hook = server
+            .mock("POST", "/hook")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"event": "update_finalizing"}"#.to_string(),
+            ))
+            .with_status(200)
+            .create();
+
+        let mut input = webhook_input(&format!("{}/hook", server.url()));
+        input.events = vec!["update_finalizing".to_string()];
+        let settings = WebhookSettings::with_config(input).unwrap().unwrap();
+
+        actix::System::new().block_on(async {
+            let addr = WebhookClient::new(settings).unwrap().start();
+            let event = mock_event(WebhookEventKind::UpdateFinalizing);
+            addr.send(DeliverEvent(event)).await.unwrap().unwrap();
+            // Events not enabled by configuration are skipped.
+            let event = mock_event(WebhookEventKind::UpdateStaged);
+            addr.send(DeliverEvent(event)).await.unwrap().unwrap();
+        });
+        m_hook.assert();
+    }
+
+    #[test]
+    fn test_bounded_queue() {
+        let settings = WebhookSettings::with_config(webhook_input("https://example.com/hook"))
+            .unwrap()
+            .unwrap();
+        let mut client = WebhookClient::new(settings).unwrap();
+        client.attempts = 2;
+
+        let overflow_before = WEBHOOK_OVERFLOW.get();
+        client.enqueue(mock_event(WebhookEventKind::UpdateAvailable));
+        for _ in 1..MAX_QUEUE_LENGTH {
+            client.enqueue(mock_event(WebhookEventKind::UpdateStaged));
+        }
+        assert_eq!(client.queue.len(), MAX_QUEUE_LENGTH);
+        assert_eq!(client.attempts, 2);
+
+        client.enqueue(mock_event(WebhookEventKind::UpdateFinalized));
+        assert_eq!(client.queue.len(), MAX_QUEUE_LENGTH);
+        assert_eq!(WEBHOOK_OVERFLOW.get(), overflow_before + 1);
+        assert_eq!(client.attempts, 0);
+        assert_eq!(
+            client.queue.front().unwrap().event,
+            WebhookEventKind::UpdateStaged
+        );
+        assert_eq!(
+            client.queue.back().unwrap().event,
+            WebhookEventKind::UpdateFinalized
+        );
+    }
+}
@@ -178,6 +178,7 @@ mod tests {
             version: "foo".to_string(),
             payload: Payload::Checksum("bar".to_string()),
             age_index: None,
+            urgent: false,
         };
         let result = deploy_locked(release, false, None);
         assert!(result.is_err());
@@ -195,6 +196,7 @@ mod tests {
             version: "foo".to_string(),
             payload: Payload::Checksum("bar".to_string()),
             age_index: None,
+            urgent: false,
         };
         let result = deploy_locked(release.clone(), false, None).unwrap();
         assert_eq!(result, release);
@@ -99,6 +99,7 @@ impl Deployment {
             payload,
             version: self.version,
             age_index: None,
+            urgent: false,
         }
     }
 
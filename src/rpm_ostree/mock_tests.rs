@@ -1,4 +1,5 @@
 use crate::cincinnati::Cincinnati;
+use crate::http_client::HttpSettings;
 use crate::identity::Identity;
 use mockito::{self, Matcher};
 use std::collections::BTreeSet;
@@ -47,6 +48,8 @@ fn test_simple_graph() {
     let id = Identity::mock_default();
     let client = Cincinnati {
         base_url: server.url(),
+        http: HttpSettings::default(),
+        backoff: Default::default(),
     };
     let update = runtime.block_on(client.fetch_update_hint(&id, BTreeSet::new(), false));
     m_graph.assert();
@@ -99,6 +102,8 @@ fn test_downgrade() {
     let id = Identity::mock_default();
     let client = Cincinnati {
         base_url: server.url(),
+        http: HttpSettings::default(),
+        backoff: Default::default(),
     };
 
     // Downgrades denied.
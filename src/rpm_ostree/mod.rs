@@ -15,14 +15,17 @@ use ostree_ext::oci_spec::distribution::Reference;
 #[cfg(test)]
 mod mock_tests;
 
-use crate::cincinnati::{Node, AGE_INDEX_KEY, CHECKSUM_SCHEME, OCI_SCHEME, SCHEME_KEY};
+use crate::cincinnati::{Node, AGE_INDEX_KEY, CHECKSUM_SCHEME, OCI_SCHEME, SCHEME_KEY, URGENT_KEY};
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use core::fmt;
 use serde::Serialize;
 use std::cmp::Ordering;
 
 /// An OS release, as described by the cincinnati graph.
-#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+///
+/// Equality and ordering only consider the release identity (age index,
+/// version and payload), not its urgency.
+#[derive(Clone, Debug, Serialize)]
 pub struct Release {
     /// OS version.
     pub version: String,
@@ -30,6 +33,8 @@ pub struct Release {
     pub payload: Payload,
     /// Release age (Cincinnati `age_index`).
     pub age_index: Option<u64>,
+    /// Whether this release is marked as urgent (Cincinnati `updates.urgent`).
+    pub urgent: bool,
 }
 
 /// payload unique identifier can either be an ostree checksum or an OCI pullspec
@@ -74,6 +79,14 @@ impl std::cmp::Ord for Release {
     }
 }
 
+impl std::cmp::PartialEq for Release {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl std::cmp::Eq for Release {}
+
 impl std::cmp::PartialOrd for Release {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -106,10 +119,17 @@ impl Release {
                 .context(format!("invalid age_index value: {}", val))?
         };
 
+        let urgent = node
+            .metadata
+            .get(URGENT_KEY)
+            .map(|v| v == "true")
+            .unwrap_or(false);
+
         let rel = Self {
             version: node.version,
             payload,
             age_index: Some(age),
+            urgent,
         };
         Ok(rel)
     }
@@ -136,7 +156,24 @@ mod tests {
                 AGE_INDEX_KEY.to_string() => "0".to_string(),
             },
         };
-        Release::from_cincinnati(input).unwrap();
+        let regular = Release::from_cincinnati(input).unwrap();
+        assert!(!regular.urgent);
+
+        let urgent = Node {
+            version: "mock-version".to_string(),
+            payload: "mock-payload".to_string(),
+            metadata: hashmap! {
+                SCHEME_KEY.to_string() => CHECKSUM_SCHEME.to_string(),
+                AGE_INDEX_KEY.to_string() => "0".to_string(),
+                URGENT_KEY.to_string() => "true".to_string(),
+            },
+        };
+        let release = Release::from_cincinnati(urgent).unwrap();
+        assert!(release.urgent);
+
+        // Urgency does not affect the release identity.
+        assert_eq!(release, regular);
+        assert_eq!(release.cmp(&regular), Ordering::Equal);
     }
 
     #[test]
@@ -184,11 +221,13 @@ mod tests {
                 version: "v0".to_string(),
                 payload: Payload::Checksum("p0".to_string()),
                 age_index: Some(0),
+                urgent: false,
             };
             let n1 = Release {
                 version: "v1".to_string(),
                 payload: Payload::Checksum("p1".to_string()),
                 age_index: Some(1),
+                urgent: false,
             };
             assert!(n0 < n1);
             assert!(n0 == n0);
@@ -200,11 +239,13 @@ mod tests {
                 version: "v0".to_string(),
                 payload: Payload::Checksum("p0".to_string()),
                 age_index: Some(0),
+                urgent: false,
             };
             let n1 = Release {
                 version: "v1".to_string(),
                 payload: Payload::Checksum("p1".to_string()),
                 age_index: Some(0),
+                urgent: false,
             };
             assert!(n0 < n1);
             assert!(!(n0 < n0));
@@ -215,11 +256,13 @@ mod tests {
                 version: "v0".to_string(),
                 payload: Payload::Checksum("p0".to_string()),
                 age_index: Some(0),
+                urgent: false,
             };
             let n1 = Release {
                 version: "v0".to_string(),
                 payload: Payload::Checksum("p1".to_string()),
                 age_index: Some(0),
+                urgent: false,
             };
             assert!(n0 < n1);
             assert!(!(n0 < n0));
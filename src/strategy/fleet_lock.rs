@@ -1,14 +1,22 @@
 //! Strategy for fleet-wide coordinated updates (FleetLock protocol).
 
 use crate::config::inputs;
-use crate::fleet_lock::{Client, ClientBuilder};
+use crate::fleet_lock::{Client, ClientBuilder, FleetLockError, RequestReason};
+use crate::http_client::{Backoff, HttpSettings};
 use crate::identity::Identity;
+use crate::rpm_ostree::Release;
 use anyhow::{anyhow, Error, Result};
 use futures::prelude::*;
 use log::trace;
 use prometheus::IntCounterVec;
 use serde::Serialize;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Minimum delay between two lease renewals.
+const MIN_LEASE_RENEWAL_DELAY: Duration = Duration::from_secs(10);
 
 lazy_static::lazy_static! {
     static ref FLEET_LOCK_REQUESTS: IntCounterVec = register_int_counter_vec!(
@@ -21,13 +29,75 @@ lazy_static::lazy_static! {
         "Total number of errors while talking to the FleetLock server.",
         &["api", "kind"]
     ).unwrap();
+    static ref FLEET_LOCK_ENDPOINT_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "zincati_strategy_fleet_lock_endpoint_errors_total",
+        "Total number of errors from each FleetLock server endpoint.",
+        &["endpoint", "kind"]
+    ).unwrap();
 }
 
 /// Strategy for remote coordination.
 #[derive(Clone, Debug, Serialize)]
 pub(crate) struct StrategyFleetLock {
+    /// Lock-manager endpoints, in order of preference.
+    endpoints: Vec<Endpoint>,
+    /// Index of the last healthy endpoint, which is tried first.
+    #[serde(skip)]
+    preferred: Arc<AtomicUsize>,
+    /// Lease on the currently held lock, if granted by the server.
+    #[serde(skip)]
+    lease: Arc<Mutex<Option<Lease>>>,
+    /// Retry backoff, while all lock-manager endpoints are unavailable.
+    #[serde(skip)]
+    backoff: Arc<Mutex<Backoff>>,
+    /// Ownership of the reboot slot, as tracked by this node.
+    ///
+    /// This is only kept in memory, as the agent unlocks any slot held by
+    /// this node when reporting steady state on startup.
+    #[serde(skip)]
+    ownership: Arc<Mutex<SlotOwnership>>,
+    /// Backoff before locking a slot again, after releasing it due to failed finalizations.
+    #[serde(skip)]
+    relock_backoff: Arc<Mutex<Backoff>>,
+    /// Number of failed finalizations after which a held slot is released (0 to never release).
+    max_failed_finalizations: u32,
+}
+
+/// Lock-manager endpoint.
+#[derive(Clone, Debug, Serialize)]
+struct Endpoint {
+    /// Base URL, for logging and metrics.
+    base_url: String,
     /// Asynchronous client.
-    pub(crate) client: Client,
+    client: Client,
+}
+
+/// Lease on a reboot slot, expiring unless renewed.
+#[derive(Clone, Copy, Debug)]
+struct Lease {
+    /// When the lease was last granted or renewed.
+    renewed: Instant,
+    /// Lease TTL, as granted by the server.
+    ttl: Duration,
+}
+
+/// Ownership of a reboot slot, as tracked by this node.
+#[derive(Clone, Copy, Debug, Default)]
+struct SlotOwnership {
+    /// Whether a reboot slot is held.
+    held: bool,
+    /// Whether a finalization (i.e. a reboot) is in progress.
+    finalizing: bool,
+    /// Number of consecutive failed finalizations while holding the slot.
+    failed_finalizations: u32,
+}
+
+impl Lease {
+    /// Return the time left until the lease should be renewed, at half its TTL.
+    fn renewal_due_in(&self, now: Instant) -> Duration {
+        let due = self.renewed + self.ttl / 2;
+        due.saturating_duration_since(now)
+    }
 }
 
 impl StrategyFleetLock {
@@ -36,78 +106,374 @@ impl StrategyFleetLock {
 
     /// Build a new FleetLock strategy.
     pub fn new(cfg: inputs::UpdateInput, identity: &Identity) -> Result<Self> {
-        // Substitute templated key with agent runtime values.
-        let base_url = if envsubst::is_templated(&cfg.fleet_lock.base_url) {
-            let context = identity.url_variables();
-            envsubst::validate_vars(&context)?;
-            envsubst::substitute(cfg.fleet_lock.base_url, &context)?
-        } else {
-            cfg.fleet_lock.base_url
-        };
-
-        if base_url.is_empty() {
+        if cfg.fleet_lock.base_urls.is_empty() {
             anyhow::bail!("empty fleet_lock base URL");
         }
-        log::info!("remote fleet_lock reboot manager: {}", &base_url);
 
-        let builder = ClientBuilder::new(base_url, identity);
-        let client = builder.build()?;
-        let strategy = Self { client };
+        let http = HttpSettings::with_config(cfg.fleet_lock.http)?;
+        let mut endpoints = Vec::with_capacity(cfg.fleet_lock.base_urls.len());
+        for url in cfg.fleet_lock.base_urls {
+            // Substitute templated key with agent runtime values.
+            let base_url = if envsubst::is_templated(&url) {
+                let context = identity.url_variables();
+                envsubst::validate_vars(&context)?;
+                envsubst::substitute(url, &context)?
+            } else {
+                url
+            };
+
+            if base_url.is_empty() {
+                anyhow::bail!("empty fleet_lock base URL");
+            }
+            log::info!("remote fleet_lock reboot manager: {}", &base_url);
+
+            let client = ClientBuilder::new(base_url.clone(), identity)
+                .leases(cfg.fleet_lock.leases)
+                .http_settings(http.clone())
+                .build()?;
+            endpoints.push(Endpoint { base_url, client });
+        }
+
+        let strategy = Self {
+            endpoints,
+            preferred: Arc::new(AtomicUsize::new(0)),
+            lease: Arc::new(Mutex::new(None)),
+            backoff: Arc::default(),
+            ownership: Arc::default(),
+            relock_backoff: Arc::default(),
+            max_failed_finalizations: cfg.fleet_lock.max_failed_finalizations,
+        };
         Ok(strategy)
     }
 
     /// Check if finalization is allowed.
-    pub(crate) fn can_finalize(&self) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
-        let api = "pre-reboot";
-        FLEET_LOCK_REQUESTS.with_label_values(&[api]).inc();
+    pub(crate) fn can_finalize(
+        &self,
+        release: &Release,
+        urgent: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
         trace!("fleet_lock strategy, checking whether update can be finalized");
+        let relock_delay = self
+            .relock_backoff
+            .lock()
+            .unwrap()
+            .remaining(Instant::now());
+        if let Some(delay) = relock_delay {
+            log::debug!(
+                "not locking a reboot slot after failed finalizations, retrying in {} seconds",
+                delay.as_secs()
+            );
+            return Box::pin(futures::future::ok(false));
+        }
 
-        let res = self.client.pre_reboot().map_err(move |e| {
-            FLEET_LOCK_ERRORS
-                .with_label_values(&[api, &e.error_kind()])
-                .inc();
-            anyhow!("lock-manager {} failure: {}", api, e)
+        let reason = if urgent {
+            RequestReason::UrgentFinalization
+        } else {
+            RequestReason::Finalization
+        };
+        self.lock("pre-reboot", Some(release.version.clone()), reason)
+    }
+
+    /// Lock a reboot slot on manual request, outside of the update flow.
+    pub(crate) fn manual_lock(&self) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
+        trace!("fleet_lock strategy, manually locking a reboot slot");
+        self.lock("manual-lock", None, RequestReason::Manual)
+    }
+
+    /// Return a strategy acting on behalf of another node and/or group, with
+    /// no reboot slot held.
+    ///
+    /// This is meant for manual operations, e.g. releasing the slot of a node
+    /// which has been reimaged (and thus got a new node ID).
+    pub(crate) fn for_node(&self, id: Option<&str>, group: Option<&str>) -> Result<Self> {
+        let endpoints = self
+            .endpoints
+            .iter()
+            .map(|e| {
+                Ok(Endpoint {
+                    base_url: e.base_url.clone(),
+                    client: e.client.for_node(id, group)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            endpoints,
+            preferred: Arc::new(AtomicUsize::new(0)),
+            lease: Arc::new(Mutex::new(None)),
+            backoff: Arc::default(),
+            ownership: Arc::default(),
+            relock_backoff: Arc::default(),
+            max_failed_finalizations: self.max_failed_finalizations,
+        })
+    }
+
+    /// Return the base URLs of lock-manager endpoints, in order of preference.
+    pub(crate) fn base_urls(&self) -> Vec<&str> {
+        self.endpoints.iter().map(|e| e.base_url.as_str()).collect()
+    }
+
+    /// Return the time left until the lease on the held lock should be renewed,
+    /// if any.
+    ///
+    /// This is rate-limited, so that renewals are not attempted in a tight loop.
+    pub(crate) fn lease_renewal_delay(&self) -> Option<Duration> {
+        let lease = *self.lease.lock().unwrap();
+        lease.map(|l| {
+            l.renewal_due_in(Instant::now())
+                .max(MIN_LEASE_RENEWAL_DELAY.min(l.ttl / 2))
+        })
+    }
+
+    /// Return the time left before the lock-manager should be contacted again,
+    /// if backing off after failures.
+    pub(crate) fn retry_delay(&self) -> Option<Duration> {
+        self.backoff.lock().unwrap().remaining(Instant::now())
+    }
+
+    /// Record the start of a finalization, which reboots the node on success.
+    pub(crate) fn finalization_started(&self) {
+        self.ownership.lock().unwrap().finalizing = true;
+    }
+
+    /// Record a failed finalization, releasing the held reboot slot (if any)
+    /// after too many consecutive failures.
+    ///
+    /// It returns whether the reboot slot has been released.
+    pub(crate) fn finalization_failed(&self) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
+        let failures = {
+            let mut ownership = self.ownership.lock().unwrap();
+            ownership.finalizing = false;
+            if !ownership.held {
+                return Box::pin(futures::future::ok(false));
+            }
+            ownership.failed_finalizations = ownership.failed_finalizations.saturating_add(1);
+            ownership.failed_finalizations
+        };
+        if self.max_failed_finalizations == 0 || failures < self.max_failed_finalizations {
+            return Box::pin(futures::future::ok(false));
+        }
+
+        log::warn!(
+            "releasing reboot slot after {} failed finalization attempts",
+            failures
+        );
+        let relock_backoff = Arc::clone(&self.relock_backoff);
+        let res = self
+            .unlock("failed-finalization", RequestReason::FailedFinalization)
+            .inspect_ok(move |_| {
+                // Let other nodes use the slot, before trying again.
+                let delay = relock_backoff
+                    .lock()
+                    .unwrap()
+                    .record_failure(None, Instant::now());
+                log::warn!(
+                    "not locking a reboot slot again for {} seconds",
+                    delay.as_secs()
+                );
+            });
+        Box::pin(res)
+    }
+
+    /// Release the held reboot slot (if any) on shutdown, unless a
+    /// finalization is in progress.
+    ///
+    /// It returns whether the reboot slot has been released.
+    pub(crate) fn release_on_shutdown(&self) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
+        let ownership = *self.ownership.lock().unwrap();
+        if !ownership.held || ownership.finalizing {
+            return Box::pin(futures::future::ok(false));
+        }
+
+        log::info!("releasing reboot slot on shutdown");
+        self.unlock("shutdown", RequestReason::Shutdown)
+    }
+
+    /// Renew the lease on the held lock, if any and due.
+    ///
+    /// It returns `false` if the lock could not be renewed, in which case the
+    /// lease is dropped and the lock has to be acquired again.
+    pub(crate) fn renew_lease(
+        &self,
+        release: &Release,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
+        let lease = *self.lease.lock().unwrap();
+        match lease {
+            Some(l) if l.renewal_due_in(Instant::now()).is_zero() => {
+                trace!("fleet_lock strategy, renewing lock lease");
+                self.lock(
+                    "renew-lease",
+                    Some(release.version.clone()),
+                    RequestReason::LeaseRenewal,
+                )
+            }
+            _ => Box::pin(futures::future::ok(true)),
+        }
+    }
+
+    /// Try to lock a reboot slot (or renew its lease), recording any granted lease.
+    fn lock(
+        &self,
+        api: &'static str,
+        target_os_version: Option<String>,
+        reason: RequestReason,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
+        FLEET_LOCK_REQUESTS.with_label_values(&[api]).inc();
+
+        let lease = Arc::clone(&self.lease);
+        let backoff = Arc::clone(&self.backoff);
+        let ownership = Arc::clone(&self.ownership);
+        let res = failover(
+            self.endpoints.clone(),
+            Arc::clone(&self.preferred),
+            api,
+            move |client| client.pre_reboot(target_os_version.as_deref(), reason),
+        )
+        .inspect(move |res| track_backoff(&backoff, res))
+        .map(move |res| {
+            let mut lease = lease.lock().unwrap();
+            match res {
+                Ok(ttl) => {
+                    ownership.lock().unwrap().held = true;
+                    *lease = ttl.map(|ttl| {
+                        trace!("fleet_lock lease granted, TTL {} seconds", ttl.as_secs());
+                        Lease {
+                            renewed: Instant::now(),
+                            ttl,
+                        }
+                    });
+                    Ok(true)
+                }
+                Err(e) => {
+                    *lease = None;
+                    FLEET_LOCK_ERRORS
+                        .with_label_values(&[api, &e.error_kind()])
+                        .inc();
+                    Err(anyhow!("lock-manager {} failure: {}", api, e))
+                }
+            }
         });
         Box::pin(res)
     }
 
     /// Try to report steady state.
     pub(crate) fn report_steady(&self) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
-        let api = "steady-state";
-        FLEET_LOCK_REQUESTS.with_label_values(&[api]).inc();
         trace!("fleet_lock strategy, attempting to report steady");
+        self.unlock("steady-state", RequestReason::SteadyState)
+    }
 
-        let res = self.client.steady_state().map_err(move |e| {
-            FLEET_LOCK_ERRORS
-                .with_label_values(&[api, &e.error_kind()])
-                .inc();
-            anyhow!("lock-manager {} failure: {}", api, e)
+    /// Unlock the reboot slot held by this node on manual request, if any.
+    pub(crate) fn manual_unlock(&self) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
+        trace!("fleet_lock strategy, manually unlocking reboot slot");
+        self.unlock("manual-unlock", RequestReason::Manual)
+    }
+
+    /// Try to unlock the reboot slot held by this node, if any.
+    fn unlock(
+        &self,
+        api: &'static str,
+        reason: RequestReason,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
+        FLEET_LOCK_REQUESTS.with_label_values(&[api]).inc();
+
+        let lease = Arc::clone(&self.lease);
+        let backoff = Arc::clone(&self.backoff);
+        let ownership = Arc::clone(&self.ownership);
+        let res = failover(
+            self.endpoints.clone(),
+            Arc::clone(&self.preferred),
+            api,
+            move |client| client.steady_state(reason),
+        )
+        .inspect(move |res| track_backoff(&backoff, res))
+        .map(move |res| match res {
+            Ok(unlocked) => {
+                *lease.lock().unwrap() = None;
+                *ownership.lock().unwrap() = SlotOwnership::default();
+                Ok(unlocked)
+            }
+            Err(e) => {
+                FLEET_LOCK_ERRORS
+                    .with_label_values(&[api, &e.error_kind()])
+                    .inc();
+                Err(anyhow!("lock-manager {} failure: {}", api, e))
+            }
         });
         Box::pin(res)
     }
 }
 
+/// Record the outcome of a request to the lock-manager in its retry backoff.
+fn track_backoff<T>(backoff: &Mutex<Backoff>, res: &Result<T, FleetLockError>) {
+    let mut backoff = backoff.lock().unwrap();
+    match res {
+        Err(e) if e.is_unavailable() => {
+            let delay = backoff.record_failure(e.retry_after(), Instant::now());
+            log::warn!(
+                "lock-manager unavailable, backing off for {} seconds",
+                delay.as_secs()
+            );
+        }
+        _ => backoff.reset(),
+    }
+}
+
+/// Send a request to the lock-manager, failing over across endpoints.
+///
+/// Endpoints are tried in order, starting from the preferred (i.e. last healthy)
+/// one. Only unavailable endpoints are skipped, while semantic rejections (e.g.
+/// a full semaphore) are returned as is.
+async fn failover<T, F, R>(
+    endpoints: Vec<Endpoint>,
+    preferred: Arc<AtomicUsize>,
+    api: &'static str,
+    request: F,
+) -> Result<T, FleetLockError>
+where
+    F: Fn(&Client) -> R,
+    R: Future<Output = Result<T, FleetLockError>>,
+{
+    let start = preferred.load(Ordering::SeqCst);
+    let mut last_err = None;
+    for index in (start..endpoints.len()).chain(0..start) {
+        let endpoint = &endpoints[index];
+        let res = request(&endpoint.client).await;
+        if let Err(e) = &res {
+            FLEET_LOCK_ENDPOINT_ERRORS
+                .with_label_values(&[&endpoint.base_url, &e.error_kind()])
+                .inc();
+            if e.is_unavailable() {
+                log::warn!(
+                    "lock-manager at '{}' unavailable for {}: {}",
+                    endpoint.base_url,
+                    api,
+                    e
+                );
+                last_err = Some(e.clone());
+                continue;
+            }
+        }
+
+        if index != start {
+            log::info!("switching to lock-manager at '{}'", endpoint.base_url);
+            preferred.store(index, Ordering::SeqCst);
+        }
+        return res;
+    }
+
+    Err(last_err.expect("no lock-manager endpoints"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::inputs::{FleetLockInput, PeriodicInput, UpdateInput};
+    use crate::config::inputs::UpdateInput;
     use crate::identity::Identity;
+    use crate::rpm_ostree::Payload;
 
     #[test]
     fn test_url_simple() {
         let id = Identity::mock_default();
-        let input = UpdateInput {
-            allow_downgrade: false,
-            enabled: true,
-            strategy: "fleet_lock".to_string(),
-            fleet_lock: FleetLockInput {
-                base_url: "https://example.com".to_string(),
-            },
-            periodic: PeriodicInput {
-                intervals: vec![],
-                time_zone: "UTC".to_string(),
-            },
-        };
+        let input = UpdateInput::mock_fleet_lock(vec!["https://example.com".to_string()]);
 
         let res = StrategyFleetLock::new(input, &id);
         assert!(res.is_ok());
@@ -116,20 +482,200 @@ mod tests {
     #[test]
     fn test_empty_url() {
         let id = Identity::mock_default();
-        let input = UpdateInput {
-            allow_downgrade: false,
-            enabled: true,
-            strategy: "fleet_lock".to_string(),
-            fleet_lock: FleetLockInput {
-                base_url: String::new(),
-            },
-            periodic: PeriodicInput {
-                intervals: vec![],
-                time_zone: "localtime".to_string(),
-            },
-        };
+        let input = UpdateInput::mock_fleet_lock(vec![String::new()]);
 
         let res = StrategyFleetLock::new(input, &id);
         assert!(res.is_err());
     }
+
+    #[test]
+    fn test_lease_renewal() {
+        let now = Instant::now();
+        let lease = Lease {
+            renewed: now,
+            ttl: Duration::from_secs(600),
+        };
+        assert_eq!(lease.renewal_due_in(now), Duration::from_secs(300));
+        assert_eq!(
+            lease.renewal_due_in(now + Duration::from_secs(200)),
+            Duration::from_secs(100)
+        );
+        assert!(lease
+            .renewal_due_in(now + Duration::from_secs(300))
+            .is_zero());
+        assert!(lease
+            .renewal_due_in(now + Duration::from_secs(900))
+            .is_zero());
+
+        let id = Identity::mock_default();
+        let mut input = UpdateInput::mock_fleet_lock(vec!["https://example.com".to_string()]);
+        input.fleet_lock.leases = true;
+        let strategy = StrategyFleetLock::new(input, &id).unwrap();
+        assert_eq!(strategy.lease_renewal_delay(), None);
+
+        // Overdue renewals are rate-limited.
+        *strategy.lease.lock().unwrap() = Some(Lease {
+            renewed: now - Duration::from_secs(900),
+            ttl: Duration::from_secs(600),
+        });
+        assert_eq!(
+            strategy.lease_renewal_delay(),
+            Some(MIN_LEASE_RENEWAL_DELAY)
+        );
+        *strategy.lease.lock().unwrap() = Some(Lease {
+            renewed: now - Duration::from_secs(10),
+            ttl: Duration::from_secs(6),
+        });
+        assert_eq!(strategy.lease_renewal_delay(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_failover() {
+        let mut primary = mockito::Server::new();
+        let mut secondary = mockito::Server::new();
+        let id = Identity::mock_default();
+        let input = UpdateInput::mock_fleet_lock(vec![primary.url(), secondary.url()]);
+        let strategy = StrategyFleetLock::new(input, &id).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        // Unavailable servers are skipped, and the healthy one is preferred.
+        let m_primary = primary
+            .mock("POST", "/v1/steady-state")
+            .with_status(503)
+            .expect(1)
+            .create();
+        let m_secondary = secondary
+            .mock("POST", "/v1/steady-state")
+            .with_status(200)
+            .expect(2)
+            .create();
+        assert!(runtime.block_on(strategy.report_steady()).unwrap());
+        assert_eq!(strategy.preferred.load(Ordering::SeqCst), 1);
+        assert!(runtime.block_on(strategy.report_steady()).unwrap());
+        m_primary.assert();
+        m_secondary.assert();
+
+        // Semantic rejections do not fail over.
+        let body = r#"{ "kind": "failed_lock_semaphore_full", "value": "semaphore full" }"#;
+        let m_primary = primary
+            .mock("POST", "/v1/pre-reboot")
+            .with_status(200)
+            .expect(0)
+            .create();
+        let m_secondary = secondary
+            .mock("POST", "/v1/pre-reboot")
+            .with_status(409)
+            .with_body(body)
+            .expect(1)
+            .create();
+        let release = Release {
+            version: "v2".to_string(),
+            payload: Payload::Checksum("sha".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        runtime
+            .block_on(strategy.can_finalize(&release, false))
+            .unwrap_err();
+        assert_eq!(strategy.preferred.load(Ordering::SeqCst), 1);
+        m_primary.assert();
+        m_secondary.assert();
+        assert_eq!(strategy.retry_delay(), None);
+
+        // Throttling servers are skipped, and back off when all are unavailable.
+        for server in [&mut primary, &mut secondary] {
+            server
+                .mock("POST", "/v1/steady-state")
+                .with_status(429)
+                .with_header("retry-after", "600")
+                .expect(1)
+                .create();
+        }
+        runtime.block_on(strategy.report_steady()).unwrap_err();
+        let delay = strategy.retry_delay().unwrap();
+        assert!(delay > Duration::from_secs(590) && delay <= Duration::from_secs(750));
+        primary.reset();
+        secondary.reset();
+
+        // A successful request resets the backoff.
+        secondary
+            .mock("POST", "/v1/steady-state")
+            .with_status(200)
+            .create();
+        assert!(runtime.block_on(strategy.report_steady()).unwrap());
+        assert_eq!(strategy.retry_delay(), None);
+    }
+
+    #[test]
+    fn test_slot_release() {
+        let mut server = mockito::Server::new();
+        let id = Identity::mock_default();
+        let mut input = UpdateInput::mock_fleet_lock(vec![server.url()]);
+        input.fleet_lock.max_failed_finalizations = 2;
+        let strategy = StrategyFleetLock::new(input, &id).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let release = Release {
+            version: "v2".to_string(),
+            payload: Payload::Checksum("sha".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        let m_lock = server
+            .mock("POST", "/v1/pre-reboot")
+            .with_status(200)
+            .expect(2)
+            .create();
+
+        // Nothing to release without a held slot.
+        let m_unlock = server
+            .mock("POST", "/v1/steady-state")
+            .match_body(mockito::Matcher::Regex("failed_finalization".to_string()))
+            .with_status(200)
+            .expect(1)
+            .create();
+        strategy.finalization_started();
+        assert!(!runtime.block_on(strategy.finalization_failed()).unwrap());
+        assert!(!runtime.block_on(strategy.release_on_shutdown()).unwrap());
+
+        // The slot is released after too many failed finalizations.
+        assert!(runtime
+            .block_on(strategy.can_finalize(&release, false))
+            .unwrap());
+        strategy.finalization_started();
+        assert!(!runtime.block_on(strategy.finalization_failed()).unwrap());
+        strategy.finalization_started();
+        assert!(runtime.block_on(strategy.finalization_failed()).unwrap());
+        m_unlock.assert();
+
+        // The slot is not locked again on the following attempt.
+        let delay = strategy
+            .relock_backoff
+            .lock()
+            .unwrap()
+            .remaining(Instant::now())
+            .unwrap();
+        assert!(delay > Duration::from_secs(290) && delay <= Duration::from_secs(375));
+        assert!(!runtime
+            .block_on(strategy.can_finalize(&release, false))
+            .unwrap());
+        strategy.relock_backoff.lock().unwrap().reset();
+
+        // The slot is kept on shutdown while rebooting, and released otherwise.
+        let m_unlock = server
+            .mock("POST", "/v1/steady-state")
+            .match_body(mockito::Matcher::Regex("shutdown".to_string()))
+            .with_status(200)
+            .expect(1)
+            .create();
+        assert!(runtime
+            .block_on(strategy.can_finalize(&release, false))
+            .unwrap());
+        strategy.finalization_started();
+        assert!(!runtime.block_on(strategy.release_on_shutdown()).unwrap());
+        assert!(!runtime.block_on(strategy.finalization_failed()).unwrap());
+        assert!(runtime.block_on(strategy.release_on_shutdown()).unwrap());
+        assert!(!runtime.block_on(strategy.release_on_shutdown()).unwrap());
+        m_unlock.assert();
+        m_lock.assert();
+    }
 }
@@ -2,12 +2,14 @@
 
 use crate::config::inputs;
 use crate::identity::Identity;
+use crate::rpm_ostree::Release;
 use anyhow::Result;
 use fn_error_context::context;
 use futures::prelude::*;
 use log::error;
 use prometheus::{IntCounterVec, IntGauge, IntGaugeVec};
 use serde::Serialize;
+use std::time::Duration;
 
 mod fleet_lock;
 pub(crate) use fleet_lock::StrategyFleetLock;
@@ -39,6 +41,16 @@ lazy_static::lazy_static! {
         "Total length of the periodic strategy schedule in use"
     ).unwrap();
 
+    static ref PERIODIC_NEXT_WINDOW: IntGauge = register_int_gauge!(
+        "zincati_updates_strategy_periodic_next_window_timestamp",
+        "UTC timestamp of the next (or current) periodic strategy window start"
+    ).unwrap();
+
+    static ref PERIODIC_WINDOW_REMAINING: IntGauge = register_int_gauge!(
+        "zincati_updates_strategy_periodic_current_window_remaining_minutes",
+        "Remaining length of the current periodic strategy window"
+    ).unwrap();
+
     static ref FINALIZATION_STRATEGY_RESPONSES: IntCounterVec = register_int_counter_vec!(
         "zincati_updates_strategy_can_finalize_responses",
         "Total number of responses from querying update strategy for finalization consent.",
@@ -86,13 +98,43 @@ impl UpdateStrategy {
             let sched_length = p.schedule_length_minutes();
             PERIODIC_LENGTH.set(sched_length as i64);
         };
+
+        let (next_window, remaining) = self.window_details();
+        PERIODIC_NEXT_WINDOW.set(next_window);
+        PERIODIC_WINDOW_REMAINING.set(remaining);
+    }
+
+    /// Return details about the periodic strategy window, as a tuple of the next
+    /// (or current) window start timestamp and the remaining minutes in the current
+    /// window.
+    ///
+    /// Both values are zero for non-periodic strategies, or if no window is found.
+    pub(crate) fn window_details(&self) -> (i64, i64) {
+        match self {
+            UpdateStrategy::Periodic(p) => (
+                p.next_window_timestamp().unwrap_or(0),
+                p.current_window_remaining_minutes(),
+            ),
+            _ => (0, 0),
+        }
+    }
+
+    /// Re-resolve the time zone in use by the strategy, if it follows the
+    /// system's local time zone configuration.
+    pub(crate) fn refresh_time_zone(&mut self) -> Result<()> {
+        if let UpdateStrategy::Periodic(p) = self {
+            if p.refresh_time_zone()? {
+                log::info!("update strategy: {}", self.human_description());
+            }
+        };
+        Ok(())
     }
 
     /// Return the configuration label/name for this update strategy.
     ///
     /// This can be used to match back an instantiated strategy to the mode label
     /// from configuration.
-    fn configuration_label(&self) -> &'static str {
+    pub(crate) fn configuration_label(&self) -> &'static str {
         match self {
             UpdateStrategy::FleetLock(_) => StrategyFleetLock::LABEL,
             UpdateStrategy::Immediate(_) => StrategyImmediate::LABEL,
@@ -111,11 +153,46 @@ impl UpdateStrategy {
         }
     }
 
+    /// Return when a reboot into a staged update is expected according to this
+    /// strategy, in human terms.
+    pub(crate) fn human_pending_reboot(&self) -> String {
+        match self {
+            UpdateStrategy::FleetLock(_) => {
+                "once a reboot slot is acquired from the FleetLock server".to_string()
+            }
+            UpdateStrategy::Immediate(_) => "shortly".to_string(),
+            UpdateStrategy::Periodic(_) => match self.window_details() {
+                (_, remaining) if remaining > 0 => {
+                    "during the current maintenance window".to_string()
+                }
+                (0, _) => "during the next maintenance window".to_string(),
+                (next, _) => match chrono::DateTime::from_timestamp(next, 0) {
+                    Some(start) => format!(
+                        "during the next maintenance window, starting {}",
+                        start.format("%a %Y-%m-%d %H:%M:%S %Z")
+                    ),
+                    None => "during the next maintenance window".to_string(),
+                },
+            },
+        }
+    }
+
     /// Check if finalization is allowed at this time.
-    pub(crate) fn can_finalize(&self) -> impl Future<Output = bool> {
+    ///
+    /// Urgent updates bypass the reboot windows of the periodic strategy,
+    /// while other strategies are consulted as usual.
+    pub(crate) fn can_finalize(
+        &self,
+        release: &Release,
+        urgent: bool,
+    ) -> impl Future<Output = bool> {
         let lock = match self {
-            UpdateStrategy::FleetLock(s) => s.can_finalize(),
+            UpdateStrategy::FleetLock(s) => s.can_finalize(release, urgent),
             UpdateStrategy::Immediate(s) => s.can_finalize(),
+            UpdateStrategy::Periodic(_) if urgent => {
+                log::warn!("urgent update, bypassing periodic strategy reboot windows");
+                Box::pin(future::ok(true))
+            }
             UpdateStrategy::Periodic(s) => s.can_finalize(),
         };
 
@@ -144,6 +221,80 @@ impl UpdateStrategy {
         }
     }
 
+    /// Return the time left until the lease on a held reboot slot should be
+    /// renewed, if any.
+    pub(crate) fn lease_renewal_delay(&self) -> Option<Duration> {
+        match self {
+            UpdateStrategy::FleetLock(s) => s.lease_renewal_delay(),
+            _ => None,
+        }
+    }
+
+    /// Return the time left before the remote service backing this strategy
+    /// should be contacted again, if backing off after failures.
+    pub(crate) fn retry_delay(&self) -> Option<Duration> {
+        match self {
+            UpdateStrategy::FleetLock(s) => s.retry_delay(),
+            _ => None,
+        }
+    }
+
+    /// Renew the lease on a held reboot slot, if any and due.
+    ///
+    /// It returns whether the reboot slot (if any) is still held.
+    pub(crate) fn renew_lease(&self, release: &Release) -> impl Future<Output = bool> {
+        let renew = match self {
+            UpdateStrategy::FleetLock(s) => s.renew_lease(release),
+            _ => Box::pin(future::ok(true)),
+        };
+
+        async {
+            renew.await.unwrap_or_else(|e| {
+                error!("{}", e);
+                false
+            })
+        }
+    }
+
+    /// Record the start of a finalization, which reboots the node on success.
+    pub(crate) fn finalization_started(&self) {
+        if let UpdateStrategy::FleetLock(s) = self {
+            s.finalization_started();
+        }
+    }
+
+    /// Record a failed finalization.
+    ///
+    /// It returns whether a held reboot slot has been released as a result.
+    pub(crate) fn finalization_failed(&self) -> impl Future<Output = bool> {
+        let release = match self {
+            UpdateStrategy::FleetLock(s) => s.finalization_failed(),
+            _ => Box::pin(future::ok(false)),
+        };
+
+        async {
+            release.await.unwrap_or_else(|e| {
+                error!("{}", e);
+                false
+            })
+        }
+    }
+
+    /// Release the held reboot slot (if any) on agent shutdown, unless the
+    /// node is rebooting into a finalized update.
+    pub(crate) fn release_on_shutdown(&self) -> impl Future<Output = ()> {
+        let release = match self {
+            UpdateStrategy::FleetLock(s) => s.release_on_shutdown(),
+            _ => Box::pin(future::ok(false)),
+        };
+
+        async {
+            if let Err(e) = release.await {
+                error!("{}", e);
+            }
+        }
+    }
+
     /// Try to report and enter steady state.
     pub(crate) fn report_steady(&self) -> impl Future<Output = bool> {
         let unlock = match self {
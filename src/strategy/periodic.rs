@@ -1,30 +1,60 @@
 //! Strategy for periodic (weekly) updates.
 
 use crate::config::inputs;
+use crate::monthly::{MonthlyCalendar, MonthlyWindow, Occurrence};
 use crate::weekly::{utils, WeeklyCalendar, WeeklyWindow};
-use anyhow::{Context, Error, Result};
-use chrono::{TimeZone, Utc};
+use anyhow::{ensure, Context, Error, Result};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 use fn_error_context::context;
 use futures::future;
 use futures::prelude::*;
 use log::trace;
+use prometheus::IntGauge;
 use serde::Serialize;
 use std::fs::read_link;
+use std::ops::Range;
 use std::path::Path;
 use std::pin::Pin;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tzfile::Tz;
+use zbus::proxy::CacheProperties;
+use zbus::{proxy, Connection};
+
+lazy_static::lazy_static! {
+    static ref TIME_SYNC_BLOCKED: IntGauge = register_int_gauge!(
+        "zincati_updates_strategy_periodic_time_sync_blocked",
+        "Whether finalization is blocked waiting for system clock synchronization"
+    ).unwrap();
+}
+
+/// Path to the system's local time zone configuration.
+static LOCALTIME_PATH: &str = "/etc/localtime";
+
+/// Maximum number of steps when coalescing weekly and monthly windows.
+const MAX_COALESCED_STEPS: usize = 16;
 
 /// Strategy for periodic (weekly) updates.
 #[derive(Clone, Debug, Serialize)]
 pub(crate) struct StrategyPeriodic {
     /// Whitelisted time windows during which updates are allowed.
     schedule: WeeklyCalendar,
+    /// Whitelisted time windows recurring on monthly basis.
+    monthly_schedule: MonthlyCalendar,
     /// Time zone in which time windows are defined in.
     #[serde(skip_serializing)]
     pub(crate) time_zone: Tz,
     /// Time zone name.
     tz_name: String,
+    /// Whether time zone follows the system's local time zone configuration.
+    tz_localtime: bool,
+    /// Absolute periods during which updates are not allowed, sorted by start.
+    blackouts: Vec<BlackoutPeriod>,
+    /// Minimum remaining window length (in minutes) required to start finalization.
+    min_remaining_minutes: u32,
+    /// Bounded wait for system clock synchronization, if enabled.
+    time_sync_wait: Option<TimeSyncWait>,
 }
 
 impl Default for StrategyPeriodic {
@@ -32,12 +62,149 @@ impl Default for StrategyPeriodic {
         let utc = "UTC";
         StrategyPeriodic {
             schedule: WeeklyCalendar::default(),
+            monthly_schedule: MonthlyCalendar::default(),
             time_zone: Tz::named(utc).unwrap(),
             tz_name: utc.to_string(),
+            tz_localtime: false,
+            blackouts: vec![],
+            min_remaining_minutes: 0,
+            time_sync_wait: None,
         }
     }
 }
 
+/// Bounded wait for system clock synchronization.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct TimeSyncWait {
+    /// Maximum time to wait for synchronization.
+    timeout: Duration,
+    /// Start of the wait (monotonic, thus unaffected by clock adjustments).
+    #[serde(skip_serializing)]
+    since: Instant,
+    /// Whether the wait is over, either synchronized or timed out (shared across clones).
+    #[serde(skip_serializing)]
+    done: Arc<AtomicBool>,
+}
+
+impl TimeSyncWait {
+    /// Start a new wait, bounded by `timeout`.
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            since: Instant::now(),
+            done: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Return whether finalization is still blocked waiting for clock synchronization.
+    ///
+    /// Once the clock is synchronized or the wait timed out, this is never
+    /// blocked again.
+    async fn is_blocked<F>(&self, synchronized: impl FnOnce() -> F) -> bool
+    where
+        F: Future<Output = Result<bool>>,
+    {
+        if self.done.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        match synchronized().await {
+            Ok(true) => {
+                log::info!("system clock synchronized");
+                self.done.store(true, Ordering::SeqCst);
+                return false;
+            }
+            Ok(false) => {}
+            Err(e) => log::warn!("failed to check system clock synchronization: {:#}", e),
+        }
+
+        if self.since.elapsed() >= self.timeout {
+            log::warn!(
+                "system clock still not synchronized after {} minutes, not waiting any longer",
+                self.timeout.as_secs() / 60
+            );
+            self.done.store(true, Ordering::SeqCst);
+            return false;
+        }
+        true
+    }
+}
+
+/// Check whether the system clock is synchronized, according to timedated.
+async fn is_time_synchronized() -> Result<bool> {
+    let connection = Connection::system()
+        .await
+        .context("failed to connect to system bus")?;
+    let proxy = TimedateProxy::builder(&connection)
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+    let synchronized = proxy
+        .ntp_synchronized()
+        .await
+        .context("failed to read timedated NTPSynchronized property")?;
+    Ok(synchronized)
+}
+
+#[proxy(
+    interface = "org.freedesktop.timedate1",
+    default_service = "org.freedesktop.timedate1",
+    default_path = "/org/freedesktop/timedate1"
+)]
+trait Timedate {
+    /// NTPSynchronized property
+    #[zbus(property, name = "NTPSynchronized")]
+    fn ntp_synchronized(&self) -> zbus::Result<bool>;
+}
+
+/// Return whether a minimum remaining window length can be satisfied by
+/// reboot windows at most `longest_window_minutes` long.
+///
+/// Adjacent windows are coalesced at runtime, so this is only a hint.
+fn is_min_remaining_reachable(min_remaining_minutes: u32, longest_window_minutes: u32) -> bool {
+    min_remaining_minutes == 0 || min_remaining_minutes < longest_window_minutes
+}
+
+/// Absolute period of time during which updates are not allowed.
+///
+/// Bounds are expressed in local wall-clock time, in the time zone of the strategy.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct BlackoutPeriod {
+    /// Period start (inclusive).
+    start: NaiveDateTime,
+    /// Period end (exclusive).
+    end: NaiveDateTime,
+}
+
+impl BlackoutPeriod {
+    /// Datetime format for blackout bounds.
+    const DATETIME_FORMAT: &'static str = "%Y-%m-%dT%H:%M";
+
+    /// Parse a blackout period from its start and end datetimes.
+    #[context("failed to parse blackout period")]
+    fn parse(start: &str, end: &str) -> Result<Self> {
+        let start = NaiveDateTime::parse_from_str(start, Self::DATETIME_FORMAT)
+            .with_context(|| format!("unrecognized start datetime: {}", start))?;
+        let end = NaiveDateTime::parse_from_str(end, Self::DATETIME_FORMAT)
+            .with_context(|| format!("unrecognized end datetime: {}", end))?;
+        ensure!(
+            start < end,
+            "blackout period ends before it starts: {} to {}",
+            start,
+            end
+        );
+
+        Ok(Self { start, end })
+    }
+
+    /// Return the absolute bounds of this period, in the given time zone.
+    fn resolve(&self, tz: &Tz) -> Option<Range<DateTime<Utc>>> {
+        let start = utils::resolve_local(&tz, &self.start)?.with_timezone(&Utc);
+        let end = utils::resolve_local(&tz, &self.end)?.with_timezone(&Utc);
+        Some(start..end)
+    }
+}
+
 impl StrategyPeriodic {
     /// Strategy label/name.
     pub const LABEL: &'static str = "periodic";
@@ -46,6 +213,19 @@ impl StrategyPeriodic {
     #[context("failed to parse periodic strategy")]
     pub fn new(cfg: inputs::UpdateInput) -> Result<Self> {
         let (time_zone, tz_name) = Self::get_time_zone_info_from_cfg(&cfg.periodic)?;
+        let longest_window_minutes = cfg
+            .periodic
+            .intervals
+            .iter()
+            .map(|entry| entry.length_minutes)
+            .chain(
+                cfg.periodic
+                    .monthly_intervals
+                    .iter()
+                    .map(|e| e.length_minutes),
+            )
+            .max()
+            .unwrap_or(0);
 
         let mut intervals = Vec::with_capacity(cfg.periodic.intervals.len());
         for entry in cfg.periodic.intervals {
@@ -57,17 +237,66 @@ impl StrategyPeriodic {
         }
 
         let calendar = WeeklyCalendar::new(intervals);
-        match calendar.length_minutes() {
-            0 => anyhow::bail!(
-                "invalid or missing periodic updates configuration: weekly calendar length is zero"
+
+        let mut monthly_intervals = Vec::with_capacity(cfg.periodic.monthly_intervals.len());
+        for entry in cfg.periodic.monthly_intervals {
+            let occurrence = Occurrence::from_string(&entry.occurrence)?;
+            let weekday = utils::weekday_from_string(&entry.start_day)?;
+            let start = utils::time_from_string(&entry.start_time)?;
+            let length = Duration::from_secs(u64::from(entry.length_minutes).saturating_mul(60));
+            let window = MonthlyWindow::new(occurrence, weekday, start.0, start.1, length)?;
+            monthly_intervals.push(window);
+        }
+        let monthly_calendar = MonthlyCalendar::new(monthly_intervals);
+
+        match (calendar.length_minutes(), monthly_calendar.len()) {
+            (0, 0) => anyhow::bail!(
+                "invalid or missing periodic updates configuration: calendar length is zero"
             ),
-            n => log::trace!("periodic updates, weekly calendar length: {} minutes", n),
+            (n, m) => log::trace!(
+                "periodic updates, weekly calendar length: {} minutes, monthly windows: {}",
+                n,
+                m
+            ),
+        };
+
+        let mut blackouts = Vec::with_capacity(cfg.periodic.blackouts.len());
+        for entry in cfg.periodic.blackouts {
+            let period = BlackoutPeriod::parse(&entry.start, &entry.end)?;
+            blackouts.push(period);
+        }
+        blackouts.sort_by_key(|period| period.start);
+
+        let time_sync_wait = if cfg.periodic.wait_time_sync {
+            let secs = u64::from(cfg.periodic.time_sync_timeout_minutes).saturating_mul(60);
+            Some(TimeSyncWait::new(Duration::from_secs(secs)))
+        } else {
+            None
         };
 
+        let min_remaining_minutes = cfg.periodic.min_remaining_minutes;
+        ensure!(
+            min_remaining_minutes < crate::weekly::MAX_WEEKLY_MINS,
+            "minimum remaining window length longer than a week: {} minutes",
+            min_remaining_minutes
+        );
+        if !is_min_remaining_reachable(min_remaining_minutes, longest_window_minutes) {
+            log::warn!(
+                "minimum remaining window length ({} minutes) is not shorter than the longest reboot window ({} minutes), finalization may never happen",
+                min_remaining_minutes,
+                longest_window_minutes
+            );
+        }
+
         let strategy = Self {
             schedule: calendar,
+            monthly_schedule: monthly_calendar,
             time_zone,
             tz_name,
+            tz_localtime: cfg.periodic.time_zone == "localtime",
+            blackouts,
+            min_remaining_minutes,
+            time_sync_wait,
         };
         Ok(strategy)
     }
@@ -77,44 +306,77 @@ impl StrategyPeriodic {
         self.tz_name.as_str()
     }
 
+    /// Re-resolve the system's local time zone, if in use, and switch to it
+    /// if it changed since the last check.
+    ///
+    /// This returns whether the time zone changed.
+    pub(crate) fn refresh_time_zone(&mut self) -> Result<bool> {
+        self.refresh_local_time_zone(Path::new(LOCALTIME_PATH))
+    }
+
+    /// Re-resolve the local time zone from the given configuration path.
+    fn refresh_local_time_zone(&mut self, local_time_path: &Path) -> Result<bool> {
+        if !self.tz_localtime {
+            return Ok(false);
+        }
+
+        let (time_zone, tz_name) = Self::get_local_time_zone_info(local_time_path)?;
+        if tz_name == self.tz_name {
+            return Ok(false);
+        }
+
+        log::info!(
+            "local time zone changed from {} to {}, rebuilding periodic schedule",
+            self.tz_name,
+            tz_name
+        );
+        self.time_zone = time_zone;
+        self.tz_name = tz_name;
+        Ok(true)
+    }
+
     /// Get the time zone from `periodic` strategy config, returning a `Tz` and its name
     /// in a tuple.
     #[context("failed to get time zone info from config")]
     fn get_time_zone_info_from_cfg(cfg: &inputs::PeriodicInput) -> Result<(Tz, String)> {
-        let tz;
-        let tz_name;
         if &cfg.time_zone == "localtime" {
-            let local_time_path = Path::new("/etc/localtime");
-            // Use `read_link()` instead of `exists()` because we only want to check for
-            // the existence of the `/etc/localtime` symlink, not whether it points to
-            // a valid file (`read_link()` returns an error if symlink doesn't exist).
-            if read_link(local_time_path).is_err() {
-                let utc = "UTC";
-                tz = Tz::named(utc)
-                    .with_context(|| format!("failed to parse time zone named: {}", utc));
-                tz_name = utc.to_string();
-            } else {
-                // Until `tzfile::Tz` has some way of getting its name or unique identifier, do
-                // the parsing of `/etc/localtime` ourselves here so we can get a `tz_str` to cache.
-                let tz_path = local_time_path.canonicalize()?;
-                let tz_str = tz_path
-                    .strip_prefix(Path::new("/usr/share/zoneinfo"))
-                    .context(
-                        "`/etc/localtime` does not link to a location in `/usr/share/zoneinfo`",
-                    )?
-                    .to_str()
-                    .unwrap_or_default();
-                tz = Tz::named(tz_str)
-                    .with_context(|| format!("failed to parse time zone named: {}", tz_str));
-                tz_name = tz_str.to_string();
-            }
-        } else {
-            tz = Tz::named(&cfg.time_zone)
-                .with_context(|| format!("failed to parse time zone named: {}", &cfg.time_zone));
-            tz_name = cfg.time_zone.to_string();
+            return Self::get_local_time_zone_info(Path::new(LOCALTIME_PATH));
+        }
+
+        let tz = Tz::named(&cfg.time_zone)
+            .with_context(|| format!("failed to parse time zone named: {}", &cfg.time_zone))?;
+        Ok((tz, cfg.time_zone.to_string()))
+    }
+
+    /// Get the system's local time zone from its configuration path (usually
+    /// `/etc/localtime`), returning a `Tz` and its name in a tuple.
+    fn get_local_time_zone_info(local_time_path: &Path) -> Result<(Tz, String)> {
+        // Use `read_link()` instead of `exists()` because we only want to check for
+        // the existence of the `/etc/localtime` symlink, not whether it points to
+        // a valid file (`read_link()` returns an error if symlink doesn't exist).
+        if read_link(local_time_path).is_err() {
+            let utc = "UTC";
+            let tz = Tz::named(utc)
+                .with_context(|| format!("failed to parse time zone named: {}", utc))?;
+            return Ok((tz, utc.to_string()));
         }
 
-        tz.map(|tz| (tz, tz_name))
+        // Until `tzfile::Tz` has some way of getting its name or unique identifier, do
+        // the parsing of `/etc/localtime` ourselves here so we can get a `tz_str` to cache.
+        let tz_path = local_time_path.canonicalize()?;
+        let tz_str = tz_path
+            .strip_prefix(Path::new("/usr/share/zoneinfo"))
+            .with_context(|| {
+                format!(
+                    "`{}` does not link to a location in `/usr/share/zoneinfo`",
+                    local_time_path.display()
+                )
+            })?
+            .to_str()
+            .unwrap_or_default();
+        let tz = Tz::named(tz_str)
+            .with_context(|| format!("failed to parse time zone named: {}", tz_str))?;
+        Ok((tz, tz_str.to_string()))
     }
 
     /// Return the measured length of the schedule, in minutes.
@@ -122,28 +384,143 @@ impl StrategyPeriodic {
         self.schedule.length_minutes()
     }
 
+    /// Return the bounds of the blackout period (if any) containing the given datetime.
+    fn containing_blackout(&self, datetime: &DateTime<Utc>) -> Option<Range<DateTime<Utc>>> {
+        self.blackouts
+            .iter()
+            .filter_map(|period| period.resolve(&self.time_zone))
+            .find(|bounds| bounds.contains(datetime))
+    }
+
+    /// Return whether finalization is allowed at the given datetime.
+    fn allowed_at(&self, datetime: &DateTime<Utc>) -> bool {
+        let dt = (&self.time_zone).from_utc_datetime(&datetime.naive_utc());
+        let in_window =
+            self.schedule.contains_datetime(&dt) || self.monthly_schedule.contains_datetime(&dt);
+        in_window && self.containing_blackout(datetime).is_none()
+    }
+
+    /// Return the start of the next window at or after the given datetime,
+    /// skipping over blackout periods.
+    ///
+    /// This returns `None` if no windows are reachable.
+    fn next_window_datetime(&self, datetime: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut candidate = *datetime;
+        // Each blackout period is skipped at most once, plus a final step.
+        let max_steps = self.blackouts.len().saturating_add(1);
+        for _ in 0..max_steps {
+            let dt = (&self.time_zone).from_utc_datetime(&candidate.naive_utc());
+            let remaining = match (
+                self.schedule.remaining_to_datetime(&dt),
+                self.monthly_schedule.remaining_to_datetime(&dt),
+            ) {
+                (Some(weekly), Some(monthly)) => weekly.min(monthly),
+                (weekly, monthly) => weekly.or(monthly)?,
+            };
+            candidate += remaining;
+
+            match self.containing_blackout(&candidate) {
+                None => return Some(candidate),
+                Some(bounds) => candidate = bounds.end,
+            }
+        }
+
+        None
+    }
+
+    /// Return the duration remaining till the end of the window containing the given
+    /// datetime, accounting for upcoming blackout periods.
+    ///
+    /// This returns `None` if finalization is not allowed at the given datetime.
+    fn remaining_in_window(&self, datetime: &DateTime<Utc>) -> Option<chrono::Duration> {
+        if !self.allowed_at(datetime) {
+            return None;
+        }
+
+        // Coalesce weekly and monthly windows which are adjacent or overlapping.
+        let mut window_end = *datetime;
+        for _ in 0..MAX_COALESCED_STEPS {
+            let dt = (&self.time_zone).from_utc_datetime(&window_end.naive_utc());
+            let remaining = match (
+                self.schedule.remaining_in_window(&dt),
+                self.monthly_schedule.remaining_in_window(&dt),
+            ) {
+                (Some(weekly), Some(monthly)) => weekly.max(monthly),
+                (weekly, monthly) => match weekly.or(monthly) {
+                    Some(r) => r,
+                    None => break,
+                },
+            };
+            if remaining <= chrono::Duration::zero() {
+                break;
+            }
+            window_end += remaining;
+        }
+
+        // Cut the window short at the start of the next blackout period, if any.
+        let mut remaining = window_end.signed_duration_since(*datetime);
+        let next_blackout = self
+            .blackouts
+            .iter()
+            .filter_map(|period| period.resolve(&self.time_zone))
+            .find(|bounds| bounds.start > *datetime);
+        if let Some(bounds) = next_blackout {
+            remaining = remaining.min(bounds.start.signed_duration_since(*datetime));
+        }
+
+        Some(remaining)
+    }
+
+    /// Return the UTC timestamp of the next window start, or the current
+    /// timestamp if already in a window.
+    pub(crate) fn next_window_timestamp(&self) -> Option<i64> {
+        self.next_window_datetime(&Utc::now())
+            .map(|dt| dt.timestamp())
+    }
+
+    /// Return the remaining length of the current window, in minutes.
+    ///
+    /// This returns zero if not currently in a window.
+    pub(crate) fn current_window_remaining_minutes(&self) -> i64 {
+        self.remaining_in_window(&Utc::now())
+            .map(|d| d.num_minutes())
+            .unwrap_or(0)
+    }
+
     /// Return the weekday and time of the next window, in human terms.
     pub(crate) fn human_next_window(&self) -> String {
-        let naive_utc_dt = Utc::now().naive_utc();
-        let dt = (&self.time_zone).from_utc_datetime(&naive_utc_dt);
-        let next_window_minute_in_week = self.schedule.next_window_minute_in_week(&dt);
+        let now = Utc::now();
+        let next = match self.next_window_datetime(&now) {
+            Some(dt) => dt,
+            None => return "not found".to_string(),
+        };
 
-        match next_window_minute_in_week {
-            Some(minute_in_week) => {
-                let (weekday, hour, minute) = utils::weekly_minute_as_weekday_time(minute_in_week);
-                format!(
-                    "at {}:{:0>2} on {} ({}), subject to time zone caveats.",
-                    hour, minute, weekday, self.tz_name
-                )
-            }
-            None => "not found".to_string(),
+        let dt = (&self.time_zone).from_utc_datetime(&next.naive_utc());
+        let minute_in_week = utils::datetime_as_weekly_minute(&dt);
+        let (weekday, hour, minute) = utils::weekly_minute_as_weekday_time(minute_in_week);
+        if next.signed_duration_since(now) < chrono::Duration::weeks(1) {
+            format!(
+                "at {}:{:0>2} on {} ({}), subject to time zone caveats.",
+                hour, minute, weekday, self.tz_name
+            )
+        } else {
+            format!(
+                "at {}:{:0>2} on {} {} ({}), subject to time zone caveats.",
+                hour,
+                minute,
+                weekday,
+                dt.format("%Y-%m-%d"),
+                self.tz_name
+            )
         }
     }
 
     /// Return the remaining duration to next window, in human terms.
     pub(crate) fn human_remaining(&self) -> String {
-        let datetime = chrono::Utc::now();
-        let remaining = self.schedule.remaining_to_datetime(&datetime);
+        let now = chrono::Utc::now();
+        let remaining = self
+            .next_window_datetime(&now)
+            .map(|next| next.signed_duration_since(now));
         match remaining {
             None => "not found".to_string(),
             Some(ref d) => WeeklyCalendar::human_remaining_duration(d)
@@ -153,9 +530,21 @@ impl StrategyPeriodic {
 
     /// Return some human-friendly information about `PeriodicStrategy`'s calendar.
     pub(crate) fn calendar_summary(&self) -> String {
+        let monthly = match self.monthly_schedule.len() {
+            0 => String::new(),
+            1 => "; 1 monthly window".to_string(),
+            n => format!("; {} monthly windows", n),
+        };
+        let blackouts = match self.blackouts.len() {
+            0 => String::new(),
+            1 => "; 1 blackout period".to_string(),
+            n => format!("; {} blackout periods", n),
+        };
         format!(
-            "total schedule length {} minutes; next window {}",
+            "total schedule length {} minutes{}{}; next window {}",
             self.schedule_length_minutes(),
+            monthly,
+            blackouts,
             if self.tz_name() != "UTC" || self.tz_name() != "Etc/UTC" {
                 self.human_next_window()
             } else {
@@ -166,15 +555,35 @@ impl StrategyPeriodic {
         )
     }
 
+    /// Return whether finalization can start at the given datetime, that is whether
+    /// enough time is left in the current window.
+    fn can_finalize_at(&self, datetime: &DateTime<Utc>) -> bool {
+        let min_remaining = chrono::Duration::minutes(i64::from(self.min_remaining_minutes));
+        match self.remaining_in_window(datetime) {
+            Some(remaining) => remaining >= min_remaining,
+            None => false,
+        }
+    }
+
     /// Check if finalization is allowed.
     pub(crate) fn can_finalize(&self) -> Pin<Box<dyn Future<Output = Result<bool, Error>>>> {
-        let naive_utc_dt = Utc::now().naive_utc();
-        let dt = (&self.time_zone).from_utc_datetime(&naive_utc_dt);
-        let allowed = self.schedule.contains_datetime(&dt);
+        let strategy = self.clone();
+        let res = async move {
+            let time_sync_blocked = match &strategy.time_sync_wait {
+                Some(wait) => wait.is_blocked(is_time_synchronized).await,
+                None => false,
+            };
+            TIME_SYNC_BLOCKED.set(i64::from(time_sync_blocked));
+            if time_sync_blocked {
+                log::info!("periodic strategy: waiting for system clock synchronization");
+                return Ok(false);
+            }
 
-        trace!("periodic strategy, can finalize updates: {}", allowed);
+            let allowed = strategy.can_finalize_at(&Utc::now());
 
-        let res = future::ok(allowed);
+            trace!("periodic strategy, can finalize updates: {}", allowed);
+            Ok(allowed)
+        };
         Box::pin(res)
     }
 
@@ -221,6 +630,177 @@ mod tests {
         assert_eq!(strategy.schedule.total_length_minutes(), 3145);
     }
 
+    #[test]
+    fn test_blackout_config() {
+        let cfg = parse_config_input("tests/fixtures/21-periodic-sample-blackout.toml");
+        let strategy = StrategyPeriodic::new(cfg.updates).unwrap();
+        assert_eq!(strategy.blackouts.len(), 2);
+        assert!(strategy.blackouts[0].start < strategy.blackouts[1].start);
+
+        let mut cfg = parse_config_input("tests/fixtures/21-periodic-sample-blackout.toml");
+        cfg.updates.periodic.blackouts = vec![inputs::PeriodicBlackoutInput {
+            start: "2026-12-19T00:00".to_string(),
+            end: "2026-12-18T00:00".to_string(),
+        }];
+        StrategyPeriodic::new(cfg.updates).unwrap_err();
+
+        let mut cfg = parse_config_input("tests/fixtures/21-periodic-sample-blackout.toml");
+        cfg.updates.periodic.blackouts = vec![inputs::PeriodicBlackoutInput {
+            start: "2026-12-19".to_string(),
+            end: "2026-12-20T00:00".to_string(),
+        }];
+        StrategyPeriodic::new(cfg.updates).unwrap_err();
+    }
+
+    #[test]
+    fn test_blackout_allowed_at() {
+        let cfg = parse_config_input("tests/fixtures/21-periodic-sample-blackout.toml");
+        let strategy = StrategyPeriodic::new(cfg.updates).unwrap();
+
+        let cases = vec![
+            // Regular window, no blackout.
+            ("2026-12-12T22:30:00+00:00", true),
+            // Outside of any window.
+            ("2026-12-12T21:59:00+00:00", false),
+            // Window within blackout.
+            ("2026-12-19T22:30:00+00:00", false),
+            ("2027-01-02T22:30:00+00:00", false),
+            // First window after blackout.
+            ("2027-01-09T22:30:00+00:00", true),
+        ];
+        for (input, allowed) in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap().into();
+            assert_eq!(strategy.allowed_at(&datetime), allowed, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_blackout_next_window() {
+        let cfg = parse_config_input("tests/fixtures/21-periodic-sample-blackout.toml");
+        let strategy = StrategyPeriodic::new(cfg.updates).unwrap();
+
+        let cases = vec![
+            ("2026-12-07T00:00:00+00:00", "2026-12-12T22:00:00+00:00"),
+            ("2026-12-12T22:30:00+00:00", "2026-12-12T22:30:00+00:00"),
+            ("2026-12-14T00:00:00+00:00", "2027-01-09T22:00:00+00:00"),
+            ("2026-12-25T00:00:00+00:00", "2027-01-09T22:00:00+00:00"),
+            ("2026-11-21T23:30:00+00:00", "2026-12-05T22:00:00+00:00"),
+        ];
+        for (input, expected) in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap().into();
+            let expected: DateTime<Utc> = DateTime::parse_from_rfc3339(expected).unwrap().into();
+            let next = strategy.next_window_datetime(&datetime).unwrap();
+            assert_eq!(next, expected, "{}", input);
+        }
+
+        // Blackouts do not affect an empty schedule.
+        let default = StrategyPeriodic::default();
+        assert_eq!(default.next_window_datetime(&Utc::now()), None);
+    }
+
+    #[test]
+    fn test_remaining_in_window() {
+        let mut cfg = parse_config_input("tests/fixtures/21-periodic-sample-blackout.toml");
+        cfg.updates.periodic.monthly_intervals = vec![inputs::PeriodicMonthlyIntervalInput {
+            occurrence: "second".to_string(),
+            start_day: "Sat".to_string(),
+            start_time: "23:00".to_string(),
+            length_minutes: 120,
+        }];
+        cfg.updates
+            .periodic
+            .blackouts
+            .push(inputs::PeriodicBlackoutInput {
+                start: "2026-11-21T22:30".to_string(),
+                end: "2026-11-22T00:00".to_string(),
+            });
+        let strategy = StrategyPeriodic::new(cfg.updates).unwrap();
+
+        let cases = vec![
+            // Weekly window only (first Saturday of the month).
+            ("2026-12-05T22:00:00+00:00", Some(60)),
+            ("2026-12-05T22:45:00+00:00", Some(15)),
+            // Cut short by upcoming blackout.
+            ("2026-11-21T22:00:00+00:00", Some(30)),
+            // Weekly window coalesced with monthly window (second Saturday).
+            ("2026-12-12T22:00:00+00:00", Some(180)),
+            ("2026-12-13T00:30:00+00:00", Some(30)),
+            // End minute is included.
+            ("2026-12-13T01:00:00+00:00", Some(0)),
+            // Not in a window.
+            ("2026-12-13T01:01:00+00:00", None),
+        ];
+        for (input, remaining) in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap().into();
+            let output = strategy
+                .remaining_in_window(&datetime)
+                .map(|d| d.num_minutes());
+            assert_eq!(output, remaining, "{}", input);
+        }
+
+        let default = StrategyPeriodic::default();
+        assert_eq!(default.current_window_remaining_minutes(), 0);
+        assert_eq!(default.next_window_timestamp(), None);
+    }
+
+    #[test]
+    fn test_min_remaining_minutes() {
+        let mut cfg = parse_config_input("tests/fixtures/21-periodic-sample-blackout.toml");
+        cfg.updates.periodic.min_remaining_minutes = 20;
+        let strategy = StrategyPeriodic::new(cfg.updates).unwrap();
+
+        let cases = vec![
+            ("2026-12-05T22:00:00+00:00", true),
+            ("2026-12-05T22:40:00+00:00", true),
+            ("2026-12-05T22:41:00+00:00", false),
+            ("2026-12-05T21:59:00+00:00", false),
+        ];
+        for (input, expected) in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap().into();
+            assert_eq!(strategy.can_finalize_at(&datetime), expected, "{}", input);
+        }
+
+        let mut cfg = parse_config_input("tests/fixtures/21-periodic-sample-blackout.toml");
+        cfg.updates.periodic.min_remaining_minutes = crate::weekly::MAX_WEEKLY_MINS;
+        StrategyPeriodic::new(cfg.updates).unwrap_err();
+
+        assert!(is_min_remaining_reachable(0, 60));
+        assert!(is_min_remaining_reachable(20, 60));
+        assert!(!is_min_remaining_reachable(60, 60));
+        assert!(!is_min_remaining_reachable(90, 60));
+        assert!(!is_min_remaining_reachable(20, 0));
+    }
+
+    #[test]
+    fn test_monthly_config() {
+        let cfg = parse_config_input("tests/fixtures/22-periodic-sample-monthly.toml");
+        let strategy = StrategyPeriodic::new(cfg.updates).unwrap();
+        assert_eq!(strategy.schedule_length_minutes(), 0);
+        assert_eq!(strategy.monthly_schedule.len(), 3);
+
+        let cases = vec![
+            ("2026-11-10T22:30:00+00:00", true),
+            ("2026-11-15T03:59:00+00:00", true),
+            ("2026-11-08T03:30:00+00:00", false),
+        ];
+        for (input, allowed) in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap().into();
+            assert_eq!(strategy.allowed_at(&datetime), allowed, "{}", input);
+        }
+
+        let datetime = DateTime::parse_from_rfc3339("2026-11-15T05:00:00+00:00")
+            .unwrap()
+            .into();
+        let expected: DateTime<Utc> = DateTime::parse_from_rfc3339("2026-12-06T03:00:00+00:00")
+            .unwrap()
+            .into();
+        assert_eq!(strategy.next_window_datetime(&datetime), Some(expected));
+
+        let mut cfg = parse_config_input("tests/fixtures/22-periodic-sample-monthly.toml");
+        cfg.updates.periodic.monthly_intervals[0].occurrence = "fifth".to_string();
+        StrategyPeriodic::new(cfg.updates).unwrap_err();
+    }
+
     #[test]
     fn test_non_utc_time() {
         use chrono::{Datelike, Timelike};
@@ -305,6 +885,81 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_time_sync_wait() {
+        let runtime = rt::Runtime::new().unwrap();
+        let unreachable = |msg| -> future::Ready<Result<bool>> { panic!("{}", msg) };
+
+        let wait = TimeSyncWait::new(Duration::from_secs(3600));
+        assert!(runtime.block_on(wait.is_blocked(|| future::ok(false))));
+        let no_timedated = || future::err(anyhow::anyhow!("no timedated"));
+        assert!(runtime.block_on(wait.is_blocked(no_timedated)));
+        // Synchronization is latched, also across clones.
+        let cloned = wait.clone();
+        assert!(!runtime.block_on(wait.is_blocked(|| future::ok(true))));
+        let check = || unreachable("checked after synchronization");
+        assert!(!runtime.block_on(cloned.is_blocked(check)));
+
+        // Timeouts are latched too.
+        let expired = TimeSyncWait::new(Duration::from_secs(0));
+        assert!(!runtime.block_on(expired.is_blocked(|| future::ok(false))));
+        let check = || unreachable("checked after timeout");
+        assert!(!runtime.block_on(expired.is_blocked(check)));
+
+        // A clock synchronized in the meantime is detected before the timeout.
+        let expired = TimeSyncWait::new(Duration::from_secs(0));
+        assert!(!runtime.block_on(expired.is_blocked(|| future::ok(true))));
+
+        let cfg = parse_config_input("tests/fixtures/20-periodic-sample.toml");
+        let strategy = StrategyPeriodic::new(cfg.updates).unwrap();
+        assert!(strategy.time_sync_wait.is_none());
+        let mut cfg = parse_config_input("tests/fixtures/20-periodic-sample.toml");
+        cfg.updates.periodic.wait_time_sync = true;
+        let strategy = StrategyPeriodic::new(cfg.updates).unwrap();
+        let wait = strategy.time_sync_wait.unwrap();
+        assert_eq!(wait.timeout, Duration::from_secs(60 * 60));
+    }
+
+    #[test]
+    fn test_refresh_local_time_zone() {
+        use std::os::unix::fs::symlink;
+
+        let tmpdir = tempfile::tempdir().unwrap();
+        let local_time_path = tmpdir.path().join("localtime");
+        symlink("/usr/share/zoneinfo/Europe/Berlin", &local_time_path).unwrap();
+
+        // Explicitly configured time zones are never refreshed.
+        let config = parse_config_input("tests/fixtures/30-periodic-sample-non-utc.toml");
+        let mut strategy = StrategyPeriodic::new(config.updates).unwrap();
+        assert!(!strategy.tz_localtime);
+        assert!(!strategy.refresh_local_time_zone(&local_time_path).unwrap());
+        assert_eq!(strategy.tz_name(), "America/Toronto");
+
+        // Avoid depending on host configuration by flipping the flag by hand.
+        strategy.tz_localtime = true;
+        assert!(strategy.refresh_local_time_zone(&local_time_path).unwrap());
+        assert_eq!(strategy.tz_name(), "Europe/Berlin");
+        assert!(!strategy.refresh_local_time_zone(&local_time_path).unwrap());
+
+        std::fs::remove_file(&local_time_path).unwrap();
+        symlink("/usr/share/zoneinfo/America/New_York", &local_time_path).unwrap();
+        assert!(strategy.refresh_local_time_zone(&local_time_path).unwrap());
+        assert_eq!(strategy.tz_name(), "America/New_York");
+        assert_eq!(strategy.time_zone, Tz::named("America/New_York").unwrap());
+
+        // Missing symlink falls back to UTC.
+        std::fs::remove_file(&local_time_path).unwrap();
+        assert!(strategy.refresh_local_time_zone(&local_time_path).unwrap());
+        assert_eq!(strategy.tz_name(), "UTC");
+
+        // Invalid target is an error, and time zone is left untouched.
+        symlink(tmpdir.path(), &local_time_path).unwrap();
+        strategy
+            .refresh_local_time_zone(&local_time_path)
+            .unwrap_err();
+        assert_eq!(strategy.tz_name(), "UTC");
+    }
+
     fn parse_config_input(config_path: &str) -> inputs::ConfigInput {
         let content = std::fs::read_to_string(config_path).unwrap();
         let frag: fragments::ConfigFragment = toml::from_str(&content).unwrap();
@@ -1,6 +1,10 @@
 //! Update agent actor.
 
 use super::{UpdateAgent, UpdateAgentInfo, UpdateAgentMachineState, UpdateAgentState};
+use crate::cincinnati::StagedUpdate;
+use crate::notifications::webhook::{
+    DeliverEvent, SendEvent, WebhookEvent, WebhookEventKind, FINALIZING_DELIVERY_TIMEOUT,
+};
 use crate::rpm_ostree::{self, Release};
 use crate::utils;
 use actix::prelude::*;
@@ -11,12 +15,19 @@ use log::trace;
 use prometheus::{IntCounter, IntCounterVec, IntGauge};
 use std::collections::BTreeSet;
 use std::mem::discriminant;
+use std::path::Path;
 use std::rc::Rc;
 use std::time::Duration;
 
 /// Label for finalization attempts blocked due to active interactive user sessions.
 pub static ACTIVE_USERSESSIONS_LABEL: &str = "active_usersessions";
 
+/// Label for finalization attempts blocked due to a postponement requested by users.
+pub static USER_POSTPONEMENT_LABEL: &str = "user_postponement";
+
+/// Label for finalization attempts blocked due to reboot rate limiting.
+pub static REBOOT_RATE_LIMIT_LABEL: &str = "reboot_rate_limit";
+
 lazy_static::lazy_static! {
     static ref LAST_REFRESH: IntGauge = register_int_gauge!(opts!(
         "zincati_update_agent_last_refresh_timestamp",
@@ -31,6 +42,10 @@ lazy_static::lazy_static! {
         "Total number of finalization attempts blocked due to reasons unrelated to update strategy.",
         &["reason"]
     ).unwrap();
+    static ref URGENT_FINALIZATIONS: IntCounter = register_int_counter!(opts!(
+        "zincati_update_agent_urgent_finalization_attempts",
+        "Total number of attempts to finalize an urgent update, bypassing reboot windows and user sessions grace period."
+    )).unwrap();
     static ref FINALIZATION_SUCCESS: IntCounter = register_int_counter!(opts!(
         "zincati_update_agent_finalization_successes",
         "Total number of successful update finalizations by the update agent."
@@ -67,6 +82,103 @@ impl Handler<LastRefresh> for UpdateAgent {
     }
 }
 
+pub struct StrategyWindow {}
+
+impl Message for StrategyWindow {
+    type Result = (i64, i64);
+}
+
+impl Handler<StrategyWindow> for UpdateAgent {
+    type Result = MessageResult<StrategyWindow>;
+
+    fn handle(&mut self, _msg: StrategyWindow, _ctx: &mut Self::Context) -> Self::Result {
+        trace!("agent: request to get strategy window details");
+        MessageResult(self.info.strategy.window_details())
+    }
+}
+
+/// Request to deploy a specific release, by version.
+pub struct DeployVersion {
+    pub version: String,
+}
+
+impl Message for DeployVersion {
+    type Result = Result<Release>;
+}
+
+impl Handler<DeployVersion> for UpdateAgent {
+    type Result = ResponseActFuture<Self, Result<Release>>;
+
+    fn handle(&mut self, msg: DeployVersion, _ctx: &mut Self::Context) -> Self::Result {
+        trace!("agent: request to deploy release '{}'", msg.version);
+
+        let update_agent_info = self.info.clone();
+        let lock = Rc::clone(&self.state);
+        let last_changed = Rc::clone(&self.state_changed);
+        let deploy = async move {
+            // Look up the release before locking state, as this hits the network.
+            let release = update_agent_info.fetch_requested(msg.version).await?;
+            let mut agent_state_guard = lock.write().await;
+            let prev_state = agent_state_guard.machine_state.clone();
+            let release = update_agent_info
+                .request_deploy(&mut agent_state_guard, release)
+                .await?;
+            last_changed.set(chrono::Utc::now());
+            update_agent_info.notify_transition(&prev_state, &agent_state_guard.machine_state);
+            Ok(release)
+        };
+
+        Box::pin(deploy.into_actor(self))
+    }
+}
+
+/// Request to postpone the finalization of a staged update.
+pub struct PostponeFinalization {
+    pub duration: Duration,
+    pub user: String,
+    pub reason: String,
+}
+
+impl Message for PostponeFinalization {
+    type Result = Result<chrono::DateTime<chrono::Utc>>;
+}
+
+impl Handler<PostponeFinalization> for UpdateAgent {
+    type Result = ResponseActFuture<Self, Result<chrono::DateTime<chrono::Utc>>>;
+
+    fn handle(&mut self, msg: PostponeFinalization, _ctx: &mut Self::Context) -> Self::Result {
+        trace!("agent: request to postpone finalization");
+
+        let max_postponement = self.info.max_postponement;
+        let lock = Rc::clone(&self.state);
+        let postpone = async move {
+            if max_postponement.is_zero() {
+                bail!("postponing reboots is not allowed by configuration");
+            }
+
+            let mut agent_state_guard = lock.write().await;
+            let until = agent_state_guard.postpone_finalization(
+                msg.duration,
+                max_postponement,
+                chrono::Utc::now(),
+            )?;
+            log::info!(
+                "reboot postponed until {} by user '{}', reason: {}",
+                until.format("%a %Y-%m-%d %H:%M:%S %Z"),
+                msg.user,
+                if msg.reason.is_empty() {
+                    "(none given)"
+                } else {
+                    &msg.reason
+                }
+            );
+            Ok(until)
+        };
+
+        Box::pin(postpone.into_actor(self))
+    }
+}
+
 pub(crate) struct RefreshTick {}
 
 impl Message for RefreshTick {
@@ -77,6 +189,11 @@ impl Handler<RefreshTick> for UpdateAgent {
     type Result = ResponseActFuture<Self, Result<(), Error>>;
 
     fn handle(&mut self, _msg: RefreshTick, _ctx: &mut Self::Context) -> Self::Result {
+        // Pick up any runtime change to the local time zone, before cloning `info`.
+        if let Err(e) = self.info.strategy.refresh_time_zone() {
+            log::warn!("failed to refresh time zone: {:#}", e);
+        }
+
         // We need a clone of `info` because we need to move it into futures to ensure a
         // long enough lifetime.
         let update_agent_info = self.info.clone();
@@ -88,6 +205,7 @@ impl Handler<RefreshTick> for UpdateAgent {
             // Consider `LAST_REFRESH` time to be when lock is acquired.
             let tick_timestamp = chrono::Utc::now();
             LAST_REFRESH.set(tick_timestamp.timestamp());
+            update_agent_info.strategy.refresh_metrics();
 
             trace!(
                 "update agent tick, current state: {:?}",
@@ -124,9 +242,14 @@ impl Handler<RefreshTick> for UpdateAgent {
                 }
                 UpdateAgentMachineState::UpdateStaged((release, _)) => {
                     let update = release.clone();
-                    update_agent_info
-                        .tick_finalize_update(&mut agent_state_guard.machine_state, update)
-                        .await
+                    let still_current = update_agent_info
+                        .tick_recheck_staged(&mut agent_state_guard, &update)
+                        .await;
+                    if still_current {
+                        update_agent_info
+                            .tick_finalize_update(&mut agent_state_guard, update)
+                            .await
+                    }
                 }
                 UpdateAgentMachineState::UpdateFinalized(release) => {
                     let update = release.clone();
@@ -143,15 +266,31 @@ impl Handler<RefreshTick> for UpdateAgent {
                 // ensure that we only set it when a `RwLock` to state is acquired.
                 last_changed.set(chrono::Utc::now());
             }
+            update_agent_info.notify_transition(&prev_state, &agent_state_guard.machine_state);
 
-            Self::refresh_delay(
+            let refresh_delay = Self::refresh_delay(
                 update_agent_info.steady_interval,
                 &prev_state,
                 &agent_state_guard.machine_state,
-            )
+            );
+
+            // Back off from remote services which are currently unavailable.
+            let backoff = Self::backoff_delay(
+                &agent_state_guard.machine_state,
+                update_agent_info.cincinnati.retry_delay(),
+                update_agent_info.strategy.retry_delay(),
+            );
+            let refresh_delay = match (refresh_delay, backoff) {
+                (Some(delay), Some(backoff)) => Some(delay.max(backoff)),
+                _ => refresh_delay,
+            };
+
+            let renewal = update_agent_info.lease_renewal_delay(&agent_state_guard.machine_state);
+            (refresh_delay, renewal)
         };
         let state_action = state_action.into_actor(self);
-        let update_machine = state_action.then(|pause, _actor, ctx| {
+        let update_machine = state_action.then(|(pause, renewal), actor, ctx| {
+            actor.schedule_lease_renewal(ctx, renewal);
             if let Some(pause) = pause {
                 log::trace!(
                     "scheduling next agent refresh in {} seconds",
@@ -168,7 +307,56 @@ impl Handler<RefreshTick> for UpdateAgent {
     }
 }
 
+/// Request to renew the lease on a held reboot slot, if any and due.
+///
+/// Renewals are scheduled independently of state machine refreshes, so that
+/// they do not trigger any further update logic.
+pub(crate) struct RenewLease {}
+
+impl Message for RenewLease {
+    type Result = ();
+}
+
+impl Handler<RenewLease> for UpdateAgent {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, _msg: RenewLease, _ctx: &mut Self::Context) -> Self::Result {
+        self.lease_renewal = None;
+
+        let update_agent_info = self.info.clone();
+        let lock = Rc::clone(&self.state);
+        let renew = async move {
+            let mut agent_state_guard = lock.write().await;
+            let release = match &agent_state_guard.machine_state {
+                UpdateAgentMachineState::UpdateStaged((release, _))
+                | UpdateAgentMachineState::UpdateFinalized(release) => release.clone(),
+                _ => return None,
+            };
+            update_agent_info
+                .tick_renew_lease(&mut agent_state_guard, &release)
+                .await;
+            update_agent_info.lease_renewal_delay(&agent_state_guard.machine_state)
+        };
+        let renew = renew
+            .into_actor(self)
+            .map(|renewal, actor, ctx| actor.schedule_lease_renewal(ctx, renewal));
+
+        Box::pin(renew)
+    }
+}
+
 impl UpdateAgent {
+    /// Schedule a renewal of the lease on a held reboot slot, replacing any pending one.
+    fn schedule_lease_renewal(&mut self, ctx: &mut Context<Self>, after: Option<Duration>) {
+        if let Some(handle) = self.lease_renewal.take() {
+            ctx.cancel_future(handle);
+        }
+        if let Some(after) = after {
+            log::trace!("scheduling lease renewal in {} seconds", after.as_secs());
+            self.lease_renewal = Some(ctx.notify_later(RenewLease {}, after));
+        }
+    }
+
     /// Schedule an immediate refresh of the state machine.
     pub fn tick_now(ctx: &mut Context<Self>) {
         ctx.notify(RefreshTick {})
@@ -201,6 +389,27 @@ impl UpdateAgent {
         Some(refresh_delay)
     }
 
+    /// Backoff delay before refreshing in `state`, for the remote services
+    /// queried in that state which are currently unavailable.
+    fn backoff_delay(
+        state: &UpdateAgentMachineState,
+        cincinnati: Option<Duration>,
+        strategy: Option<Duration>,
+    ) -> Option<Duration> {
+        match state {
+            // Reporting steady state goes through the update strategy.
+            UpdateAgentMachineState::Initialized => strategy,
+            // Checking for updates goes through Cincinnati.
+            UpdateAgentMachineState::ReportedSteady | UpdateAgentMachineState::NoNewUpdate => {
+                cincinnati
+            }
+            // Finalizing goes through the update strategy, while re-checks
+            // against Cincinnati are skipped when backing off.
+            UpdateAgentMachineState::UpdateStaged(_) => strategy,
+            _ => None,
+        }
+    }
+
     /// Return whether a transition from `prev_state` to `cur_state` warrants an immediate
     /// tick.
     fn should_tick_immediately(
@@ -292,6 +501,14 @@ impl UpdateAgentInfo {
             state.machine_state.end();
         }
 
+        // Clear any stale pending update information, e.g. from before an
+        // agent restart.
+        if self.enabled {
+            if let Err(e) = run_motd_helper(&["unset"]) {
+                log::error!("{:#}", e);
+            }
+        }
+
         utils::notify_ready();
         utils::update_unit_status(status);
     }
@@ -326,6 +543,15 @@ impl UpdateAgentInfo {
 
         match release {
             Some(release) => {
+                if let Some(not_before) = self.rollout_pending(state, &release, timestamp_now) {
+                    utils::update_unit_status(&format!(
+                        "found update on remote: {}; staging delayed until {} due to rollout spread",
+                        release.version,
+                        not_before.format("%a %Y-%m-%d %H:%M:%S %Z")
+                    ));
+                    state.machine_state.no_new_update();
+                    return;
+                }
                 utils::update_unit_status(&format!("found update on remote: {}", release.version));
                 state.machine_state.update_available(release);
             }
@@ -335,6 +561,53 @@ impl UpdateAgentInfo {
         }
     }
 
+    /// Return the time before which a release seen on remote should not be staged,
+    /// if its local rollout delay has not passed yet.
+    fn rollout_pending(
+        &self,
+        state: &mut UpdateAgentState,
+        release: &Release,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        if self.rollout_spread.is_zero() {
+            return None;
+        }
+
+        // Restore the first-seen timestamp from a previous run, if any.
+        let path = Path::new(super::ROLLOUT_FIRST_SEEN_PATH);
+        if state.rollout_first_seen.is_none() {
+            state.rollout_first_seen = super::read_rollout_first_seen(path).unwrap_or_else(|e| {
+                log::error!("failed to read rollout first-seen timestamp: {:#}", e);
+                None
+            });
+        }
+
+        let (first_seen, newly_seen) = state.record_seen_release(release, now);
+        let delay = super::rollout_delay(
+            &self.identity.node_uuid.lower_hex(),
+            &release.payload.to_string(),
+            self.rollout_spread,
+        );
+        let not_before = first_seen + chrono::Duration::from_std(delay).ok()?;
+        if newly_seen {
+            let payload = release.payload.to_string();
+            if let Err(e) = super::write_rollout_first_seen(path, &payload, first_seen) {
+                log::error!("failed to record rollout first-seen timestamp: {:#}", e);
+            }
+            log::info!(
+                "found update on remote: {}, staging delayed until {} due to rollout spread",
+                release.version,
+                not_before.format("%a %Y-%m-%d %H:%M:%S %Z")
+            );
+        }
+
+        if now < not_before {
+            Some(not_before)
+        } else {
+            None
+        }
+    }
+
     /// Try to stage an update.
     async fn tick_stage_update(&self, state: &mut UpdateAgentState, release: Release) {
         trace!("trying to stage an update");
@@ -349,6 +622,12 @@ impl UpdateAgentInfo {
             log::error!("failed to stage deployment: {}", e);
             let fail_count =
                 UpdateAgentInfo::deploy_attempt_failed(&release, &mut state.machine_state);
+            if state.machine_state == UpdateAgentMachineState::NoNewUpdate
+                && state.requested_release.as_ref() == Some(&release)
+            {
+                // The requested release has been abandoned.
+                state.requested_release = None;
+            }
             let msg = format!(
                 "trying to stage {} (failed attempts: {})",
                 release.version, fail_count,
@@ -358,25 +637,223 @@ impl UpdateAgentInfo {
         };
     }
 
+    /// Look up a specific release on remote, as an explicitly requested update target.
+    async fn fetch_requested(&self, version: String) -> Result<Release> {
+        if !self.enabled {
+            bail!("auto-updates logic is disabled by configuration");
+        }
+
+        self.cincinnati
+            .fetch_target_version(&self.identity, version, self.allow_downgrade)
+            .await
+    }
+
+    /// Set a specific release as the update target, on explicit request.
+    ///
+    /// The target is picked up on the next refresh, going through the usual
+    /// staging and finalization logic.
+    async fn request_deploy(
+        &self,
+        state: &mut UpdateAgentState,
+        release: Release,
+    ) -> Result<Release> {
+        match &state.machine_state {
+            UpdateAgentMachineState::ReportedSteady | UpdateAgentMachineState::NoNewUpdate => {
+                state.machine_state.update_available(release.clone());
+            }
+            UpdateAgentMachineState::UpdateStaged((staged, _)) => {
+                if staged.payload == release.payload {
+                    bail!("release '{}' is already staged", release.version);
+                }
+                self.cleanup_pending_deployment().await;
+                self.refresh_update_motd(state, None);
+                state.machine_state.update_superseded(release.clone());
+            }
+            other => bail!("cannot deploy a release while in state {:?}", other),
+        }
+
+        // Explicitly requested releases may be denylisted, e.g. after a rollback.
+        state.denylist.retain(|r| r.payload != release.payload);
+        state.requested_release = Some(release.clone());
+
+        let status_msg = format!("deployment of release {} requested", release.version);
+        log::info!("{}", status_msg);
+        utils::update_unit_status(&status_msg);
+        Ok(release)
+    }
+
+    /// Periodically re-check a staged update against remote, replacing or
+    /// abandoning it if the update graph changed in the meantime.
+    ///
+    /// Returns whether the staged update is still the current target.
+    async fn tick_recheck_staged(&self, state: &mut UpdateAgentState, release: &Release) -> bool {
+        // Explicitly requested releases are kept as is.
+        if state.requested_release.as_ref() == Some(release) {
+            return true;
+        }
+
+        // Do not hold finalization back while Cincinnati is unavailable.
+        if self.cincinnati.retry_delay().is_some() {
+            trace!("skipping staged update re-check, backing off from Cincinnati");
+            return true;
+        }
+
+        let now = chrono::Utc::now();
+        let recheck_interval =
+            chrono::Duration::from_std(self.steady_interval).unwrap_or(chrono::Duration::MAX);
+        match state.staged_checked {
+            Some(checked) if now.signed_duration_since(checked) < recheck_interval => return true,
+            _ => state.staged_checked = Some(now),
+        }
+
+        trace!("re-checking staged update on remote");
+        let outcome = self
+            .cincinnati
+            .check_staged_update(
+                &self.identity,
+                release.clone(),
+                state.denylist.clone(),
+                self.allow_downgrade,
+            )
+            .await;
+
+        match outcome {
+            Some(StagedUpdate::Superseded(newer)) => {
+                if self.rollout_pending(state, &newer, now).is_some() {
+                    return true;
+                }
+                log::info!(
+                    "staged update {} superseded by newer release {}, re-targeting",
+                    release.version,
+                    newer.version
+                );
+                self.cleanup_pending_deployment().await;
+                self.refresh_update_motd(state, None);
+                utils::update_unit_status(&format!("found update on remote: {}", newer.version));
+                state.machine_state.update_superseded(newer);
+                false
+            }
+            Some(StagedUpdate::Pulled) => {
+                log::warn!(
+                    "staged update {} is not a valid update target anymore, abandoning it",
+                    release.version
+                );
+                self.cleanup_pending_deployment().await;
+                self.refresh_update_motd(state, None);
+                state.denylist.insert(release.clone());
+                utils::update_unit_status(&format!("abandoned staged update: {}", release.version));
+                state.machine_state.update_abandoned();
+                false
+            }
+            Some(StagedUpdate::Current) | None => true,
+        }
+    }
+
+    /// Return the time left until the lease on a held reboot slot should be
+    /// renewed, if any is held in the current state.
+    fn lease_renewal_delay(&self, state: &UpdateAgentMachineState) -> Option<Duration> {
+        match state {
+            UpdateAgentMachineState::UpdateStaged(_)
+            | UpdateAgentMachineState::UpdateFinalized(_) => self.strategy.lease_renewal_delay(),
+            _ => None,
+        }
+    }
+
+    /// Renew the lease on a held reboot slot, if any and due.
+    async fn tick_renew_lease(&self, state: &mut UpdateAgentState, release: &Release) {
+        if !self.strategy.renew_lease(release).await {
+            log::warn!("failed to renew the lease on the reboot slot, it may have expired");
+            // A lapsed lease voids any previous strategy approval.
+            if let Some(postponement) = state.postponement.as_mut() {
+                postponement.strategy_approved = false;
+            }
+        }
+    }
+
     /// Try to finalize an update.
-    async fn tick_finalize_update(&self, state: &mut UpdateAgentMachineState, release: Release) {
+    async fn tick_finalize_update(&self, state: &mut UpdateAgentState, release: Release) {
         trace!("trying to finalize an update");
         FINALIZATION_ATTEMPTS.inc();
 
-        let strategy_can_finalize = self.strategy.can_finalize().await;
-        if !strategy_can_finalize {
+        // Check this before the strategy, so that no reboot slot (e.g. a FleetLock
+        // lock) gets acquired only to be left unused.
+        let last_finalization = self.last_finalization();
+        if let Some(not_before) = super::reboot_rate_limited_until(
+            last_finalization,
+            self.min_interval_between_reboots,
+            chrono::Utc::now(),
+        ) {
+            FINALIZATION_BLOCKED
+                .with_label_values(&[REBOOT_RATE_LIMIT_LABEL])
+                .inc();
+            let not_before = not_before.format("%a %Y-%m-%d %H:%M:%S %Z");
+            utils::update_unit_status(&format!(
+                "update staged: {}; reboot delayed until {} due to reboot rate limit",
+                &release.version, not_before
+            ));
+            self.refresh_update_motd(
+                state,
+                Some((
+                    &release,
+                    format!("after {}, due to reboot rate limit", not_before),
+                )),
+            );
+            state.machine_state.update_staged(release);
+            return;
+        }
+
+        let urgent = self.honor_urgent && release.urgent;
+        if urgent {
+            log::warn!(
+                "release '{}' is marked as urgent, bypassing reboot windows and user sessions grace period",
+                release.version
+            );
+        }
+
+        // A strategy approval is preserved while finalization is postponed by users.
+        let strategy_approved = state.postponement_approved(&release, chrono::Utc::now());
+        let postponement = state.postponement.clone().filter(|p| p.release == release);
+        if !strategy_approved && !self.strategy.can_finalize(&release, urgent).await {
             utils::update_unit_status(&format!(
                 "update staged: {}; reboot pending due to update strategy",
                 &release.version
             ));
+            let reboot = self.strategy.human_pending_reboot();
+            self.refresh_update_motd(state, Some((&release, reboot)));
             // Reset number of postponements to `MAX_FINALIZE_POSTPONEMENTS`
             // if strategy does not allow finalization.
-            state.update_staged(release);
+            state.machine_state.update_staged(release);
             return;
         }
 
-        let usersessions_can_finalize = state.usersessions_can_finalize();
-        if !usersessions_can_finalize {
+        if let Some(postponement) = postponement.filter(|p| !urgent && p.until > chrono::Utc::now())
+        {
+            FINALIZATION_BLOCKED
+                .with_label_values(&[USER_POSTPONEMENT_LABEL])
+                .inc();
+            let until = postponement.until.format("%a %Y-%m-%d %H:%M:%S %Z");
+            utils::update_unit_status(&format!(
+                "update staged: {}; reboot postponed by user until {}",
+                release.version, until
+            ));
+            let reboot = format!("after {}, as postponed by a user", until);
+            self.refresh_update_motd(state, Some((&release, reboot)));
+            state.postponement = Some(super::UserPostponement {
+                strategy_approved: true,
+                ..postponement
+            });
+            return;
+        }
+
+        if urgent {
+            URGENT_FINALIZATIONS.inc();
+            state
+                .machine_state
+                .usersessions_notify_urgent(&self.message_templates);
+        } else if !state
+            .machine_state
+            .usersessions_can_finalize(&self.message_templates)
+        {
             FINALIZATION_BLOCKED
                 .with_label_values(&[ACTIVE_USERSESSIONS_LABEL])
                 .inc();
@@ -384,20 +861,137 @@ impl UpdateAgentInfo {
                 "update staged: {}; reboot delayed due to active user sessions",
                 release.version
             ));
+            let reboot =
+                "once all interactive users have logged out, or at the end of a grace period";
+            self.refresh_update_motd(state, Some((&release, reboot.to_string())));
             // Record postponement and postpone finalization.
-            state.record_postponement();
+            state.machine_state.record_postponement();
             return;
         }
 
+        // Record this finalization before rebooting, restoring the previous
+        // record if it fails.
+        self.record_last_finalization(Some(chrono::Utc::now()));
+        self.strategy.finalization_started();
+        self.notify_finalizing(&state.machine_state, &release).await;
         match self.finalize_deployment(release).await {
             Ok(release) => {
                 FINALIZATION_SUCCESS.inc();
-                let status_msg = format!("update finalized: {}", release.version);
+                let status_msg = if urgent {
+                    format!("urgent update finalized: {}", release.version)
+                } else {
+                    format!("update finalized: {}", release.version)
+                };
                 log::info!("{}", &status_msg);
-                state.update_finalized(release);
+                state.postponement = None;
+                state.requested_release = None;
+                state.machine_state.update_finalized(release);
                 utils::update_unit_status(&status_msg);
             }
-            Err(e) => log::error!("failed to finalize deployment: {}", e),
+            Err(e) => {
+                log::error!("failed to finalize deployment: {}", e);
+                self.record_last_finalization(last_finalization);
+                if self.strategy.finalization_failed().await {
+                    // The reboot slot has to be acquired again.
+                    if let Some(postponement) = state.postponement.as_mut() {
+                        postponement.strategy_approved = false;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Return the timestamp of the last finalization, if reboot rate limiting is enabled.
+    fn last_finalization(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        if self.min_interval_between_reboots.is_zero() {
+            return None;
+        }
+
+        super::read_last_finalization(Path::new(super::LAST_FINALIZATION_PATH)).unwrap_or_else(
+            |e| {
+                log::error!("failed to read last finalization timestamp: {:#}", e);
+                None
+            },
+        )
+    }
+
+    /// Persist the timestamp of the last finalization, if reboot rate limiting is enabled.
+    fn record_last_finalization(&self, datetime: Option<chrono::DateTime<chrono::Utc>>) {
+        if self.min_interval_between_reboots.is_zero() {
+            return;
+        }
+
+        let path = Path::new(super::LAST_FINALIZATION_PATH);
+        if let Err(e) = super::write_last_finalization(path, datetime) {
+            log::error!("failed to record last finalization timestamp: {:#}", e);
+        }
+    }
+
+    /// Refresh the MOTD and issue fragments about the pending update (a staged
+    /// release and when a reboot into it is expected), if it changed.
+    fn refresh_update_motd(
+        &self,
+        state: &mut UpdateAgentState,
+        pending: Option<(&Release, String)>,
+    ) {
+        let pending = pending.map(|(release, reboot)| (release.version.clone(), reboot));
+        if state.update_motd == pending {
+            return;
+        }
+
+        let outcome = match &pending {
+            Some((version, reboot)) => {
+                run_motd_helper(&["set", "--version", version, "--reboot", reboot])
+            }
+            None => run_motd_helper(&["unset"]),
+        };
+        match outcome {
+            Ok(_) => {
+                log::debug!("MOTD updated with pending update state");
+                state.update_motd = pending;
+            }
+            Err(e) => log::error!("{:#}", e),
+        }
+    }
+
+    /// Send a webhook event for a state transition, if relevant and enabled.
+    fn notify_transition(&self, prev: &UpdateAgentMachineState, cur: &UpdateAgentMachineState) {
+        let webhook = match &self.webhook {
+            Some(addr) => addr,
+            None => return,
+        };
+        if let Some((kind, release, error_kind)) = cur.lifecycle_event(prev) {
+            let event = WebhookEvent::new(kind, &self.identity, cur.label(), release, error_kind);
+            webhook.do_send(SendEvent(event));
+        }
+    }
+
+    /// Send a webhook event for an imminent finalization, if enabled.
+    ///
+    /// As the node reboots right after finalizing, the event is delivered
+    /// synchronously, waiting for at most `FINALIZING_DELIVERY_TIMEOUT`.
+    async fn notify_finalizing(&self, state: &UpdateAgentMachineState, release: &Release) {
+        let webhook = match &self.webhook {
+            Some(addr) => addr,
+            None => return,
+        };
+        let event = WebhookEvent::new(
+            WebhookEventKind::UpdateFinalizing,
+            &self.identity,
+            state.label(),
+            Some(release),
+            None,
+        );
+        let delivery = webhook
+            .send(DeliverEvent(event))
+            .unwrap_or_else(|e| Err(e.into()));
+        match tokio::time::timeout(FINALIZING_DELIVERY_TIMEOUT, delivery).await {
+            Ok(Ok(_)) => log::trace!("delivered 'UpdateFinalizing' webhook event"),
+            Ok(Err(e)) => log::warn!("{:#}", e),
+            Err(_) => log::warn!(
+                "timed out delivering 'UpdateFinalizing' webhook event after {} seconds",
+                FINALIZING_DELIVERY_TIMEOUT.as_secs()
+            ),
         }
     }
 
@@ -577,12 +1171,30 @@ impl UpdateAgentInfo {
             utils::update_unit_status(&msg);
             log::info!("{}", msg);
             state.machine_state.update_staged(release);
+            state.staged_checked = Some(chrono::Utc::now());
         }
 
         Ok(())
     }
 }
 
+/// Run the privileged MOTD helper with the given arguments.
+#[context("failed to update pending update MOTD")]
+fn run_motd_helper(args: &[&str]) -> Result<()> {
+    let cmd = std::process::Command::new("pkexec")
+        .arg("/usr/libexec/zincati")
+        .arg("motd")
+        .args(args)
+        .output()?;
+    if !cmd.status.success() {
+        bail!(
+            "MOTD helper failed: {}",
+            String::from_utf8_lossy(&cmd.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -596,6 +1208,7 @@ mod tests {
             version: "v1".to_string(),
             payload: rpm_ostree::Payload::Checksum("ostree-checksum".to_string()),
             age_index: None,
+            urgent: false,
         };
 
         // Transition between states with different discriminants.
@@ -643,4 +1256,43 @@ mod tests {
             &cur_state
         ));
     }
+
+    #[test]
+    fn test_backoff_delay() {
+        let update = Release {
+            version: "v1".to_string(),
+            payload: rpm_ostree::Payload::Checksum("ostree-checksum".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        let cincinnati = Some(Duration::from_secs(300));
+        let strategy = Some(Duration::from_secs(600));
+
+        let cases = [
+            (UpdateAgentMachineState::StartState, None),
+            (UpdateAgentMachineState::Initialized, strategy),
+            (UpdateAgentMachineState::ReportedSteady, cincinnati),
+            (UpdateAgentMachineState::NoNewUpdate, cincinnati),
+            (
+                UpdateAgentMachineState::UpdateAvailable((update.clone(), 0)),
+                None,
+            ),
+            (
+                UpdateAgentMachineState::UpdateStaged((update.clone(), 0)),
+                strategy,
+            ),
+            (UpdateAgentMachineState::UpdateFinalized(update), None),
+            (UpdateAgentMachineState::EndState, None),
+        ];
+        for (state, expected) in cases {
+            let delay = UpdateAgent::backoff_delay(&state, cincinnati, strategy);
+            assert_eq!(delay, expected, "{:?}", state);
+        }
+
+        // Services which are available do not delay refreshes.
+        let state = UpdateAgentMachineState::NoNewUpdate;
+        assert_eq!(UpdateAgent::backoff_delay(&state, None, strategy), None);
+        let state = UpdateAgentMachineState::Initialized;
+        assert_eq!(UpdateAgent::backoff_delay(&state, cincinnati, None), None);
+    }
 }
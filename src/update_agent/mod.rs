@@ -1,20 +1,23 @@
 //! Update agent.
 
 mod actor;
-pub use actor::LastRefresh;
+pub use actor::{DeployVersion, LastRefresh, PostponeFinalization, StrategyWindow};
 
 use crate::cincinnati::Cincinnati;
 use crate::config::Settings;
 use crate::identity::Identity;
+use crate::notifications::webhook::{WebhookClient, WebhookEventKind};
+use crate::notifications::{desktop, MessagePhase, MessageTemplates, MessageVars};
 use crate::rpm_ostree::{Release, RpmOstreeClient};
 use crate::strategy::UpdateStrategy;
 use actix::Addr;
 use anyhow::{Context, Result};
 use chrono::prelude::*;
+use fn_error_context::context;
 use prometheus::{IntCounter, IntGauge};
 use serde::Deserialize;
 use std::cell::Cell;
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::Path;
 use std::rc::Rc;
@@ -42,6 +45,15 @@ const MAX_DEPLOY_ATTEMPTS: u8 = 12;
 /// an interactive session but you don't want to wait for the full timeout.
 const INTERACTIVE_SESSION_OVERRIDE: &str = "/run/zincati/override-interactive-check";
 
+/// System-wide locale configuration.
+const SYSTEM_LOCALE_PATH: &str = "/etc/locale.conf";
+
+/// Persisted timestamp of the last finalization initiated by Zincati.
+const LAST_FINALIZATION_PATH: &str = "/var/lib/zincati/last-finalization";
+
+/// Persisted payload and timestamp of the latest release first seen on remote.
+const ROLLOUT_FIRST_SEEN_PATH: &str = "/var/lib/zincati/rollout-first-seen";
+
 /// Maximum number of postponements to finalizing an update in the
 /// `UpdateStaged` state before forcing an update finalization and reboot.
 pub(crate) const MAX_FINALIZE_POSTPONEMENTS: u8 = 10;
@@ -73,14 +85,22 @@ lazy_static::lazy_static! {
 #[derive(Debug, Deserialize)]
 pub struct SessionJson {
     user: String,
+    uid: Option<u32>,
     tty: Option<String>,
+    seat: Option<String>,
+    class: Option<String>,
+    leader: Option<u32>,
 }
 
-/// A user login session with a tty.
+/// A user login session with a tty or a graphical desktop.
 pub struct InteractiveSession {
     user: String,
-    /// Device file of session's tty.
-    tty_dev: String,
+    /// Device file of session's tty, if any.
+    tty_dev: Option<String>,
+    /// UID of the user owning a running D-Bus bus, for graphical sessions without a tty.
+    desktop_uid: Option<u32>,
+    /// Locale of the session, if known.
+    locale: Option<String>,
 }
 
 /// State machine for the agent.
@@ -123,6 +143,68 @@ impl Default for UpdateAgentMachineState {
 }
 
 impl UpdateAgentMachineState {
+    /// Return the name of this state, without details.
+    fn label(&self) -> &'static str {
+        match self {
+            UpdateAgentMachineState::StartState => "StartState",
+            UpdateAgentMachineState::Initialized => "Initialized",
+            UpdateAgentMachineState::ReportedSteady => "ReportedSteady",
+            UpdateAgentMachineState::NoNewUpdate => "NoNewUpdate",
+            UpdateAgentMachineState::UpdateAvailable(_) => "UpdateAvailable",
+            UpdateAgentMachineState::UpdateStaged(_) => "UpdateStaged",
+            UpdateAgentMachineState::UpdateFinalized(_) => "UpdateFinalized",
+            UpdateAgentMachineState::EndState => "EndState",
+        }
+    }
+
+    /// Return the lifecycle event for a transition from `prev` to this state, if
+    /// any, along with the target release and an error kind for failure events.
+    fn lifecycle_event<'a>(
+        &'a self,
+        prev: &'a Self,
+    ) -> Option<(WebhookEventKind, Option<&'a Release>, Option<&'static str>)> {
+        use UpdateAgentMachineState as State;
+        let event = match (prev, self) {
+            (State::Initialized, State::ReportedSteady) => {
+                (WebhookEventKind::ReportedSteady, None, None)
+            }
+            (State::UpdateAvailable((prev, prev_fails)), State::UpdateAvailable((r, fails)))
+                if prev == r && fails > prev_fails =>
+            {
+                (
+                    WebhookEventKind::DeployFailed,
+                    Some(r),
+                    Some("deploy_failed"),
+                )
+            }
+            (State::UpdateAvailable(_), State::UpdateAvailable(_)) => return None,
+            (State::UpdateStaged(_), State::UpdateAvailable((r, _))) => {
+                (WebhookEventKind::UpdateSuperseded, Some(r), None)
+            }
+            (_, State::UpdateAvailable((r, _))) => {
+                (WebhookEventKind::UpdateAvailable, Some(r), None)
+            }
+            (State::UpdateAvailable((r, _)), State::NoNewUpdate) => (
+                WebhookEventKind::UpdateAbandoned,
+                Some(r),
+                Some("max_deploy_attempts"),
+            ),
+            (State::UpdateStaged((r, _)), State::NoNewUpdate) => (
+                WebhookEventKind::UpdateAbandoned,
+                Some(r),
+                Some("release_pulled"),
+            ),
+            (State::UpdateAvailable(_), State::UpdateStaged((r, _))) => {
+                (WebhookEventKind::UpdateStaged, Some(r), None)
+            }
+            (State::UpdateStaged(_), State::UpdateFinalized(r)) => {
+                (WebhookEventKind::UpdateFinalized, Some(r), None)
+            }
+            _ => return None,
+        };
+        Some(event)
+    }
+
     /// Progress the machine to a new state.
     fn transition_to(&mut self, state: Self) {
         use std::mem::discriminant;
@@ -227,6 +309,21 @@ impl UpdateAgentMachineState {
         self.transition_to(target);
     }
 
+    /// Transition from the UpdateStaged state to the UpdateAvailable state,
+    /// replacing the staged release with a newer one.
+    fn update_superseded(&mut self, update: Release) {
+        let target = UpdateAgentMachineState::UpdateAvailable((update, 0));
+        // Allowed starting states.
+        assert!(
+            matches!(self, UpdateAgentMachineState::UpdateStaged(_)),
+            "transition not allowed: {:?} to {:?}",
+            self,
+            target
+        );
+
+        self.transition_to(target);
+    }
+
     /// Transition to the UpdateStaged state, setting the number of postponements
     /// remaining to `MAX_FINALIZE_POSTPONEMENTS`.
     fn update_staged(&mut self, update: Release) {
@@ -237,7 +334,7 @@ impl UpdateAgentMachineState {
 
     /// Determine whether to allow finalization based off of current state.
     /// Returns a boolean indicating whether a finalization is permitted.
-    fn usersessions_can_finalize(&mut self) -> bool {
+    fn usersessions_can_finalize(&mut self, templates: &MessageTemplates) -> bool {
         let interactive_sessions = get_interactive_user_sessions();
 
         // If we failed to check for interactive sessions, assume nobody
@@ -249,7 +346,17 @@ impl UpdateAgentMachineState {
             vec![]
         });
 
-        self.handle_interactive_sessions(&sessions)
+        self.handle_interactive_sessions(&sessions, templates)
+    }
+
+    /// Warn active user sessions about an imminent reboot into an urgent update.
+    fn usersessions_notify_urgent(&self, templates: &MessageTemplates) {
+        let sessions = get_interactive_user_sessions().unwrap_or_else(|e| {
+            log::error!("failed to check for interactive sessions: {}", e);
+            vec![]
+        });
+
+        self.notify_urgent_finalization(&sessions, templates)
     }
 
     /// Helper for determining whether to allow a finalization by first checking whether
@@ -257,7 +364,11 @@ impl UpdateAgentMachineState {
     /// state's remaining postponements (possibly broadcasting warning messages to active sessions).
     ///
     /// Returns a boolean indicating whether finalization is permitted.
-    fn handle_interactive_sessions(&self, interactive_sessions: &[InteractiveSession]) -> bool {
+    fn handle_interactive_sessions(
+        &self,
+        interactive_sessions: &[InteractiveSession],
+        templates: &MessageTemplates,
+    ) -> bool {
         DETECTED_ACTIVE_USERS.set(interactive_sessions.len() as i64);
         log::trace!(
             "handling interactive sessions, total: {}",
@@ -300,18 +411,57 @@ impl UpdateAgentMachineState {
                 "interactive sessions detected, entering grace period (maximum {})",
                 format_seconds(max_reboot_delay_secs)
             );
-            let warning_msg = format_reboot_warning(max_reboot_delay_secs, &release.version);
-            broadcast(&warning_msg, interactive_sessions);
+            let vars = reboot_warning_vars(max_reboot_delay_secs, &release.version);
+            broadcast(
+                templates,
+                MessagePhase::FirstWarning,
+                &vars,
+                interactive_sessions,
+            );
         } else if postponements_remaining == 1 {
             log::warn!("last attempt to wait for the end of all interactive sessions");
-            let warning_msg =
-                format_reboot_warning(DEFAULT_POSTPONEMENT_TIME_SECS, &release.version);
-            broadcast(&warning_msg, interactive_sessions);
+            let vars = reboot_warning_vars(DEFAULT_POSTPONEMENT_TIME_SECS, &release.version);
+            broadcast(
+                templates,
+                MessagePhase::FinalWarning,
+                &vars,
+                interactive_sessions,
+            );
         }
 
         false
     }
 
+    /// Warn interactive sessions (if any) about an imminent reboot into an
+    /// urgent update, without waiting for them to end.
+    fn notify_urgent_finalization(
+        &self,
+        interactive_sessions: &[InteractiveSession],
+        templates: &MessageTemplates,
+    ) {
+        DETECTED_ACTIVE_USERS.set(interactive_sessions.len() as i64);
+        if interactive_sessions.is_empty() {
+            return;
+        }
+
+        let release = match self {
+            UpdateAgentMachineState::UpdateStaged((r, _)) => r,
+            _ => unreachable!(
+                "transition not allowed: notify_urgent_finalization on {:?}",
+                self,
+            ),
+        };
+
+        log::warn!("interactive sessions detected, not delaying reboot for urgent update");
+        let vars = reboot_warning_vars(0, &release.version);
+        broadcast(
+            templates,
+            MessagePhase::UrgentWarning,
+            &vars,
+            interactive_sessions,
+        );
+    }
+
     /// Record an additional postponement in machine's state (reduce the number of remaining
     /// postponements allowed by one) after a finalization postponement.
     fn record_postponement(&mut self) {
@@ -384,6 +534,8 @@ pub(crate) struct UpdateAgent {
     state_changed: Rc<Cell<DateTime<Utc>>>,
     /// Update agent's information.
     info: UpdateAgentInfo,
+    /// Pending renewal of the lease on a held reboot slot, if any.
+    lease_renewal: Option<actix::SpawnHandle>,
 }
 
 /// Non-atomic read-write state of the update agent.
@@ -393,6 +545,102 @@ pub(crate) struct UpdateAgentState {
     machine_state: UpdateAgentMachineState,
     /// List of releases to ignore.
     denylist: BTreeSet<Release>,
+    /// Payload of the latest release seen on remote, and when it was first seen.
+    rollout_first_seen: Option<(String, DateTime<Utc>)>,
+    /// Last time the staged release was checked against remote.
+    staged_checked: Option<DateTime<Utc>>,
+    /// Release explicitly requested as update target, if any.
+    requested_release: Option<Release>,
+    /// User-requested postponement of the pending finalization, if any.
+    postponement: Option<UserPostponement>,
+    /// Pending update (version and expected reboot) currently shown in the MOTD, if any.
+    update_motd: Option<(String, String)>,
+}
+
+/// User-requested postponement of a pending finalization.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct UserPostponement {
+    /// Staged release whose finalization is postponed.
+    release: Release,
+    /// When the first postponement for this release was requested.
+    started: DateTime<Utc>,
+    /// Time until which finalization is postponed.
+    until: DateTime<Utc>,
+    /// Whether the update strategy allowed finalization in the meantime.
+    strategy_approved: bool,
+}
+
+impl UpdateAgentState {
+    /// Postpone finalization of the staged release by `duration`, for at most
+    /// `max` since the first postponement of this release.
+    ///
+    /// Returns the time until which finalization is postponed.
+    fn postpone_finalization(
+        &mut self,
+        duration: Duration,
+        max: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>> {
+        let release = match &self.machine_state {
+            UpdateAgentMachineState::UpdateStaged((release, _)) => release.clone(),
+            _ => anyhow::bail!("no update is pending finalization"),
+        };
+        let (started, prev_until, strategy_approved) = match &self.postponement {
+            Some(p) if p.release == release => {
+                (p.started, p.until, p.strategy_approved && p.until > now)
+            }
+            _ => (now, now, false),
+        };
+
+        let limit = started + chrono::Duration::from_std(max)?;
+        let requested = now + chrono::Duration::from_std(duration)?;
+        let until = requested.min(limit).max(prev_until);
+        if until <= now {
+            anyhow::bail!("maximum postponement reached");
+        }
+
+        self.postponement = Some(UserPostponement {
+            release,
+            started,
+            until,
+            strategy_approved,
+        });
+        Ok(until)
+    }
+
+    /// Whether the update strategy already approved finalizing `release`
+    /// during a postponement which is still active.
+    ///
+    /// An approval is dropped once its postponement expires, so that the
+    /// strategy is consulted again before finalizing.
+    fn postponement_approved(&mut self, release: &Release, now: DateTime<Utc>) -> bool {
+        match self.postponement.as_mut() {
+            Some(p) if p.release == *release => {
+                if p.until <= now {
+                    p.strategy_approved = false;
+                }
+                p.strategy_approved
+            }
+            _ => false,
+        }
+    }
+
+    /// Record a release seen on remote, returning when it was first seen and
+    /// whether it is newly seen.
+    fn record_seen_release(
+        &mut self,
+        release: &Release,
+        now: DateTime<Utc>,
+    ) -> (DateTime<Utc>, bool) {
+        let payload = release.payload.to_string();
+        match &self.rollout_first_seen {
+            Some((seen, first_seen)) if *seen == payload => (*first_seen, false),
+            _ => {
+                self.rollout_first_seen = Some((payload, now));
+                (now, true)
+            }
+        }
+    }
 }
 
 /// Read-only information about the update agent.
@@ -408,15 +656,31 @@ pub(crate) struct UpdateAgentInfo {
     identity: Identity,
     /// Refresh interval in steady state.
     steady_interval: Duration,
+    /// Maximum local delay before staging a newly seen release.
+    rollout_spread: Duration,
+    /// Minimum interval between two finalizations.
+    min_interval_between_reboots: Duration,
+    /// Whether urgent releases may bypass periodic windows and user sessions grace period.
+    honor_urgent: bool,
+    /// Maximum time users may postpone a pending reboot.
+    max_postponement: Duration,
+    /// Templates for user-facing messages.
+    message_templates: MessageTemplates,
     /// rpm-ostree client actor.
     rpm_ostree_actor: Addr<RpmOstreeClient>,
     /// Update strategy.
     strategy: UpdateStrategy,
+    /// Webhook client actor, if enabled.
+    webhook: Option<Addr<WebhookClient>>,
 }
 
 impl UpdateAgent {
     /// Build an update agent with the given config.
-    pub(crate) fn with_config(cfg: Settings, rpm_ostree_addr: Addr<RpmOstreeClient>) -> Self {
+    pub(crate) fn with_config(
+        cfg: Settings,
+        rpm_ostree_addr: Addr<RpmOstreeClient>,
+        webhook_addr: Option<Addr<WebhookClient>>,
+    ) -> Self {
         let steady_secs = cfg.steady_interval_secs.get();
         Self {
             state: Rc::new(RwLock::new(UpdateAgentState::default())),
@@ -428,33 +692,175 @@ impl UpdateAgent {
                 identity: cfg.identity,
                 rpm_ostree_actor: rpm_ostree_addr,
                 steady_interval: Duration::from_secs(steady_secs),
+                rollout_spread: cfg.rollout_spread,
+                min_interval_between_reboots: cfg.min_interval_between_reboots,
+                honor_urgent: cfg.honor_urgent,
+                max_postponement: cfg.max_postponement,
+                message_templates: cfg.message_templates,
                 strategy: cfg.strategy,
+                webhook: webhook_addr,
             },
+            lease_renewal: None,
         }
     }
 }
 
-/// Attempt to broadcast msg to sessions.
-fn broadcast(msg: &str, sessions: &[InteractiveSession]) {
-    let mut sessions_broadcasted: usize = 0;
+/// Read the persisted timestamp of the last finalization, if any.
+fn read_last_finalization(path: &Path) -> Result<Option<DateTime<Utc>>> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| format!("failed to read '{}'", path.display()));
+        }
+    };
+    let timestamp: i64 = content
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid timestamp in '{}'", path.display()))?;
+    let datetime = Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .with_context(|| format!("out of range timestamp in '{}'", path.display()))?;
+    Ok(Some(datetime))
+}
+
+/// Persist the timestamp of the last finalization (or clear it).
+fn write_last_finalization(path: &Path, datetime: Option<DateTime<Utc>>) -> Result<()> {
+    let datetime = match datetime {
+        Some(dt) => dt,
+        None => {
+            return match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).with_context(|| format!("failed to remove '{}'", path.display()))
+                }
+                _ => Ok(()),
+            };
+        }
+    };
+
+    write_state_file(path, &format!("{}\n", datetime.timestamp()))
+}
+
+/// Read the persisted payload and first-seen timestamp of the latest release
+/// seen on remote, if any.
+fn read_rollout_first_seen(path: &Path) -> Result<Option<(String, DateTime<Utc>)>> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| format!("failed to read '{}'", path.display()));
+        }
+    };
+    let (timestamp, payload) = content
+        .trim()
+        .split_once(' ')
+        .with_context(|| format!("invalid content in '{}'", path.display()))?;
+    let timestamp: i64 = timestamp
+        .parse()
+        .with_context(|| format!("invalid timestamp in '{}'", path.display()))?;
+    let datetime = Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .with_context(|| format!("out of range timestamp in '{}'", path.display()))?;
+    Ok(Some((payload.to_string(), datetime)))
+}
+
+/// Persist the payload and first-seen timestamp of the latest release seen on remote.
+fn write_rollout_first_seen(path: &Path, payload: &str, datetime: DateTime<Utc>) -> Result<()> {
+    write_state_file(path, &format!("{} {}\n", datetime.timestamp(), payload))
+}
+
+/// Atomically write a state file.
+fn write_state_file(path: &Path, content: &str) -> Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)
+        .with_context(|| format!("failed to create temporary file in '{}'", dir.display()))?;
+    std::io::Write::write_all(&mut tmp, content.as_bytes())?;
+    tmp.persist(path)
+        .with_context(|| format!("failed to persist '{}'", path.display()))?;
+    Ok(())
+}
+
+/// Return the time before which finalization is not allowed, if the minimum
+/// interval since the last finalization has not passed yet.
+fn reboot_rate_limited_until(
+    last_finalization: Option<DateTime<Utc>>,
+    min_interval: Duration,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let not_before = last_finalization? + chrono::Duration::from_std(min_interval).ok()?;
+    if now < not_before {
+        Some(not_before)
+    } else {
+        None
+    }
+}
+
+/// Compute a stable local rollout delay in `[0, spread)`, derived from the node
+/// identity and the release payload.
+fn rollout_delay(node_uuid: &str, payload: &str, spread: Duration) -> Duration {
+    let spread_secs = spread.as_secs();
+    if spread_secs == 0 {
+        return Duration::from_secs(0);
+    }
 
-    let broadcast_msg = format!(
-        "\nBroadcast message from Zincati at {}:\n{}\n",
-        chrono::Utc::now().format("%a %Y-%m-%d %H:%M:%S %Z"),
-        msg
-    );
+    // FNV-1a, which (unlike `DefaultHasher`) is stable across toolchain versions.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in node_uuid.bytes().chain([0u8]).chain(payload.bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    Duration::from_secs(hash % spread_secs)
+}
+
+/// Attempt to broadcast a message to sessions, rendered in each session's language.
+fn broadcast(
+    templates: &MessageTemplates,
+    phase: MessagePhase,
+    vars: &MessageVars,
+    sessions: &[InteractiveSession],
+) {
+    let mut sessions_broadcasted: usize = 0;
 
     for session in sessions.iter() {
-        // Write message to tty device.
-        log::trace!(
-            "Attempting to broadcast a message to user {} at {}",
-            &session.user,
-            &session.tty_dev
-        );
-        if let Err(e) = fs::write(&session.tty_dev, &broadcast_msg) {
-            log::error!("failed to write to {}: {}", &session.tty_dev, e);
-            continue;
-        };
+        let locale = session.locale.as_deref();
+        let message = templates.render(phase, locale, vars);
+
+        match (&session.tty_dev, session.desktop_uid) {
+            (Some(tty_dev), _) => {
+                // Write message to tty device.
+                let broadcast_msg = format!(
+                    "\n{}\n{}\n",
+                    templates.render(MessagePhase::Header, locale, vars),
+                    message
+                );
+                log::trace!(
+                    "Attempting to broadcast a message to user {} at {}",
+                    &session.user,
+                    tty_dev
+                );
+                if let Err(e) = fs::write(tty_dev, &broadcast_msg) {
+                    log::error!("failed to write to {}: {}", tty_dev, e);
+                    continue;
+                };
+            }
+            (None, Some(uid)) => {
+                // Send a desktop notification on the user bus.
+                log::trace!(
+                    "Attempting to send a desktop notification to user {} (UID {})",
+                    &session.user,
+                    uid
+                );
+                let summary = templates.render(MessagePhase::NotificationSummary, locale, vars);
+                let critical = phase != MessagePhase::FirstWarning;
+                if let Err(e) = run_notify_helper(uid, &summary, &message, critical) {
+                    log::error!("{:#}", e);
+                    continue;
+                }
+            }
+            (None, None) => continue,
+        }
 
         sessions_broadcasted = sessions_broadcasted.saturating_add(1);
     }
@@ -487,44 +893,104 @@ fn get_interactive_user_sessions() -> Result<Vec<InteractiveSession>> {
     let sessions: Vec<SessionJson> = serde_json::from_slice(&cmdrun.stdout)
         .context("failed to deserialize output of `loginctl`")?;
 
-    // Filter out sessions that aren't interactive (don't have a tty), and map
-    // these sessions into an `InteractiveSession` struct.
+    // Filter out sessions that aren't interactive (don't have a tty nor a
+    // graphical seat), and map these sessions into an `InteractiveSession` struct.
     let interactive_sessions: Vec<InteractiveSession> = sessions
         .into_iter()
-        .filter_map(|session| match session.tty {
-            Some(mut tty) => {
-                tty.insert_str(0, "/dev/");
-                Some(InteractiveSession {
-                    user: session.user,
-                    tty_dev: tty,
-                })
-            }
-            _ => {
+        .filter_map(|session| {
+            let tty_dev = session
+                .tty
+                .filter(|tty| !tty.is_empty())
+                .map(|tty| format!("/dev/{}", tty));
+            let desktop_uid = match (&tty_dev, &session.seat, session.uid) {
+                (None, Some(seat), Some(uid))
+                    if !seat.is_empty() && session.class.as_deref() != Some("greeter") =>
+                {
+                    Some(uid).filter(|uid| Path::new(&desktop::user_bus_path(*uid)).exists())
+                }
+                _ => None,
+            };
+            if tty_dev.is_none() && desktop_uid.is_none() {
                 log::debug!(
-                    "found user {} with no tty, user considered non-interactive",
+                    "found user {} with no tty nor user bus, user considered non-interactive",
                     session.user
                 );
-                None
+                return None;
             }
+            Some(InteractiveSession {
+                user: session.user,
+                tty_dev,
+                desktop_uid,
+                locale: session_locale(session.leader),
+            })
         })
         .collect();
 
     Ok(interactive_sessions)
 }
 
-/// Returns a warning string about the time until reboot and the release
-/// that is staged.
-fn format_reboot_warning(seconds: u64, release_ver: &str) -> String {
-    let time_till_reboot = format_seconds(seconds);
-
-    format!(
-        "New update {} is available and has been deployed.\n\
-        If permitted by the update strategy, Zincati will reboot into this update when\n\
-        all interactive users have logged out, or in {}, whichever comes\n\
-        earlier. Please log out of all active sessions in order to let the auto-update\n\
-        process continue.",
-        release_ver, time_till_reboot
-    )
+/// Send a desktop notification to a user, through the privileged notification helper.
+#[context("failed to send desktop notification to UID {}", uid)]
+fn run_notify_helper(uid: u32, summary: &str, body: &str, critical: bool) -> Result<()> {
+    let mut cmd = std::process::Command::new("pkexec");
+    cmd.arg("/usr/libexec/zincati")
+        .arg("notify")
+        .arg("--uid")
+        .arg(uid.to_string())
+        .arg("--summary")
+        .arg(summary)
+        .arg("--body")
+        .arg(body);
+    if critical {
+        cmd.arg("--critical");
+    }
+    let output = cmd.output().context("failed to run `pkexec` binary")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "notification helper failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Return the locale of a session, looked up from the environment of its leader
+/// process if accessible, or else from the system configuration.
+fn session_locale(leader: Option<u32>) -> Option<String> {
+    let leader_env = leader.and_then(|pid| fs::read(format!("/proc/{}/environ", pid)).ok());
+    if let Some(locale) = leader_env.and_then(|env| locale_from_env(env.split(|b| *b == 0))) {
+        return Some(locale);
+    }
+
+    let system_env = fs::read(SYSTEM_LOCALE_PATH).ok()?;
+    locale_from_env(system_env.split(|b| *b == b'\n'))
+}
+
+/// Return the locale for messages from a list of `KEY=value` environment entries.
+fn locale_from_env<'a>(entries: impl Iterator<Item = &'a [u8]>) -> Option<String> {
+    let mut vars = HashMap::new();
+    for entry in entries {
+        let entry = String::from_utf8_lossy(entry);
+        if let Some((key, value)) = entry.trim().split_once('=') {
+            vars.insert(key.to_string(), value.trim_matches('"').to_string());
+        }
+    }
+
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|key| vars.get(*key))
+        .find(|value| !value.is_empty())
+        .cloned()
+}
+
+/// Returns template variables for a warning about the time until reboot and
+/// the release that is staged.
+fn reboot_warning_vars(seconds: u64, release_ver: &str) -> MessageVars {
+    MessageVars {
+        version: release_ver.to_string(),
+        deadline: Utc::now() + chrono::Duration::seconds(seconds as i64),
+        time_remaining: format_seconds(seconds),
+    }
 }
 
 /// Helper to return a human-friendly version of seconds.
@@ -605,6 +1071,7 @@ mod tests {
             version: "v1".to_string(),
             payload: Payload::Checksum("ostree-checksum".to_string()),
             age_index: None,
+            urgent: false,
         };
         machine.update_available(update.clone());
         assert_eq!(
@@ -641,6 +1108,7 @@ mod tests {
             version: "v1".to_string(),
             payload: Payload::Checksum("ostree-checksum".to_string()),
             age_index: None,
+            urgent: false,
         };
         let mut machine = UpdateAgentMachineState::NoNewUpdate;
 
@@ -668,6 +1136,7 @@ mod tests {
 
     #[test]
     fn test_fsm_postpone_finalize() {
+        let templates = MessageTemplates::default();
         let steady_interval = Duration::from_secs(DEFAULT_STEADY_INTERVAL_SECS);
         let default_interval = Duration::from_secs(DEFAULT_REFRESH_PERIOD_SECS);
         let postponement_interval = Duration::from_secs(DEFAULT_POSTPONEMENT_TIME_SECS);
@@ -675,6 +1144,7 @@ mod tests {
             version: "v1".to_string(),
             payload: Payload::Checksum("ostree-checksum".to_string()),
             age_index: None,
+            urgent: false,
         };
         let mut machine = UpdateAgentMachineState::UpdateAvailable((update.clone(), 0));
         let (delay, should_jitter) = machine.get_refresh_delay(steady_interval);
@@ -689,7 +1159,8 @@ mod tests {
 
         // Set up empty interactive sessions.
         let no_interactive_sessions: Vec<InteractiveSession> = vec![];
-        let can_finalize = machine.handle_interactive_sessions(&no_interactive_sessions);
+        let can_finalize =
+            machine.handle_interactive_sessions(&no_interactive_sessions, &templates);
         assert!(can_finalize);
         assert_eq!(
             machine,
@@ -705,13 +1176,16 @@ mod tests {
         let fake_tty = format!("{}/tty1", fake_tty_path_str);
         let fake_session = InteractiveSession {
             user: String::from("fakeuser"),
-            tty_dev: String::from(&fake_tty),
+            tty_dev: Some(String::from(&fake_tty)),
+            desktop_uid: None,
+            locale: None,
         };
         let interactive_sessions_present: Vec<InteractiveSession> = vec![fake_session];
 
         // Postpone MAX_FINALIZE_POSTPONEMENTS times (counting from 1).
         for finalization_attempt in 1..MAX_FINALIZE_POSTPONEMENTS + 1 {
-            let can_finalize = machine.handle_interactive_sessions(&interactive_sessions_present);
+            let can_finalize =
+                machine.handle_interactive_sessions(&interactive_sessions_present, &templates);
             assert!(!can_finalize);
             machine.record_postponement(); // as we cannot finalize.
             let postponement_remaining =
@@ -732,11 +1206,227 @@ mod tests {
         assert!(tty_contents.contains(&format_seconds(DEFAULT_POSTPONEMENT_TIME_SECS)));
 
         // Reached 0 remaining postponements.
-        let can_finalize = machine.handle_interactive_sessions(&interactive_sessions_present);
+        let can_finalize =
+            machine.handle_interactive_sessions(&interactive_sessions_present, &templates);
         assert!(can_finalize);
         assert_eq!(machine, UpdateAgentMachineState::UpdateStaged((update, 0)));
     }
 
+    #[test]
+    fn test_fsm_superseded_update() {
+        let staged = Release {
+            version: "v1".to_string(),
+            payload: Payload::Checksum("ostree-checksum".to_string()),
+            age_index: Some(1),
+            urgent: false,
+        };
+        let newer = Release {
+            version: "v2".to_string(),
+            payload: Payload::Checksum("newer-checksum".to_string()),
+            age_index: Some(2),
+            urgent: false,
+        };
+        let mut machine = UpdateAgentMachineState::UpdateStaged((staged, 3));
+        machine.update_superseded(newer.clone());
+        assert_eq!(
+            machine,
+            UpdateAgentMachineState::UpdateAvailable((newer.clone(), 0))
+        );
+
+        machine.update_staged(newer.clone());
+        machine.update_abandoned();
+        assert_eq!(machine, UpdateAgentMachineState::NoNewUpdate);
+    }
+
+    #[test]
+    fn test_postpone_finalization() {
+        let release = Release {
+            version: "v1".to_string(),
+            payload: Payload::Checksum("ostree-checksum".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        let max = Duration::from_secs(2 * 60 * 60);
+        let now = Utc.timestamp_opt(1_790_000_000, 0).unwrap();
+        let mut state = UpdateAgentState::default();
+
+        // Nothing to postpone.
+        state
+            .postpone_finalization(Duration::from_secs(60), max, now)
+            .unwrap_err();
+
+        state.machine_state = UpdateAgentMachineState::UpdateStaged((release.clone(), 10));
+        let until = state
+            .postpone_finalization(Duration::from_secs(30 * 60), max, now)
+            .unwrap();
+        assert_eq!(until, now + chrono::Duration::minutes(30));
+
+        // Shorter requests do not shorten an existing postponement.
+        let later = now + chrono::Duration::minutes(10);
+        let until = state
+            .postpone_finalization(Duration::from_secs(60), max, later)
+            .unwrap();
+        assert_eq!(until, now + chrono::Duration::minutes(30));
+
+        // Overall postponement is capped, starting from the first request.
+        state.postponement.as_mut().unwrap().strategy_approved = true;
+        let until = state
+            .postpone_finalization(Duration::from_secs(3 * 60 * 60), max, later)
+            .unwrap();
+        assert_eq!(until, now + chrono::Duration::hours(2));
+        assert!(state.postponement.as_ref().unwrap().strategy_approved);
+        assert!(state.postponement_approved(&release, later));
+        state
+            .postpone_finalization(Duration::from_secs(60), max, until)
+            .unwrap_err();
+
+        // Strategy approval does not outlive the postponement.
+        assert!(!state.postponement_approved(&release, until));
+        assert!(!state.postponement.as_ref().unwrap().strategy_approved);
+
+        // A different staged release starts afresh.
+        let newer = Release {
+            version: "v2".to_string(),
+            payload: Payload::Checksum("newer-checksum".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        state.machine_state = UpdateAgentMachineState::UpdateStaged((newer.clone(), 10));
+        let until = state
+            .postpone_finalization(Duration::from_secs(60 * 60), max, until)
+            .unwrap();
+        let postponement = state.postponement.unwrap();
+        assert_eq!(postponement.release, newer);
+        assert_eq!(postponement.until, until);
+        assert!(!postponement.strategy_approved);
+    }
+
+    #[test]
+    fn test_notify_urgent_finalization() {
+        let update = Release {
+            version: "v1".to_string(),
+            payload: Payload::Checksum("ostree-checksum".to_string()),
+            age_index: None,
+            urgent: true,
+        };
+        let machine = UpdateAgentMachineState::UpdateStaged((update.clone(), 0));
+
+        let fake_tty_path = tempfile::tempdir_in("/tmp").unwrap();
+        let fake_tty = fake_tty_path.path().join("tty1");
+        let fake_session = InteractiveSession {
+            user: String::from("fakeuser"),
+            tty_dev: Some(fake_tty.to_str().unwrap().to_string()),
+            desktop_uid: None,
+            locale: None,
+        };
+        machine.notify_urgent_finalization(&[fake_session], &MessageTemplates::default());
+
+        let tty_contents = fs::read_to_string(&fake_tty).unwrap();
+        assert!(tty_contents.contains("Broadcast message from Zincati"));
+        assert!(tty_contents.contains("urgent update"));
+        assert!(tty_contents.contains(&update.version));
+    }
+
+    #[test]
+    fn test_lifecycle_event() {
+        use UpdateAgentMachineState as State;
+        let update = Release {
+            version: "v1".to_string(),
+            payload: Payload::Checksum("ostree-checksum".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+
+        let kind = |prev: &State, cur: &State| cur.lifecycle_event(prev).map(|e| (e.0, e.2));
+        assert_eq!(
+            kind(&State::Initialized, &State::ReportedSteady),
+            Some((WebhookEventKind::ReportedSteady, None))
+        );
+        assert_eq!(kind(&State::ReportedSteady, &State::NoNewUpdate), None);
+        assert_eq!(
+            kind(
+                &State::NoNewUpdate,
+                &State::UpdateAvailable((update.clone(), 0))
+            ),
+            Some((WebhookEventKind::UpdateAvailable, None))
+        );
+        assert_eq!(
+            kind(
+                &State::UpdateAvailable((update.clone(), 0)),
+                &State::UpdateAvailable((update.clone(), 1))
+            ),
+            Some((WebhookEventKind::DeployFailed, Some("deploy_failed")))
+        );
+        assert_eq!(
+            kind(
+                &State::UpdateAvailable((update.clone(), 11)),
+                &State::NoNewUpdate
+            ),
+            Some((
+                WebhookEventKind::UpdateAbandoned,
+                Some("max_deploy_attempts")
+            ))
+        );
+        assert_eq!(
+            kind(
+                &State::UpdateAvailable((update.clone(), 1)),
+                &State::UpdateStaged((update.clone(), MAX_FINALIZE_POSTPONEMENTS))
+            ),
+            Some((WebhookEventKind::UpdateStaged, None))
+        );
+        assert_eq!(
+            kind(
+                &State::UpdateStaged((update.clone(), MAX_FINALIZE_POSTPONEMENTS)),
+                &State::UpdateStaged((update.clone(), 1))
+            ),
+            None
+        );
+        assert_eq!(
+            kind(
+                &State::UpdateStaged((update.clone(), 1)),
+                &State::UpdateAvailable((update.clone(), 0))
+            ),
+            Some((WebhookEventKind::UpdateSuperseded, None))
+        );
+        assert_eq!(
+            kind(
+                &State::UpdateStaged((update.clone(), 1)),
+                &State::NoNewUpdate
+            ),
+            Some((WebhookEventKind::UpdateAbandoned, Some("release_pulled")))
+        );
+
+        let staged = State::UpdateStaged((update.clone(), 1));
+        let finalized = State::UpdateFinalized(update.clone());
+        let (event, release, _) = finalized.lifecycle_event(&staged).unwrap();
+        assert_eq!(event, WebhookEventKind::UpdateFinalized);
+        assert_eq!(release, Some(&update));
+        assert_eq!(finalized.label(), "UpdateFinalized");
+    }
+
+    #[test]
+    fn test_locale_from_env() {
+        let environ = b"PATH=/usr/bin\0LANG=de_DE.UTF-8\0\0";
+        assert_eq!(
+            locale_from_env(environ.split(|b| *b == 0)),
+            Some("de_DE.UTF-8".to_string())
+        );
+
+        let environ = b"LANG=de_DE.UTF-8\0LC_MESSAGES=fr_FR.UTF-8\0LC_ALL=";
+        assert_eq!(
+            locale_from_env(environ.split(|b| *b == 0)),
+            Some("fr_FR.UTF-8".to_string())
+        );
+
+        let locale_conf = b"# system locale\nLANG=\"en_US.UTF-8\"\n";
+        assert_eq!(
+            locale_from_env(locale_conf.split(|b| *b == b'\n')),
+            Some("en_US.UTF-8".to_string())
+        );
+
+        assert_eq!(locale_from_env(b"PATH=/usr/bin".split(|b| *b == 0)), None);
+    }
+
     #[test]
     fn test_format_seconds() {
         assert_eq!("1 second", format_seconds(1));
@@ -747,4 +1437,111 @@ mod tests {
         assert_eq!("2 minutes", format_seconds(2 * 60));
         assert_eq!("42 minutes and 23 seconds", format_seconds(42 * 60 + 23));
     }
+
+    #[test]
+    fn test_rollout_delay() {
+        let spread = Duration::from_secs(48 * 60 * 60);
+        let node = "e0f3745b108f471cbd4883c6fbed8cdd";
+        let delay = rollout_delay(node, "sha256-a", spread);
+        assert!(delay < spread);
+        assert_eq!(delay, rollout_delay(node, "sha256-a", spread));
+
+        // Different payloads and nodes get (most likely) different delays.
+        assert_ne!(delay, rollout_delay(node, "sha256-b", spread));
+        let other_node = "27e3ac02af3946af995c9940e18b0cce";
+        assert_ne!(delay, rollout_delay(other_node, "sha256-a", spread));
+
+        let zero = Duration::from_secs(0);
+        assert_eq!(rollout_delay(node, "sha256-a", zero), zero);
+    }
+
+    #[test]
+    fn test_record_seen_release() {
+        let mut state = UpdateAgentState::default();
+        let first = Release {
+            version: "v1".to_string(),
+            payload: Payload::Checksum("ostree-checksum-1".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        let second = Release {
+            version: "v2".to_string(),
+            payload: Payload::Checksum("ostree-checksum-2".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        let t0 = chrono::Utc::now();
+        let t1 = t0 + chrono::Duration::hours(1);
+
+        assert_eq!(state.record_seen_release(&first, t0), (t0, true));
+        assert_eq!(state.record_seen_release(&first, t1), (t0, false));
+        assert_eq!(state.record_seen_release(&second, t1), (t1, true));
+    }
+
+    #[test]
+    fn test_last_finalization() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("last-finalization");
+        assert_eq!(read_last_finalization(&path).unwrap(), None);
+
+        let timestamp = Utc.timestamp_opt(1_790_000_000, 0).unwrap();
+        write_last_finalization(&path, Some(timestamp)).unwrap();
+        assert_eq!(read_last_finalization(&path).unwrap(), Some(timestamp));
+
+        write_last_finalization(&path, None).unwrap();
+        assert_eq!(read_last_finalization(&path).unwrap(), None);
+        write_last_finalization(&path, None).unwrap();
+
+        fs::write(&path, "not-a-timestamp").unwrap();
+        read_last_finalization(&path).unwrap_err();
+    }
+
+    #[test]
+    fn test_rollout_first_seen() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("rollout-first-seen");
+        assert_eq!(read_rollout_first_seen(&path).unwrap(), None);
+
+        let release = Release {
+            version: "v1".to_string(),
+            payload: Payload::Checksum("ostree-checksum-1".to_string()),
+            age_index: None,
+            urgent: false,
+        };
+        let t0 = Utc.timestamp_opt(1_790_000_000, 0).unwrap();
+        let payload = release.payload.to_string();
+        write_rollout_first_seen(&path, &payload, t0).unwrap();
+        let restored = read_rollout_first_seen(&path).unwrap();
+        assert_eq!(restored, Some((payload, t0)));
+
+        // A restored first-seen timestamp survives agent restarts.
+        let mut state = UpdateAgentState {
+            rollout_first_seen: restored,
+            ..Default::default()
+        };
+        let t1 = t0 + chrono::Duration::hours(1);
+        assert_eq!(state.record_seen_release(&release, t1), (t0, false));
+
+        fs::write(&path, "not-a-timestamp").unwrap();
+        read_rollout_first_seen(&path).unwrap_err();
+    }
+
+    #[test]
+    fn test_reboot_rate_limited_until() {
+        let interval = Duration::from_secs(24 * 60 * 60);
+        let now = Utc::now();
+        assert_eq!(reboot_rate_limited_until(None, interval, now), None);
+
+        let recent = now - chrono::Duration::hours(2);
+        assert_eq!(
+            reboot_rate_limited_until(Some(recent), interval, now),
+            Some(recent + chrono::Duration::hours(24))
+        );
+
+        let old = now - chrono::Duration::hours(25);
+        assert_eq!(reboot_rate_limited_until(Some(old), interval, now), None);
+
+        let zero = Duration::from_secs(0);
+        assert_eq!(reboot_rate_limited_until(Some(recent), zero, now), None);
+    }
 }
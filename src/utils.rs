@@ -1,6 +1,8 @@
 //! Miscellaneous utility functions.
 
+use anyhow::{bail, Context, Result};
 use libsystemd::daemon::{notify, NotifyState};
+use std::time::Duration;
 
 /// Helper function to update unit's status text.
 pub(crate) fn update_unit_status(status: &str) {
@@ -33,3 +35,73 @@ fn sd_notify(state: &[NotifyState]) {
         }
     }
 }
+
+/// Parse a human-friendly duration, as a sequence of integers with a unit suffix
+/// (`s`, `m`, `h`, or `d`).
+///
+/// ## Example
+///
+/// ```rust
+/// let spread = parse_duration("1d12h").unwrap();
+/// assert_eq!(spread.as_secs(), 36 * 60 * 60);
+/// ```
+pub(crate) fn parse_duration(input: &str) -> Result<Duration> {
+    let mut total_secs = 0u64;
+    let mut digits = String::new();
+    for c in input.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let multiplier: u64 = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => bail!("unrecognized duration unit '{}' in: {}", c, input),
+        };
+        if digits.is_empty() {
+            bail!("missing value before unit '{}' in: {}", c, input);
+        }
+        let value: u64 = digits
+            .parse()
+            .with_context(|| format!("invalid duration value in: {}", input))?;
+        total_secs = total_secs.saturating_add(value.saturating_mul(multiplier));
+        digits.clear();
+    }
+
+    if !digits.is_empty() {
+        bail!("missing unit after value '{}' in: {}", digits, input);
+    }
+    if input.trim().is_empty() {
+        bail!("empty duration");
+    }
+
+    Ok(Duration::from_secs(total_secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        let cases = vec![
+            ("48h", 48 * 60 * 60),
+            ("30m", 30 * 60),
+            ("1d12h", 36 * 60 * 60),
+            ("90s", 90),
+            ("0s", 0),
+        ];
+        for (input, secs) in cases {
+            assert_eq!(parse_duration(input).unwrap().as_secs(), secs, "{}", input);
+        }
+
+        parse_duration("").unwrap_err();
+        parse_duration("48").unwrap_err();
+        parse_duration("h").unwrap_err();
+        parse_duration("2w").unwrap_err();
+        parse_duration("-1h").unwrap_err();
+    }
+}
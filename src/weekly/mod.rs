@@ -9,13 +9,13 @@
 pub(crate) mod utils;
 
 use anyhow::{ensure, Result};
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Datelike, NaiveTime, TimeZone};
 use fn_error_context::context;
 use intervaltree::{Element, IntervalTree};
 use serde::{Serialize, Serializer};
 use std::cmp::Ordering;
 use std::fmt::Write;
-use std::ops::Range;
+use std::ops::{Range, RangeInclusive};
 use std::time::Duration;
 
 /// Whole week duration, in minutes.
@@ -48,14 +48,51 @@ impl WeeklyCalendar {
 
     /// Return whether datetime is contained in this weekly calendar.
     pub fn contains_datetime(&self, datetime: &DateTime<impl TimeZone>) -> bool {
-        let timepoint = utils::datetime_as_weekly_minute(datetime);
-        self.windows.query_point(timepoint).count() > 0
+        let timepoint = utils::datetime_at_minute(datetime);
+        self.occurrences(&timepoint, -1..=0)
+            .iter()
+            .any(|occurrence| occurrence.contains(&timepoint))
+    }
+
+    /// Return the absolute occurrences of all windows, for a range of weeks relative
+    /// to the week containing the given datetime.
+    ///
+    /// Window starts are local wall-clock times in the time zone of the datetime,
+    /// resolved according to `utils::resolve_local`. Window lengths are elapsed
+    /// time, thus a window crossing a DST transition always lasts its full length.
+    fn occurrences<T: TimeZone>(
+        &self,
+        datetime: &DateTime<T>,
+        weeks: RangeInclusive<i64>,
+    ) -> Vec<Range<DateTime<T>>> {
+        let tz = datetime.timezone();
+        let local = datetime.naive_local();
+        let days_from_monday = i64::from(local.weekday().num_days_from_monday());
+        let week_start =
+            local.date().and_time(NaiveTime::MIN) - chrono::Duration::days(days_from_monday);
+
+        let mut occurrences = Vec::new();
+        for week in weeks {
+            let base = week_start + chrono::Duration::weeks(week);
+            for elem in self.windows.iter() {
+                let local_start = base + chrono::Duration::minutes(i64::from(elem.range.start));
+                if let Some(start) = utils::resolve_local(&tz, &local_start) {
+                    // Range end accounts for a +1 on the last minute in the window.
+                    let length = elem.range.end.saturating_sub(elem.range.start);
+                    let end = start.clone() + chrono::Duration::minutes(i64::from(length));
+                    occurrences.push(start..end);
+                }
+            }
+        }
+        occurrences.sort_by(|a, b| a.start.cmp(&b.start));
+        occurrences
     }
 
     /// Return the minutes since the beginning of the week of the next window
     /// containing the given datetime.
     ///
     /// This returns `None` if no windows are reachable.
+    #[cfg(test)]
     pub fn next_window_minute_in_week(
         &self,
         datetime: &DateTime<impl TimeZone>,
@@ -94,7 +131,10 @@ impl WeeklyCalendar {
     /// Return the duration remaining till the next window containing the given datetime.
     ///
     /// This returns `None` if no windows are reachable.
-    pub fn remaining_to_datetime(&self, datetime: &DateTime<Utc>) -> Option<chrono::Duration> {
+    pub fn remaining_to_datetime(
+        &self,
+        datetime: &DateTime<impl TimeZone>,
+    ) -> Option<chrono::Duration> {
         if self.is_empty() {
             return None;
         }
@@ -104,29 +144,31 @@ impl WeeklyCalendar {
             return Some(chrono::Duration::zero());
         }
 
-        let timepoint = utils::datetime_as_weekly_minute(datetime);
-        // Next window is this week, just subtract remaining minutes.
-        if let Some(next) = self
-            .windows
-            .iter_sorted()
-            .find(|x| x.range.start >= timepoint)
-        {
-            let remaining_mins = next.range.start.saturating_sub(timepoint);
-            return Some(chrono::Duration::minutes(i64::from(remaining_mins)));
-        };
+        let timepoint = utils::datetime_at_minute(datetime);
+        self.occurrences(&timepoint, 0..=1)
+            .into_iter()
+            .find(|occurrence| occurrence.start > timepoint)
+            .map(|occurrence| occurrence.start.signed_duration_since(timepoint))
+    }
 
-        // Next window is not this week, wrap remaining minutes to the first
-        // window of the next week (calendar has been already verified non-empty).
-        let remaining_mins = {
-            let remaining_this_week: i64 = MAX_WEEKLY_MINS.saturating_sub(timepoint).into();
-            let first_window_next_week = self
-                .windows
-                .iter_sorted()
-                .next()
-                .expect("unexpected empty weekly calendar");
-            remaining_this_week.saturating_add(first_window_next_week.range.start.into())
-        };
-        Some(chrono::Duration::minutes(remaining_mins))
+    /// Return the duration remaining till the end of the window containing the given datetime.
+    ///
+    /// Overlapping and adjacent windows (including across week boundary) are coalesced.
+    /// This returns `None` if datetime is not contained in any window.
+    pub fn remaining_in_window(
+        &self,
+        datetime: &DateTime<impl TimeZone>,
+    ) -> Option<chrono::Duration> {
+        // Coverage is capped to a whole week.
+        let timepoint = utils::datetime_at_minute(datetime);
+        let limit = timepoint.clone() + chrono::Duration::weeks(1);
+        let occurrences = self.occurrences(&timepoint, -1..=2);
+        let coverage_end = utils::coalesced_end(&occurrences, &timepoint, &limit)?;
+
+        // Range end accounts for a +1 on the last minute in the window.
+        let remaining =
+            coverage_end.signed_duration_since(timepoint) - chrono::Duration::minutes(1);
+        Some(remaining)
     }
 
     /// Format remaining duration till the next window in human terms.
@@ -136,7 +178,7 @@ impl WeeklyCalendar {
         }
 
         let mut human_readable = "in".to_string();
-        let days = remaining.num_days() % 7;
+        let days = remaining.num_days();
         let earlier_output = if days > 0 {
             write!(&mut human_readable, " {}d", days)?;
             true
@@ -344,7 +386,7 @@ impl WeeklyWindow {
 
     /// Return whether datetime is contained in this window.
     #[cfg(test)]
-    pub fn contains_datetime(&self, datetime: &DateTime<Utc>) -> bool {
+    pub fn contains_datetime(&self, datetime: &DateTime<chrono::Utc>) -> bool {
         let instant = utils::datetime_as_weekly_minute(datetime);
         self.start_minutes() <= instant && instant <= self.end_minutes()
     }
@@ -374,7 +416,7 @@ impl PartialEq for WeeklyWindow {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Local;
+    use chrono::{Local, Utc};
 
     #[test]
     fn window_basic() {
@@ -541,13 +583,48 @@ mod tests {
         for (input, remaining) in cases {
             let datetime = DateTime::parse_from_rfc3339(input).unwrap();
             let output = calendar
-                .remaining_to_datetime(&datetime.into())
+                .remaining_to_datetime(&datetime)
                 .unwrap()
                 .num_minutes();
             assert_eq!(output, remaining, "{}", input);
         }
     }
 
+    #[test]
+    fn datetime_remaining_in_window() {
+        let l1 = utils::check_minutes(60).unwrap();
+        let mut windows = WeeklyWindow::parse_timespan(chrono::Weekday::Sun, 23, 30, l1).unwrap();
+        let l2 = utils::check_minutes(30).unwrap();
+        windows.extend(WeeklyWindow::parse_timespan(chrono::Weekday::Mon, 0, 30, l2).unwrap());
+        let l3 = utils::check_minutes(15).unwrap();
+        windows.extend(WeeklyWindow::parse_timespan(chrono::Weekday::Wed, 10, 0, l3).unwrap());
+        let calendar = WeeklyCalendar::new(windows);
+
+        let cases = vec![
+            // Coalesced across week boundary: Sun 23:30 to Mon 01:00.
+            ("2020-11-22T23:30:00+00:00", Some(90)),
+            ("2020-11-23T00:15:00+00:00", Some(45)),
+            ("2020-11-23T01:00:00+00:00", Some(0)),
+            ("2020-11-23T01:01:00+00:00", None),
+            ("2020-11-25T10:05:00+00:00", Some(10)),
+            ("2020-11-25T09:59:00+00:00", None),
+        ];
+        for (input, remaining) in cases {
+            let datetime = DateTime::parse_from_rfc3339(input).unwrap();
+            let output = calendar
+                .remaining_in_window(&datetime)
+                .map(|d| d.num_minutes());
+            assert_eq!(output, remaining, "{}", input);
+        }
+
+        let length = utils::check_minutes(MAX_WEEKLY_MINS).unwrap();
+        let windows = WeeklyWindow::parse_timespan(chrono::Weekday::Mon, 0, 0, length).unwrap();
+        let calendar = WeeklyCalendar::new(windows);
+        let datetime = DateTime::parse_from_rfc3339("2020-11-25T10:05:00+00:00").unwrap();
+        let output = calendar.remaining_in_window(&datetime).unwrap();
+        assert_eq!(output.num_minutes(), i64::from(MAX_WEEKLY_MINS) - 1);
+    }
+
     #[test]
     fn human_remaining() {
         use chrono::Duration;
@@ -566,6 +643,7 @@ mod tests {
             (2879, "in 1d 23h 59m"),
             (2880, "in 2d 0h 0m"),
             (4503, "in 3d 3h 3m"),
+            (10110, "in 7d 0h 30m"),
         ];
 
         for (mins, human) in cases {
@@ -575,6 +653,92 @@ mod tests {
         }
     }
 
+    #[test]
+    fn calendar_dst_transitions() {
+        use chrono::TimeZone;
+        use tzfile::Tz;
+
+        fn at<'a>(tz: &'a Tz, input: &str) -> DateTime<&'a Tz> {
+            let utc = DateTime::parse_from_rfc3339(input).unwrap().naive_utc();
+            tz.from_utc_datetime(&utc)
+        }
+
+        // Sunday 01:00 for 3h, crossing transitions in Europe.
+        let length = utils::check_minutes(180).unwrap();
+        let windows = WeeklyWindow::parse_timespan(chrono::Weekday::Sun, 1, 0, length).unwrap();
+        let calendar = WeeklyCalendar::new(windows);
+        let berlin = Tz::named("Europe/Berlin").unwrap();
+        let cases = vec![
+            // Spring forward, window ends at 05:00 CEST.
+            ("2026-03-28T23:59:00+00:00", false, None),
+            ("2026-03-29T00:00:00+00:00", true, Some(180)),
+            ("2026-03-29T02:30:00+00:00", true, Some(30)),
+            ("2026-03-29T03:01:00+00:00", false, None),
+            // Fall back, window ends at 03:00 CET.
+            ("2026-10-24T23:00:00+00:00", true, Some(180)),
+            ("2026-10-25T02:00:00+00:00", true, Some(0)),
+            ("2026-10-25T02:30:00+00:00", false, None),
+        ];
+        for (input, contained, remaining) in cases {
+            let datetime = at(&berlin, input);
+            assert_eq!(
+                calendar.contains_datetime(&datetime),
+                contained,
+                "{}",
+                input
+            );
+            let output = calendar
+                .remaining_in_window(&datetime)
+                .map(|d| d.num_minutes());
+            assert_eq!(output, remaining, "{}", input);
+        }
+
+        // Elapsed time till next window, across a transition.
+        let length = utils::check_minutes(60).unwrap();
+        let windows = WeeklyWindow::parse_timespan(chrono::Weekday::Sun, 10, 0, length).unwrap();
+        let calendar = WeeklyCalendar::new(windows);
+        let cases = vec![
+            ("Europe/Berlin", "2026-03-28T12:00:00+00:00", 20 * 60),
+            ("Europe/Berlin", "2026-10-24T12:00:00+00:00", 21 * 60),
+            ("America/New_York", "2026-03-07T15:00:00+00:00", 23 * 60),
+            ("America/New_York", "2026-10-31T15:00:00+00:00", 24 * 60),
+            ("Australia/Sydney", "2026-04-04T12:00:00+00:00", 12 * 60),
+            ("Australia/Lord_Howe", "2026-10-03T12:00:00+00:00", 11 * 60),
+        ];
+        for (zone, input, remaining) in cases {
+            let tz = Tz::named(zone).unwrap();
+            let datetime = at(&tz, input);
+            let output = calendar.remaining_to_datetime(&datetime).unwrap();
+            assert_eq!(output.num_minutes(), remaining, "{} {}", zone, input);
+        }
+
+        // Sunday 02:30 for 1h, starting at nonexistent or ambiguous local times.
+        let length = utils::check_minutes(60).unwrap();
+        let windows = WeeklyWindow::parse_timespan(chrono::Weekday::Sun, 2, 30, length).unwrap();
+        let calendar = WeeklyCalendar::new(windows);
+        let cases = vec![
+            ("Europe/Berlin", "2026-03-29T01:29:00+00:00", false),
+            ("Europe/Berlin", "2026-03-29T01:30:00+00:00", true),
+            ("Europe/Berlin", "2026-03-29T02:30:00+00:00", true),
+            ("Europe/Berlin", "2026-10-25T00:30:00+00:00", true),
+            ("Europe/Berlin", "2026-10-25T01:30:00+00:00", true),
+            ("Europe/Berlin", "2026-10-25T01:31:00+00:00", false),
+            ("America/New_York", "2026-03-08T07:29:00+00:00", false),
+            ("America/New_York", "2026-03-08T07:30:00+00:00", true),
+        ];
+        for (zone, input, contained) in cases {
+            let tz = Tz::named(zone).unwrap();
+            let datetime = at(&tz, input);
+            assert_eq!(
+                calendar.contains_datetime(&datetime),
+                contained,
+                "{} {}",
+                zone,
+                input
+            );
+        }
+    }
+
     #[test]
     fn test_next_window_minute_in_week() {
         use chrono::{NaiveDate, TimeZone};
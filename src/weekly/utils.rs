@@ -2,9 +2,10 @@
 
 use crate::weekly::{MinuteInWeek, MAX_WEEKLY_MINS, MAX_WEEKLY_SECS};
 use anyhow::{anyhow, bail, ensure, Result};
-use chrono::{DateTime, TimeZone, Weekday};
+use chrono::{DateTime, LocalResult, NaiveDateTime, Offset, TimeZone, Timelike, Weekday};
 use fn_error_context::context;
 use std::convert::TryInto;
+use std::ops::Range;
 use std::time::Duration;
 
 /// Convert `MinuteInWeek` to a week day and time.
@@ -41,6 +42,70 @@ pub(crate) fn datetime_as_weekly_minute(datetime: &DateTime<impl TimeZone>) -> M
     time_as_weekly_minute(weekday, hour, minute)
 }
 
+/// Truncate datetime to minutes granularity.
+pub(crate) fn datetime_at_minute<T: TimeZone>(datetime: &DateTime<T>) -> DateTime<T> {
+    datetime
+        .with_second(0)
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or_else(|| datetime.clone())
+}
+
+/// Resolve a local wall-clock time to an absolute datetime in the given time zone.
+///
+/// Around DST transitions, local times can be ambiguous or nonexistent. Similarly
+/// to iCalendar (RFC 5545), these are resolved as follows:
+///  * ambiguous times (repeated by a backward transition) map to their first occurrence.
+///  * nonexistent times (skipped by a forward transition) are interpreted with the
+///    offset in effect before the transition, thus shifting them forward by the gap.
+pub(crate) fn resolve_local<T: TimeZone>(tz: &T, local: &NaiveDateTime) -> Option<DateTime<T>> {
+    match tz.from_local_datetime(local) {
+        LocalResult::Single(dt) => Some(dt),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest),
+        LocalResult::None => {
+            let day_before = local.checked_sub_signed(chrono::Duration::days(1))?;
+            let offset = tz
+                .from_local_datetime(&day_before)
+                .earliest()?
+                .offset()
+                .fix();
+            let utc = local.checked_sub_offset(offset)?;
+            Some(tz.from_utc_datetime(&utc))
+        }
+    }
+}
+
+/// Return the end of the coverage given by a set of (possibly overlapping) intervals,
+/// starting from the intervals which contain the given datetime.
+///
+/// Overlapping and adjacent intervals are coalesced, and coverage is capped at `limit`.
+/// This returns `None` if datetime is not contained in any interval.
+pub(crate) fn coalesced_end<T: TimeZone>(
+    intervals: &[Range<DateTime<T>>],
+    datetime: &DateTime<T>,
+    limit: &DateTime<T>,
+) -> Option<DateTime<T>> {
+    let mut coverage_end = intervals
+        .iter()
+        .filter(|interval| interval.contains(datetime))
+        .map(|interval| interval.end.clone())
+        .max()?;
+
+    // Every extension strictly grows coverage, so this terminates.
+    while coverage_end < *limit {
+        let extension = intervals
+            .iter()
+            .filter(|interval| interval.start <= coverage_end && interval.end > coverage_end)
+            .map(|interval| interval.end.clone())
+            .max();
+        match extension {
+            Some(end) => coverage_end = end,
+            None => break,
+        }
+    }
+
+    Some(coverage_end.min(limit.clone()))
+}
+
 /// Convert a point in weekly-time to minutes since beginning of week.
 pub(crate) fn time_as_weekly_minute(day: chrono::Weekday, hour: u8, minute: u8) -> MinuteInWeek {
     let hour_minutes = u32::from(hour.min(23)).saturating_mul(60);
@@ -186,6 +251,70 @@ mod tests {
         time_from_string("23:60").unwrap_err();
     }
 
+    #[test]
+    fn test_resolve_local() {
+        use chrono::NaiveDateTime;
+        use tzfile::Tz;
+
+        let cases = vec![
+            // Nonexistent (spring forward), shifted by the gap.
+            (
+                "Europe/Berlin",
+                "2026-03-29T02:30",
+                "2026-03-29T01:30:00+00:00",
+            ),
+            (
+                "America/New_York",
+                "2026-03-08T02:30",
+                "2026-03-08T07:30:00+00:00",
+            ),
+            (
+                "Australia/Sydney",
+                "2026-10-04T02:00",
+                "2026-10-03T16:00:00+00:00",
+            ),
+            (
+                "Australia/Lord_Howe",
+                "2026-10-04T02:10",
+                "2026-10-03T15:40:00+00:00",
+            ),
+            // Ambiguous (fall back), first occurrence.
+            (
+                "Europe/Berlin",
+                "2026-10-25T02:30",
+                "2026-10-25T00:30:00+00:00",
+            ),
+            (
+                "America/New_York",
+                "2026-11-01T01:30",
+                "2026-11-01T05:30:00+00:00",
+            ),
+            (
+                "Australia/Sydney",
+                "2026-04-05T02:30",
+                "2026-04-04T15:30:00+00:00",
+            ),
+            (
+                "Australia/Lord_Howe",
+                "2026-04-05T01:45",
+                "2026-04-04T14:45:00+00:00",
+            ),
+            // Regular.
+            (
+                "Europe/Berlin",
+                "2026-07-01T12:00",
+                "2026-07-01T10:00:00+00:00",
+            ),
+        ];
+        for (zone, local, expected) in cases {
+            let tz = Tz::named(zone).unwrap();
+            let local = NaiveDateTime::parse_from_str(local, "%Y-%m-%dT%H:%M").unwrap();
+            let output = resolve_local(&&tz, &local).unwrap();
+            let expected = DateTime::parse_from_rfc3339(expected).unwrap();
+            assert_eq!(output, expected, "{} {}", zone, local);
+        }
+    }
+
     #[test]
     fn test_weekly_minute_as_weekday_time() {
         let t = (24 * 60) * 2 + 60 * 4 + 5;